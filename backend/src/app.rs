@@ -0,0 +1,302 @@
+//组装全局状态、限流器、后台任务和路由
+//main.rs和集成测试共用这一套，保证测试跑的就是线上的路由
+use std::sync::Arc;
+
+use salvo::affix_state;
+use salvo::prelude::*;
+use sqlx::{MySql, Pool};
+use tokio::time::{Duration as TokioDuration, sleep};
+
+use crate::config::{settings::Settings, webauthn::create_webauthn};
+use crate::handlers;
+use crate::handlers::email::require_verified_email;
+use crate::middleware::auth::{require_auth, require_role};
+use crate::middleware::rate_limit::RateLimiter;
+use crate::services::role_service::ADMIN_ROLE;
+use crate::services::{revocation_service, session_service, token_service};
+use crate::state::{AppState, AttemptCounter, PasskeyChallengeStore, RevocationStore};
+use crate::utils::auth::PasswordPolicy;
+use crate::utils::captcha_store::build_captcha_store;
+use crate::utils::jwt_keys::JwtKeys;
+use crate::utils::login_throttle::build_attempt_tracker;
+use crate::utils::mailer::build_mailer;
+use crate::utils::pow::ProofOfWork;
+use crate::utils::pow_nonce_store::build_pow_nonce_store;
+
+//限流：每个路由组一个限流器
+#[derive(Clone)]
+pub struct Limiters {
+    pub captcha: RateLimiter,
+    pub pow: RateLimiter,
+    pub auth: RateLimiter,
+    pub account: RateLimiter,
+    pub admin: RateLimiter,
+}
+
+impl Limiters {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            captcha: RateLimiter::new("captcha", settings.rate_limit.captcha),
+            pow: RateLimiter::new("pow", settings.rate_limit.pow),
+            auth: RateLimiter::new("auth", settings.rate_limit.auth),
+            account: RateLimiter::new("account", settings.rate_limit.account),
+            admin: RateLimiter::new("admin", settings.rate_limit.admin),
+        }
+    }
+
+    fn all(&self) -> [&RateLimiter; 5] {
+        [
+            &self.captcha,
+            &self.pow,
+            &self.auth,
+            &self.account,
+            &self.admin,
+        ]
+    }
+}
+
+//组装全局状态，注入到salvo的Depot里
+pub async fn build_state(settings: &Settings, db: Pool<MySql>) -> anyhow::Result<AppState> {
+    //创建验证码存储：默认存在进程内存，CAPTCHA_BACKEND=mysql/redis时多个实例共享
+    let captcha_store = build_captcha_store(&settings.captcha, &db).await?;
+    //已用的工作量证明随机串：后端跟验证码存储一样，上限单独配置(POW_CAPACITY)
+    let pow_nonces = build_pow_nonce_store(&settings.captcha, &settings.pow, &db).await?;
+    //创建token吊销列表缓存，启动时先把库里还有效的记录加载进来
+    let revocation_store = Arc::new(RevocationStore::default());
+    revocation_service::sync_revocation_store(&db, &revocation_store).await?;
+    //发邮件：默认只打印到控制台，MAIL_TRANSPORT=smtp时走SMTP
+    let mailer = build_mailer(&settings.mail)?;
+    //登录失败计数：默认存在进程内存，LOGIN_THROTTLE_BACKEND=mysql时存数据库
+    let login_attempts = build_attempt_tracker(&settings.login_throttle, &db);
+
+    Ok(AppState {
+        captcha_store,
+        captcha_presets: Arc::new(settings.captcha.presets.clone()),
+        pow: Arc::new(ProofOfWork::new(&settings.pow)),
+        pow_nonces,
+        revocation_store,
+        //两步验证失败次数计数(存在进程内存)
+        mfa_attempts: Arc::new(AttemptCounter::default()),
+        //通行密钥：WebAuthn实例 + 进行中的challenge(存在进程内存)
+        passkey_challenges: Arc::new(PasskeyChallengeStore::default()),
+        debug_captcha: settings.debug_captcha,
+        trusted_proxy_hops: settings.trusted_proxy_hops,
+        db,
+
+        //把jwt从settings注入到全局状态
+        jwt_keys: Arc::new(JwtKeys::from_settings(&settings.jwt_keys)?),
+        jwt_expire_seconds: settings.jwt_expire_seconds,
+        refresh_expire_seconds: settings.refresh_expire_seconds,
+
+        mailer,
+        app_base_url: settings.app_base_url.clone(),
+        password_reset_expire_seconds: settings.password_reset_expire_seconds,
+        email_verify_expire_seconds: settings.email_verify_expire_seconds,
+        email_resend_interval_seconds: settings.email_resend_interval_seconds,
+
+        totp_issuer: settings.totp_issuer.clone(),
+        mfa_challenge_expire_seconds: settings.mfa_challenge_expire_seconds,
+
+        webauthn: Arc::new(create_webauthn(&settings.webauthn)?),
+        passkey_challenge_expire_seconds: settings.webauthn.challenge_expire_seconds,
+
+        login_attempts,
+        login_throttle: settings.login_throttle.clone(),
+        password_policy: Arc::new(PasswordPolicy::from_settings(&settings.password_policy)?),
+        argon2: settings.argon2.clone(),
+    })
+}
+
+//后台定时任务
+pub fn spawn_background_tasks(state: &AppState, limiters: &Limiters) {
+    //每60s清理一次过期验证码、过期的PoW随机串、过期的通行密钥challenge、过期的失败计数和补满的限流桶
+    let (task_state, limiters) = (state.clone(), limiters.clone());
+    tokio::spawn(async move {
+        loop {
+            sleep(TokioDuration::from_secs(60)).await;
+            if let Err(e) = task_state.captcha_store.cleanup_expired().await {
+                eprintln!("清理过期验证码失败:{e}");
+            }
+            if let Err(e) = task_state.pow_nonces.cleanup_expired().await {
+                eprintln!("清理过期PoW随机串失败:{e}");
+            }
+            for limiter in limiters.all() {
+                limiter.cleanup_expired();
+            }
+            task_state.passkey_challenges.cleanup_expired();
+            task_state.mfa_attempts.cleanup_expired();
+            if let Err(e) = task_state.login_attempts.cleanup_expired().await {
+                eprintln!("清理登录失败计数失败:{e}");
+            }
+        }
+    });
+
+    //每60s清理一次过期的吊销记录，并从库里同步其他实例写入的记录；顺便删掉过期的会话和refresh token
+    let (db, revocation_store) = (state.db.clone(), state.revocation_store.clone());
+    tokio::spawn(async move {
+        loop {
+            sleep(TokioDuration::from_secs(60)).await;
+            if let Err(e) = revocation_service::sync_revocation_store(&db, &revocation_store).await
+            {
+                eprintln!("同步吊销记录失败:{e}");
+            }
+            if let Err(e) = session_service::delete_expired_sessions(&db).await {
+                eprintln!("清理过期会话失败:{e}");
+            }
+            if let Err(e) = token_service::delete_expired_refresh_tokens(&db).await {
+                eprintln!("清理过期refresh token失败:{e}");
+            }
+        }
+    });
+}
+
+//路由
+pub fn router(state: AppState, limiters: &Limiters) -> Router {
+    Router::new()
+        //健康检测
+        .push(Router::with_path("health").get(handlers::health::health))
+        //JWT公钥(JWKS)：其他服务用来校验这里签发的token
+        .push(Router::with_path(".well-known/jwks.json").get(handlers::jwks::jwks))
+        //验证码
+        .push(
+            Router::with_path("api/captcha")
+                .hoop(limiters.captcha.clone())
+                .get(handlers::captcha::get_captcha)
+                //验证码校验
+                .push(Router::with_path("verify").post(handlers::captcha::verify_captcha)),
+        )
+        //工作量证明：登录/注册时可以代替验证码
+        .push(
+            Router::with_path("api/challenge/pow")
+                .hoop(limiters.pow.clone())
+                .get(handlers::challenge::get_pow_challenge),
+        )
+        //接口路径对齐前端
+        .push(
+            Router::with_path("api/auth")
+                .hoop(limiters.auth.clone())
+                .push(Router::with_path("register").post(handlers::auth::register))
+                .push(Router::with_path("login").post(handlers::auth::login))
+                .push(Router::with_path("login/2fa").post(handlers::two_factor::login_2fa))
+                .push(Router::with_path("refresh").post(handlers::auth::refresh))
+                //找回密码
+                .push(
+                    Router::with_path("password/forgot").post(handlers::password::forgot_password),
+                )
+                .push(Router::with_path("password/reset").post(handlers::password::reset_password))
+                //通行密钥登录
+                .push(
+                    Router::with_path("passkey/login/start")
+                        .post(handlers::passkey::start_authentication),
+                )
+                .push(
+                    Router::with_path("passkey/login/finish")
+                        .post(handlers::passkey::finish_authentication),
+                )
+                //邮箱验证(链接里带着token，不需要登录)
+                .push(Router::with_path("email/verify").post(handlers::email::verify_email))
+                //下面的接口都需要登录：没登录/token无效统一返回401
+                .push(
+                    Router::new()
+                        .hoop(require_auth)
+                        .push(Router::with_path("logout").post(handlers::auth::logout))
+                        .push(Router::with_path("me").get(handlers::auth::me))
+                        .push(Router::with_path("locale").put(handlers::auth::update_locale))
+                        //登录会话
+                        .push(
+                            Router::with_path("sessions")
+                                .get(handlers::session::list_sessions)
+                                .push(
+                                    Router::with_path("sign-out-others")
+                                        .post(handlers::session::sign_out_others),
+                                )
+                                .push(
+                                    Router::with_path("{id}")
+                                        .delete(handlers::session::delete_session),
+                                ),
+                        )
+                        //两步验证
+                        .push(Router::with_path("2fa/setup").post(handlers::two_factor::setup_totp))
+                        .push(
+                            Router::with_path("2fa/confirm")
+                                .post(handlers::two_factor::confirm_totp),
+                        )
+                        .push(
+                            Router::with_path("2fa/disable")
+                                .post(handlers::two_factor::disable_totp),
+                        )
+                        //通行密钥注册(加在已登录的账号上)
+                        //要先验证过邮箱
+                        .push(
+                            Router::with_path("passkey/register")
+                                .hoop(require_verified_email)
+                                .push(
+                                    Router::with_path("start")
+                                        .post(handlers::passkey::start_registration),
+                                )
+                                .push(
+                                    Router::with_path("finish")
+                                        .post(handlers::passkey::finish_registration),
+                                ),
+                        )
+                        //重发验证邮件
+                        .push(
+                            Router::with_path("email/resend")
+                                .post(handlers::email::resend_verification),
+                        ),
+                ),
+        )
+        //账号设置：修改密码、修改邮箱
+        .push(
+            Router::with_path("api/account")
+                .hoop(limiters.account.clone())
+                //确认修改邮箱(链接里带着token，不需要登录)
+                .push(
+                    Router::with_path("email/confirm")
+                        .post(handlers::account::confirm_email_change),
+                )
+                .push(
+                    Router::new()
+                        .hoop(require_auth)
+                        .push(
+                            Router::with_path("password").post(handlers::account::change_password),
+                        )
+                        //修改邮箱：新邮箱要点自己的确认链接，当前邮箱不用先验证(填错了的邮箱验证不了，只能改掉)
+                        .push(Router::with_path("email").post(handlers::account::change_email)),
+                ),
+        )
+        //管理接口：需要登录+管理员角色
+        .push(
+            Router::with_path("api/admin")
+                .hoop(limiters.admin.clone())
+                .hoop(require_auth)
+                .hoop(require_role(ADMIN_ROLE))
+                .push(
+                    Router::with_path("users")
+                        .get(handlers::admin::list_users)
+                        .push(
+                            Router::with_path("{id}")
+                                .get(handlers::admin::get_user)
+                                .delete(handlers::admin::delete_user)
+                                .push(
+                                    Router::with_path("disable")
+                                        .post(handlers::admin::disable_user),
+                                )
+                                .push(
+                                    Router::with_path("enable").post(handlers::admin::enable_user),
+                                )
+                                .push(
+                                    Router::with_path("force-password-reset")
+                                        .post(handlers::admin::force_password_reset),
+                                )
+                                .push(
+                                    Router::with_path("roles/{role}")
+                                        .put(handlers::admin::grant_role)
+                                        .delete(handlers::admin::revoke_role),
+                                ),
+                        ),
+                ),
+        )
+        //注入全局状态：让全部的handler都能拿到Appstate
+        .hoop(affix_state::inject(state))
+}
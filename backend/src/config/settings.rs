@@ -1,5 +1,6 @@
 //读取环境变量
 use dotenvy::dotenv;
+use jsonwebtoken::Algorithm;
 use std::env;
 
 //全局配置：使用环境变量驱动
@@ -9,9 +10,41 @@ pub struct Settings {
     pub server_port: u16,
     pub database_url: String,
     pub debug_captcha: bool,
+    //前面有几层自己的反向代理：客户端IP从X-Forwarded-For右数第这么多个取，0表示不看这个header
+    pub trusted_proxy_hops: usize,
+    //启动时设为管理员的账号(用户名或邮箱)，只在还没有任何管理员时生效
+    pub admin_bootstrap_account: Option<String>,
     //JWT配置
-    pub jwt_secret: String,      //签名token的密钥
-    pub jwt_expire_seconds: i64, //token有效期
+    pub jwt_keys: JwtKeySettings,    //签名/校验token的密钥
+    pub jwt_expire_seconds: i64,     //access token有效期(短)
+    pub refresh_expire_seconds: i64, //refresh token有效期(长)
+    //前端地址：邮件里的链接要指向前端页面
+    pub app_base_url: String,
+    //找回密码链接有效期
+    pub password_reset_expire_seconds: i64,
+    //邮箱验证链接有效期
+    pub email_verify_expire_seconds: i64,
+    //重发验证邮件的最小间隔
+    pub email_resend_interval_seconds: i64,
+    //两步验证：验证器App里显示的发行方名称、登录时中间token的有效期
+    pub totp_issuer: String,
+    pub mfa_challenge_expire_seconds: i64,
+    //通行密钥(WebAuthn)配置
+    pub webauthn: WebauthnSettings,
+    //邮件配置
+    pub mail: MailSettings,
+    //登录失败锁定(防暴力破解)
+    pub login_throttle: LoginThrottleSettings,
+    //接口限流
+    pub rate_limit: RateLimitSettings,
+    //验证码存储
+    pub captcha: CaptchaSettings,
+    //工作量证明(验证码的替代方式)
+    pub pow: PowSettings,
+    //密码规则(注册/重置密码时校验)
+    pub password_policy: PasswordPolicySettings,
+    //密码哈希(argon2)参数
+    pub argon2: Argon2Settings,
 }
 
 impl Settings {
@@ -34,20 +67,703 @@ impl Settings {
             .trim()
             == "true";
 
-        let jwt_secret =
-            env::var("JWT_SECRET").map_err(|_| anyhow::anyhow!("缺少环境变量JWT_SECRET"))?;
+        //TRUSTED_PROXY_HOPS没设置时兼容旧的TRUST_PROXY=true(只有一层代理)
+        let trusted_proxy_hops = match env::var("TRUSTED_PROXY_HOPS") {
+            Ok(v) => v.trim().parse::<usize>()?,
+            Err(_) => usize::from(env_or("TRUST_PROXY", "false").to_lowercase().trim() == "true"),
+        };
+
+        let admin_bootstrap_account = env::var("ADMIN_BOOTSTRAP_ACCOUNT")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
 
         let jwt_expire_seconds = env::var("JWT_EXPIRE_SECONDS")
-            .unwrap_or_else(|_| "604800".to_string())
+            .unwrap_or_else(|_| "900".to_string())
+            .parse::<i64>()?;
+
+        let refresh_expire_seconds = env::var("REFRESH_EXPIRE_SECONDS")
+            .unwrap_or_else(|_| "2592000".to_string())
             .parse::<i64>()?;
 
+        let app_base_url = env_or("APP_BASE_URL", "http://localhost:5173")
+            .trim_end_matches('/')
+            .to_string();
+
+        let password_reset_expire_seconds =
+            env_or("PASSWORD_RESET_EXPIRE_SECONDS", "1800").parse::<i64>()?;
+
+        let email_verify_expire_seconds =
+            env_or("EMAIL_VERIFY_EXPIRE_SECONDS", "86400").parse::<i64>()?;
+        let email_resend_interval_seconds =
+            env_or("EMAIL_RESEND_INTERVAL_SECONDS", "60").parse::<i64>()?;
+
+        let totp_issuer = env_or("TOTP_ISSUER", "Personal Page");
+        let mfa_challenge_expire_seconds =
+            env_or("MFA_CHALLENGE_EXPIRE_SECONDS", "300").parse::<i64>()?;
+
+        let jwt_keys = JwtKeySettings::from_env(&app_base_url)?;
+
+        let webauthn = WebauthnSettings::from_env(&app_base_url, &totp_issuer)?;
+
+        let mail = MailSettings::from_env()?;
+
+        let login_throttle = LoginThrottleSettings::from_env()?;
+
+        let rate_limit = RateLimitSettings::from_env()?;
+
+        let captcha = CaptchaSettings::from_env()?;
+
+        let pow = PowSettings::from_env()?;
+
+        let password_policy = PasswordPolicySettings::from_env()?;
+
+        let argon2 = Argon2Settings::from_env()?;
+
         Ok(Self {
             server_host,
             server_port,
             database_url,
             debug_captcha,
-            jwt_secret,
+            trusted_proxy_hops,
+            admin_bootstrap_account,
+            jwt_keys,
             jwt_expire_seconds,
+            refresh_expire_seconds,
+            app_base_url,
+            password_reset_expire_seconds,
+            email_verify_expire_seconds,
+            email_resend_interval_seconds,
+            totp_issuer,
+            mfa_challenge_expire_seconds,
+            webauthn,
+            mail,
+            login_throttle,
+            rate_limit,
+            captcha,
+            pow,
+            password_policy,
+            argon2,
+        })
+    }
+}
+
+//JWT签名密钥
+//HS256:签名和校验都用JWT_SECRET
+//RS256/EdDSA:用JWT_PRIVATE_KEY_FILE里的私钥签名，token头里带JWT_KEY_ID
+//JWT_PUBLIC_KEYS里的公钥都可以用来校验，格式"kid=公钥PEM文件,kid=公钥PEM文件"
+//轮换密钥时新旧公钥同时配置，等旧私钥签发的token都过期了再把旧公钥删掉
+//签发的token里带上iss/aud，校验时必须一致：不同环境(测试/线上)的token不能混用
+#[derive(Clone, Debug)]
+pub struct JwtKeySettings {
+    pub algorithm: Algorithm,
+    //换成非对称算法之后JWT_SECRET还可以留着，用来校验切换之前签发的(没有kid的)token
+    pub secret: Option<String>,
+    pub key_id: Option<String>,
+    pub private_key_path: Option<String>,
+    pub public_keys: Vec<(String, String)>,
+    pub issuer: String,   //签发方，默认是前端地址
+    pub audience: String, //接收方，默认和签发方一样；其他服务校验时要认这个值
+    //校验exp/nbf时允许的时钟误差(秒)
+    pub leeway_seconds: u64,
+}
+
+impl JwtKeySettings {
+    fn from_env(app_base_url: &str) -> anyhow::Result<Self> {
+        let algorithm = match env_or("JWT_ALGORITHM", "HS256").trim() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            other => anyhow::bail!("JWT_ALGORITHM不支持:{other}(可选HS256/RS256/EdDSA)"),
+        };
+
+        let mut public_keys = Vec::new();
+        for item in env_or("JWT_PUBLIC_KEYS", "").split(',') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            let (kid, path) = item.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!("JWT_PUBLIC_KEYS格式不对:{item}(应该是 kid=文件路径)")
+            })?;
+            public_keys.push((kid.trim().to_string(), path.trim().to_string()));
+        }
+
+        let issuer = env_or("JWT_ISSUER", app_base_url).trim().to_string();
+        let settings = Self {
+            algorithm,
+            secret: env::var("JWT_SECRET").ok().filter(|v| !v.is_empty()),
+            key_id: env::var("JWT_KEY_ID")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            private_key_path: env::var("JWT_PRIVATE_KEY_FILE")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            public_keys,
+            audience: env_or("JWT_AUDIENCE", &issuer).trim().to_string(),
+            issuer,
+            leeway_seconds: env_or("JWT_LEEWAY_SECONDS", "60").parse::<u64>()?,
+        };
+        if settings.issuer.is_empty() || settings.audience.is_empty() {
+            anyhow::bail!("JWT_ISSUER和JWT_AUDIENCE不能为空");
+        }
+
+        if settings.algorithm == Algorithm::HS256 {
+            if settings.secret.is_none() {
+                anyhow::bail!("缺少环境变量JWT_SECRET");
+            }
+        } else {
+            let Some(key_id) = &settings.key_id else {
+                anyhow::bail!("JWT_ALGORITHM={algorithm:?}时必须设置JWT_KEY_ID");
+            };
+            if settings.private_key_path.is_none() {
+                anyhow::bail!("JWT_ALGORITHM={algorithm:?}时必须设置JWT_PRIVATE_KEY_FILE");
+            }
+            if !settings.public_keys.iter().any(|(kid, _)| kid == key_id) {
+                anyhow::bail!("JWT_PUBLIC_KEYS里没有JWT_KEY_ID={key_id}对应的公钥");
+            }
+        }
+        Ok(settings)
+    }
+}
+
+//邮件发送方式
+//Log:只打印到控制台(本地开发默认)
+//Smtp:真的通过SMTP发出去；测试时可以指向本地的SMTP捕获工具(比如MailHog的1025端口)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MailTransport {
+    Log,
+    Smtp,
+}
+
+//SMTP连接的加密方式
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SmtpTls {
+    None,     //明文(只用于本地SMTP捕获工具)
+    StartTls, //先明文连接再升级(一般是587端口)
+    Tls,      //直接TLS(一般是465端口)
+}
+
+#[derive(Clone, Debug)]
+pub struct MailSettings {
+    pub transport: MailTransport,
+    pub from: String, //发件人，比如 "Personal Page <no-reply@example.com>"
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls: SmtpTls,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+}
+
+impl MailSettings {
+    fn from_env() -> anyhow::Result<Self> {
+        let transport = match env_or("MAIL_TRANSPORT", "log").to_lowercase().as_str() {
+            "log" => MailTransport::Log,
+            "smtp" => MailTransport::Smtp,
+            other => anyhow::bail!("MAIL_TRANSPORT不支持:{other}(可选log/smtp)"),
+        };
+
+        let smtp_tls = match env_or("SMTP_TLS", "none").to_lowercase().as_str() {
+            "none" => SmtpTls::None,
+            "starttls" => SmtpTls::StartTls,
+            "tls" => SmtpTls::Tls,
+            other => anyhow::bail!("SMTP_TLS不支持:{other}(可选none/starttls/tls)"),
+        };
+
+        Ok(Self {
+            transport,
+            from: env_or("MAIL_FROM", "no-reply@localhost"),
+            smtp_host: env_or("SMTP_HOST", "127.0.0.1"),
+            smtp_port: env_or("SMTP_PORT", "1025").parse::<u16>()?,
+            smtp_tls,
+            smtp_username: env::var("SMTP_USERNAME").ok().filter(|v| !v.is_empty()),
+            smtp_password: env::var("SMTP_PASSWORD").ok().filter(|v| !v.is_empty()),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WebauthnSettings {
+    pub rp_id: String,     //通行密钥绑定的域名，比如example.com
+    pub rp_origin: String, //前端页面的来源，比如https://example.com
+    pub rp_name: String,   //认证器弹窗里显示的网站名称
+    pub challenge_expire_seconds: i64,
+}
+
+impl WebauthnSettings {
+    //默认值跟着前端地址和TOTP发行方名称走，本地开发不用额外配置
+    fn from_env(app_base_url: &str, default_name: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            rp_id: env_or("WEBAUTHN_RP_ID", "localhost"),
+            rp_origin: env_or("WEBAUTHN_RP_ORIGIN", app_base_url),
+            rp_name: env_or("WEBAUTHN_RP_NAME", default_name),
+            challenge_expire_seconds: env_or("WEBAUTHN_CHALLENGE_EXPIRE_SECONDS", "300")
+                .parse::<i64>()?,
+        })
+    }
+}
+
+//登录失败计数存在哪里
+//Memory:进程内存(单实例部署)
+//Mysql:数据库(多实例部署时计数共享)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ThrottleBackend {
+    Memory,
+    Mysql,
+}
+
+//登录失败锁定
+//同一个账号/同一个IP在window_seconds内连续失败达到阈值就锁定
+//锁定时长从base开始，之后每多失败一次翻倍，最长不超过max
+#[derive(Clone, Debug)]
+pub struct LoginThrottleSettings {
+    pub backend: ThrottleBackend,
+    pub account_max_failures: u32, //单个账号的失败阈值
+    pub ip_max_failures: u32,      //单个IP的失败阈值(一个IP可能在试很多账号，所以大一些)
+    pub base_lockout_seconds: i64,
+    pub max_lockout_seconds: i64,
+    pub window_seconds: i64, //超过这么久没有再失败，计数从头开始
+}
+
+impl LoginThrottleSettings {
+    fn from_env() -> anyhow::Result<Self> {
+        let backend = match env_or("LOGIN_THROTTLE_BACKEND", "memory")
+            .to_lowercase()
+            .as_str()
+        {
+            "memory" => ThrottleBackend::Memory,
+            "mysql" => ThrottleBackend::Mysql,
+            other => anyhow::bail!("LOGIN_THROTTLE_BACKEND不支持:{other}(可选memory/mysql)"),
+        };
+
+        Ok(Self {
+            backend,
+            account_max_failures: env_or("LOGIN_ACCOUNT_MAX_FAILURES", "5").parse::<u32>()?,
+            ip_max_failures: env_or("LOGIN_IP_MAX_FAILURES", "20").parse::<u32>()?,
+            base_lockout_seconds: env_or("LOGIN_LOCKOUT_BASE_SECONDS", "30").parse::<i64>()?,
+            max_lockout_seconds: env_or("LOGIN_LOCKOUT_MAX_SECONDS", "3600").parse::<i64>()?,
+            window_seconds: env_or("LOGIN_FAILURE_WINDOW_SECONDS", "900").parse::<i64>()?,
+        })
+    }
+}
+
+//限流配额：period_seconds秒内最多requests次(令牌桶，允许一次性用完)
+#[derive(Clone, Copy, Debug)]
+pub struct RateQuota {
+    pub requests: u32,
+    pub period_seconds: u64,
+}
+
+impl RateQuota {
+    //格式："次数/秒数"，比如"10/60"表示每分钟10次
+    fn parse(key: &str, value: &str) -> anyhow::Result<Self> {
+        let (requests, period_seconds) = value
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("{key}格式不对:{value}(应该是 次数/秒数)"))?;
+        let quota = Self {
+            requests: requests.trim().parse::<u32>()?,
+            period_seconds: period_seconds.trim().parse::<u64>()?,
+        };
+        if quota.requests == 0 || quota.period_seconds == 0 {
+            anyhow::bail!("{key}的次数和秒数都必须大于0:{value}");
+        }
+        Ok(quota)
+    }
+}
+
+//各路由组的限流配额
+#[derive(Clone, Debug)]
+pub struct RateLimitSettings {
+    //获取验证码：每次都会在内存里存一条，所以限得紧
+    pub captcha: RateQuota,
+    //获取工作量证明challenge
+    pub pow: RateQuota,
+    //api/auth下的接口
+    pub auth: RateQuota,
+    //api/account下的接口(修改密码、邮箱)
+    pub account: RateQuota,
+    //api/admin下的接口
+    pub admin: RateQuota,
+}
+
+impl RateLimitSettings {
+    fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            captcha: RateQuota::parse(
+                "RATE_LIMIT_CAPTCHA",
+                &env_or("RATE_LIMIT_CAPTCHA", "10/60"),
+            )?,
+            pow: RateQuota::parse("RATE_LIMIT_POW", &env_or("RATE_LIMIT_POW", "20/60"))?,
+            auth: RateQuota::parse("RATE_LIMIT_AUTH", &env_or("RATE_LIMIT_AUTH", "60/60"))?,
+            account: RateQuota::parse(
+                "RATE_LIMIT_ACCOUNT",
+                &env_or("RATE_LIMIT_ACCOUNT", "20/60"),
+            )?,
+            admin: RateQuota::parse("RATE_LIMIT_ADMIN", &env_or("RATE_LIMIT_ADMIN", "120/60"))?,
+        })
+    }
+}
+
+//验证码存在哪里
+//Memory:进程内存(单实例部署，重启后丢失)
+//Mysql:数据库(多实例部署时共享)
+//Redis:Redis或者兼容RESP协议的服务(多实例部署时共享，过期由Redis自己删除)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptchaBackend {
+    Memory,
+    Mysql,
+    Redis,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaptchaSettings {
+    pub backend: CaptchaBackend,
+    //格式：redis://[[用户名]:密码@]主机[:端口][/库编号]
+    pub redis_url: String,
+    //同一个验证码最多猜错几次，达到之后作废
+    pub max_attempts: u32,
+    //最多同时存多少个验证码，满了按过期时间从早到晚淘汰
+    pub capacity: usize,
+    //同一个IP最多同时持有几个验证码，超出时淘汰这个IP最早的
+    pub per_ip_limit: usize,
+    //验证码图片的生成参数
+    pub presets: CaptchaPresets,
+}
+
+impl CaptchaSettings {
+    fn from_env() -> anyhow::Result<Self> {
+        let backend = match env_or("CAPTCHA_BACKEND", "memory").to_lowercase().as_str() {
+            "memory" => CaptchaBackend::Memory,
+            "mysql" => CaptchaBackend::Mysql,
+            "redis" => CaptchaBackend::Redis,
+            other => anyhow::bail!("CAPTCHA_BACKEND不支持:{other}(可选memory/mysql/redis)"),
+        };
+
+        let settings = Self {
+            backend,
+            redis_url: env_or("CAPTCHA_REDIS_URL", "redis://127.0.0.1:6379"),
+            max_attempts: env_or("CAPTCHA_MAX_ATTEMPTS", "5").parse::<u32>()?,
+            capacity: env_or("CAPTCHA_CAPACITY", "100000").parse::<usize>()?,
+            per_ip_limit: env_or("CAPTCHA_PER_IP_LIMIT", "10").parse::<usize>()?,
+            presets: CaptchaPresets::from_env()?,
+        };
+        if settings.max_attempts == 0 || settings.capacity == 0 || settings.per_ip_limit == 0 {
+            anyhow::bail!(
+                "CAPTCHA_MAX_ATTEMPTS、CAPTCHA_CAPACITY、CAPTCHA_PER_IP_LIMIT都必须大于0"
+            );
+        }
+        Ok(settings)
+    }
+}
+
+//验证码难度：决定噪点、网格、扭曲和干扰圆点加多少
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptchaDifficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+//容易认错的字符，不管怎么配置都不会出现在验证码里(答案不区分大小写，o也要去掉)
+const AMBIGUOUS_CAPTCHA_CHARS: &[char] = &['0', 'O', 'o', '1', 'l', 'I'];
+
+//captcha crate在400x300的画布上从x=100开始往右写字，单个字符宽13-54像素、高不超过50像素
+//图片尺寸是以文字为中心裁出来的，裁剪区域超出画布会直接panic，所以启动时就要检查
+const CAPTCHA_MAX_LENGTH: u32 = 8;
+const CAPTCHA_MIN_SIZE: u32 = 40;
+const CAPTCHA_MAX_HEIGHT: u32 = 240;
+
+//一种验证码的生成参数
+#[derive(Clone, Debug)]
+pub struct CaptchaConfig {
+    //字符个数
+    pub length: u32,
+    //图片尺寸(像素)
+    pub width: u32,
+    pub height: u32,
+    pub difficulty: CaptchaDifficulty,
+    //从这些字符里随机挑，已经去掉了容易认错的
+    pub charset: Vec<char>,
+    //多少秒后过期
+    pub ttl_seconds: i64,
+}
+
+impl CaptchaConfig {
+    //读取{prefix}_LENGTH、{prefix}_WIDTH等，没设置的项用fallback里的值
+    fn from_env(prefix: &str, fallback: &CaptchaConfig) -> anyhow::Result<Self> {
+        let var = |name: &str| {
+            env::var(format!("{prefix}_{name}"))
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let difficulty = match var("DIFFICULTY") {
+            Some(v) => match v.to_lowercase().as_str() {
+                "easy" => CaptchaDifficulty::Easy,
+                "medium" => CaptchaDifficulty::Medium,
+                "hard" => CaptchaDifficulty::Hard,
+                other => anyhow::bail!("{prefix}_DIFFICULTY不支持:{other}(可选easy/medium/hard)"),
+            },
+            None => fallback.difficulty,
+        };
+        let charset = match var("CHARSET") {
+            Some(v) => captcha_charset(&format!("{prefix}_CHARSET"), v.chars().collect())?,
+            None => fallback.charset.clone(),
+        };
+
+        let config = Self {
+            length: var("LENGTH").map_or(Ok(fallback.length), |v| v.parse::<u32>())?,
+            width: var("WIDTH").map_or(Ok(fallback.width), |v| v.parse::<u32>())?,
+            height: var("HEIGHT").map_or(Ok(fallback.height), |v| v.parse::<u32>())?,
+            difficulty,
+            charset,
+            ttl_seconds: var("TTL_SECONDS")
+                .map_or(Ok(fallback.ttl_seconds), |v| v.parse::<i64>())?,
+        };
+
+        if config.length == 0 || config.length > CAPTCHA_MAX_LENGTH {
+            anyhow::bail!(
+                "{prefix}_LENGTH必须在1-{CAPTCHA_MAX_LENGTH}之间:{}",
+                config.length
+            );
+        }
+        //宽度：左边不能超出画布(按最窄的字符算)，右边也不能超出(按最宽的字符算)
+        let max_width = (200 + 13 * config.length).min(600 - 54 * config.length);
+        if config.width < CAPTCHA_MIN_SIZE || config.width > max_width {
+            anyhow::bail!(
+                "{prefix}_WIDTH在{}位验证码时必须在{CAPTCHA_MIN_SIZE}-{max_width}之间:{}",
+                config.length,
+                config.width
+            );
+        }
+        if config.height < CAPTCHA_MIN_SIZE || config.height > CAPTCHA_MAX_HEIGHT {
+            anyhow::bail!(
+                "{prefix}_HEIGHT必须在{CAPTCHA_MIN_SIZE}-{CAPTCHA_MAX_HEIGHT}之间:{}",
+                config.height
+            );
+        }
+        if config.ttl_seconds <= 0 {
+            anyhow::bail!("{prefix}_TTL_SECONDS必须大于0:{}", config.ttl_seconds);
+        }
+        Ok(config)
+    }
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        Self {
+            length: 5,
+            width: 180,
+            height: 60,
+            difficulty: CaptchaDifficulty::Easy,
+            charset: captcha::Captcha::new()
+                .supported_chars()
+                .into_iter()
+                .filter(|c| !AMBIGUOUS_CAPTCHA_CHARS.contains(c))
+                .collect(),
+            ttl_seconds: 120,
+        }
+    }
+}
+
+//检查配置的字符集：字体里没有的字符画不出来(会导致验证码少字)，直接报错；容易认错的去掉
+fn captcha_charset(key: &str, chars: Vec<char>) -> anyhow::Result<Vec<char>> {
+    let supported = captcha::Captcha::new().supported_chars();
+    let mut charset = Vec::new();
+    for c in chars {
+        if !supported.contains(&c) {
+            anyhow::bail!("{key}里的字符验证码字体不支持:{c}");
+        }
+        if !AMBIGUOUS_CAPTCHA_CHARS.contains(&c) && !charset.contains(&c) {
+            charset.push(c);
+        }
+    }
+    if charset.is_empty() {
+        anyhow::bail!("{key}去掉容易认错的字符之后为空");
+    }
+    Ok(charset)
+}
+
+//不同场景的验证码参数：登录、注册可以单独配置，其他地方(找回密码等)用通用配置
+//CAPTCHA_LENGTH、CAPTCHA_DIFFICULTY等是通用配置，CAPTCHA_LOGIN_*、CAPTCHA_REGISTER_*没设置的项沿用通用配置
+#[derive(Clone, Debug)]
+pub struct CaptchaPresets {
+    pub default: CaptchaConfig,
+    pub login: CaptchaConfig,
+    pub register: CaptchaConfig,
+}
+
+impl CaptchaPresets {
+    fn from_env() -> anyhow::Result<Self> {
+        let default = CaptchaConfig::from_env("CAPTCHA", &CaptchaConfig::default())?;
+        Ok(Self {
+            login: CaptchaConfig::from_env("CAPTCHA_LOGIN", &default)?,
+            register: CaptchaConfig::from_env("CAPTCHA_REGISTER", &default)?,
+            default,
         })
     }
+
+    //按场景名取参数，不认识的场景用通用配置
+    pub fn get(&self, scene: &str) -> &CaptchaConfig {
+        match scene {
+            "login" => &self.login,
+            "register" => &self.register,
+            _ => &self.default,
+        }
+    }
+}
+
+//工作量证明：客户端要找到一个solution，让sha256(challenge:solution)开头有difficulty个0比特
+//难度=基础难度+这个IP最近登录失败次数*每次失败增加的难度，不超过最大难度
+//每多1比特，客户端平均要多算一倍
+#[derive(Clone, Debug)]
+pub struct PowSettings {
+    //challenge的签名密钥；不配置时每次启动随机生成(重启后之前发的challenge作废)
+    //多实例部署时要配置成一样的，否则A实例发的challenge到B实例校验不过
+    pub secret: Option<String>,
+    pub base_difficulty: u32,
+    pub max_difficulty: u32,
+    pub difficulty_step: u32,
+    //challenge多少秒后过期
+    pub ttl_seconds: i64,
+    //最多同时记多少个已用的随机串(跟验证码分开存，不占验证码的名额)
+    //满了之后新的solution一律拒绝，不淘汰旧的：淘汰掉的challenge在过期前可以再用一次
+    pub capacity: usize,
+}
+
+impl PowSettings {
+    //难度上限：32比特平均要算40多亿次哈希，再高就不是正常客户端能算出来的了
+    const DIFFICULTY_LIMIT: u32 = 32;
+
+    fn from_env() -> anyhow::Result<Self> {
+        let settings = Self {
+            secret: env::var("POW_SECRET")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            base_difficulty: env_or("POW_BASE_DIFFICULTY", "16").parse::<u32>()?,
+            max_difficulty: env_or("POW_MAX_DIFFICULTY", "24").parse::<u32>()?,
+            difficulty_step: env_or("POW_DIFFICULTY_STEP", "1").parse::<u32>()?,
+            ttl_seconds: env_or("POW_TTL_SECONDS", "120").parse::<i64>()?,
+            capacity: env_or("POW_CAPACITY", "100000").parse::<usize>()?,
+        };
+        if settings.base_difficulty == 0
+            || settings.base_difficulty > settings.max_difficulty
+            || settings.max_difficulty > Self::DIFFICULTY_LIMIT
+        {
+            anyhow::bail!(
+                "POW_BASE_DIFFICULTY必须大于0且不超过POW_MAX_DIFFICULTY，POW_MAX_DIFFICULTY不能超过{}:{}/{}",
+                Self::DIFFICULTY_LIMIT,
+                settings.base_difficulty,
+                settings.max_difficulty
+            );
+        }
+        if settings.ttl_seconds <= 0 {
+            anyhow::bail!("POW_TTL_SECONDS必须大于0:{}", settings.ttl_seconds);
+        }
+        if settings.capacity == 0 {
+            anyhow::bail!("POW_CAPACITY必须大于0");
+        }
+        Ok(settings)
+    }
+}
+
+//密码规则：长度按字符数算(Unicode规范化之后)
+#[derive(Clone, Debug)]
+pub struct PasswordPolicySettings {
+    pub min_length: usize,
+    //上限防止有人提交超长密码拖慢argon2
+    pub max_length: usize,
+    //至少包含几种字符(小写字母/大写字母/数字/符号)，1-4
+    pub min_char_classes: usize,
+    //强度估算的最低分，0-4，0表示不检查
+    pub min_strength: u8,
+    //密码里不能包含用户名、邮箱
+    pub reject_personal_info: bool,
+    //额外的泄露密码列表文件(每行一个SHA-1前缀)，内置的列表总是会加载
+    pub breached_list_path: Option<String>,
+}
+
+impl PasswordPolicySettings {
+    fn from_env() -> anyhow::Result<Self> {
+        let policy = Self {
+            min_length: env_or("PASSWORD_MIN_LENGTH", "6").parse::<usize>()?,
+            max_length: env_or("PASSWORD_MAX_LENGTH", "128").parse::<usize>()?,
+            min_char_classes: env_or("PASSWORD_MIN_CHAR_CLASSES", "1").parse::<usize>()?,
+            min_strength: env_or("PASSWORD_MIN_STRENGTH", "2").parse::<u8>()?,
+            reject_personal_info: env_or("PASSWORD_REJECT_PERSONAL_INFO", "true")
+                .to_lowercase()
+                .trim()
+                == "true",
+            breached_list_path: env::var("PASSWORD_BREACHED_LIST")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+        };
+        if policy.min_length == 0 || policy.min_length > policy.max_length {
+            anyhow::bail!(
+                "PASSWORD_MIN_LENGTH必须大于0且不超过PASSWORD_MAX_LENGTH:{}/{}",
+                policy.min_length,
+                policy.max_length
+            );
+        }
+        if !(1..=4).contains(&policy.min_char_classes) {
+            anyhow::bail!(
+                "PASSWORD_MIN_CHAR_CLASSES必须在1-4之间:{}",
+                policy.min_char_classes
+            );
+        }
+        if policy.min_strength > 4 {
+            anyhow::bail!("PASSWORD_MIN_STRENGTH必须在0-4之间:{}", policy.min_strength);
+        }
+        Ok(policy)
+    }
+}
+
+//argon2id参数：默认值和argon2库的默认值一样(OWASP推荐的最低配置)
+//调高之后，老用户下次登录时会自动按新参数重新哈希
+#[derive(Clone, Debug)]
+pub struct Argon2Settings {
+    pub memory_kib: u32,  //内存开销(KiB)
+    pub time_cost: u32,   //迭代次数
+    pub parallelism: u32, //并行度
+    //pepper：额外的服务端密钥，不存数据库，只泄露数据库时没法离线爆破密码
+    pub pepper: Option<String>,
+    //换pepper时把旧的放到这里(PASSWORD_OLD_PEPPERS，逗号分隔)：按哈希里的keyid找到对应的pepper校验，
+    //用户下次登录成功时按新pepper重新哈希；所有老哈希都升级完之后再删掉
+    pub old_peppers: Vec<String>,
+}
+
+impl Argon2Settings {
+    fn from_env() -> anyhow::Result<Self> {
+        let settings = Self {
+            memory_kib: env_or("ARGON2_MEMORY_KIB", "19456").parse::<u32>()?,
+            time_cost: env_or("ARGON2_TIME_COST", "2").parse::<u32>()?,
+            parallelism: env_or("ARGON2_PARALLELISM", "1").parse::<u32>()?,
+            pepper: env::var("PASSWORD_PEPPER").ok().filter(|v| !v.is_empty()),
+            old_peppers: env_or("PASSWORD_OLD_PEPPERS", "")
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect(),
+        };
+        //参数不合法的话启动时就报错，不要等到第一次注册
+        argon2::Params::new(
+            settings.memory_kib,
+            settings.time_cost,
+            settings.parallelism,
+            None,
+        )
+        .map_err(|e| anyhow::anyhow!("ARGON2参数不合法:{e}"))?;
+        Ok(settings)
+    }
+}
+
+//读取环境变量，没有设置时使用默认值
+fn env_or(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default.to_string())
 }
@@ -0,0 +1,19 @@
+//创建WebAuthn实例(通行密钥登录用)
+use webauthn_rs::prelude::Url;
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+use super::settings::WebauthnSettings;
+
+//rp_id：通行密钥绑定的域名(不带协议和端口)，比如example.com
+//rp_origin：浏览器里前端页面的完整来源，比如https://example.com
+//认证器签名时会带上这两个值，不匹配的请求直接失败，所以通行密钥没法被钓鱼网站拿去用
+pub fn create_webauthn(settings: &WebauthnSettings) -> anyhow::Result<Webauthn> {
+    let rp_origin = Url::parse(&settings.rp_origin)
+        .map_err(|e| anyhow::anyhow!("WEBAUTHN_RP_ORIGIN格式错误:{e}"))?;
+
+    let webauthn = WebauthnBuilder::new(&settings.rp_id, &rp_origin)?
+        .rp_name(&settings.rp_name)
+        .build()?;
+
+    Ok(webauthn)
+}
@@ -0,0 +1,330 @@
+//统一错误类型
+//handler返回Result<Json<T>, AppError>，出错时用?直接返回
+//响应体统一是{"code":"xxx","message":"xxx"}：code是给前端判断用的稳定标识，message是给用户看的
+//message按语言从i18n的文案里查
+use std::collections::BTreeMap;
+
+use salvo::http::header::{CONTENT_LANGUAGE, HeaderValue, RETRY_AFTER};
+use salvo::prelude::*;
+use serde::Serialize;
+
+use crate::i18n::Locale;
+use crate::middleware::auth::CurrentUser;
+use crate::validation::FieldErrors;
+
+pub type AppResult<T> = Result<T, AppError>;
+
+//错误码：序列化成snake_case字符串，比如"captcha_invalid"
+//只能加不能改名，前端会按它做判断
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    //通用
+    InvalidJson,
+    InvalidParameter,
+    ValidationFailed,
+    Internal,
+    TooManyRequests,
+    //验证码
+    CaptchaInvalid,
+    PowInvalid,
+    //注册/登录
+    UsernameLength,
+    UsernameCharset,
+    EmailInvalid,
+    PasswordTooShort,
+    PasswordTooLong,
+    PasswordCharClasses,
+    PasswordTooWeak,
+    PasswordContainsPersonalInfo,
+    PasswordBreached,
+    PasswordRequired,
+    AccountRequired,
+    InvalidCredentials,
+    PasswordIncorrect,
+    UserExists,
+    TooManyFailures,
+    AccountDisabled,
+    //token/会话
+    TokenMissing,
+    TokenInvalid,
+    TokenRevoked,
+    SessionExpired,
+    SessionNotFound,
+    RefreshTokenInvalid,
+    RefreshTokenExpired,
+    RefreshTokenRevoked,
+    RefreshTokenReused,
+    //找回密码/邮箱验证
+    ResetTokenInvalid,
+    VerifyTokenInvalid,
+    EmailAlreadyVerified,
+    //require_verified_email用：注册通行密钥前要先验证邮箱
+    EmailNotVerified,
+    EmailSendTooFrequent,
+    EmailUnchanged,
+    EmailTaken,
+    EmailChangeTokenInvalid,
+    //两步验证
+    TotpAlreadyEnabled,
+    TotpNotEnabled,
+    TotpSetupRequired,
+    MfaCodeInvalid,
+    MfaChallengeExpired,
+    MfaTooManyAttempts,
+    //通行密钥
+    PasskeyNotAvailable,
+    PasskeyChallengeInvalid,
+    PasskeyVerifyFailed,
+    PasskeyAlreadyRegistered,
+    PasskeyRemoved,
+    //管理
+    PermissionDenied,
+    UserNotFound,
+    UserDeleted,
+    RoleNotFound,
+    RoleNotAssigned,
+    LastAdmin,
+    CannotModifySelf,
+}
+
+//按HTTP状态分类
+//- Validation 400:参数/验证码错误
+//- Unauthorized 401:未登录/token无效
+//- Forbidden 403:已登录但没有权限/账号被禁用
+//- NotFound 404
+//- Conflict 409:用户名或邮箱重复、状态冲突
+//- InvalidFields 422:请求参数校验不通过，按字段列出所有错误
+//- RateLimited 429:带Retry-After(秒)
+//- Internal 500:数据库等内部错误，具体原因只打日志，不返回给前端
+#[derive(Debug)]
+pub enum AppError {
+    Validation(ErrorCode),
+    Unauthorized(ErrorCode),
+    Forbidden(ErrorCode),
+    NotFound(ErrorCode),
+    Conflict(ErrorCode),
+    InvalidFields(FieldErrors),
+    RateLimited { code: ErrorCode, retry_after: i64 },
+    Internal(anyhow::Error),
+}
+
+impl AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::InvalidFields(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            AppError::Validation(code)
+            | AppError::Unauthorized(code)
+            | AppError::Forbidden(code)
+            | AppError::NotFound(code)
+            | AppError::Conflict(code)
+            | AppError::RateLimited { code, .. } => *code,
+            AppError::InvalidFields(_) => ErrorCode::ValidationFailed,
+            AppError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+//service层都返回anyhow::Result，用?时自动变成Internal
+impl<E> From<E> for AppError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(e: E) -> Self {
+        AppError::Internal(e.into())
+    }
+}
+
+//统一错误响应结构
+#[derive(Serialize)]
+struct ErrorResp {
+    code: ErrorCode,
+    message: &'static str,
+    //只有参数校验错误才有：字段名 -> 这个字段的所有错误
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<BTreeMap<&'static str, Vec<FieldErrorResp>>>,
+}
+
+#[derive(Serialize)]
+struct FieldErrorResp {
+    code: ErrorCode,
+    message: &'static str,
+}
+
+#[async_trait]
+impl Writer for AppError {
+    async fn write(self, req: &mut Request, depot: &mut Depot, res: &mut Response) {
+        match &self {
+            //内部错误把完整的错误链打到日志里
+            AppError::Internal(e) => {
+                eprintln!("[error] {} {}: {e:?}", req.method(), req.uri().path());
+            }
+            AppError::RateLimited { retry_after, .. } => {
+                res.headers_mut().insert(
+                    RETRY_AFTER,
+                    retry_after
+                        .to_string()
+                        .parse()
+                        .expect("数字一定是合法header"),
+                );
+            }
+            _ => {}
+        }
+
+        //已登录且设置过语言的用户按设置来，否则看Accept-Language
+        let locale = depot
+            .obtain::<CurrentUser>()
+            .ok()
+            .and_then(|current| current.user.locale.as_deref())
+            .and_then(Locale::from_tag)
+            .unwrap_or_else(|| Locale::from_request(req));
+
+        let fields = match &self {
+            AppError::InvalidFields(errors) => Some(
+                errors
+                    .iter()
+                    .map(|(field, codes)| {
+                        let errors = codes
+                            .iter()
+                            .map(|&code| FieldErrorResp {
+                                code,
+                                message: locale.error_message(code),
+                            })
+                            .collect();
+                        (field, errors)
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        let code = self.code();
+        res.status_code(self.status_code());
+        res.headers_mut()
+            .insert(CONTENT_LANGUAGE, HeaderValue::from_static(locale.tag()));
+        res.render(Json(ErrorResp {
+            code,
+            message: locale.error_message(code),
+            fields,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::test::{ResponseExt, TestClient};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    #[handler]
+    async fn conflict() -> AppResult<()> {
+        Err(AppError::Conflict(ErrorCode::UserExists))
+    }
+
+    #[handler]
+    async fn internal() -> AppResult<()> {
+        Err(anyhow::anyhow!("数据库连接断了").into())
+    }
+
+    #[handler]
+    async fn invalid_fields() -> AppResult<()> {
+        let mut errors = FieldErrors::default();
+        errors.add("username", ErrorCode::UsernameLength);
+        errors.add("username", ErrorCode::UsernameCharset);
+        errors.add("email", ErrorCode::EmailInvalid);
+        Err(AppError::InvalidFields(errors))
+    }
+
+    #[handler]
+    async fn rate_limited() -> AppResult<()> {
+        Err(AppError::RateLimited {
+            code: ErrorCode::TooManyFailures,
+            retry_after: 30,
+        })
+    }
+
+    //返回(状态码, 响应, body)
+    async fn call(handler: impl Handler, accept_language: &str) -> (StatusCode, Response, Value) {
+        let service = Service::new(Router::new().goal(handler));
+        let mut res = TestClient::get("http://127.0.0.1/")
+            .add_header("accept-language", accept_language, true)
+            .send(&service)
+            .await;
+        let body = res.take_json::<Value>().await.unwrap();
+        (res.status_code.unwrap(), res, body)
+    }
+
+    #[tokio::test]
+    async fn renders_code_and_localized_message() {
+        let (status, res, body) = call(conflict, "en-US,en;q=0.9").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            body,
+            json!({ "code": "user_exists", "message": "Username or email already exists" })
+        );
+        assert_eq!(res.headers().get(CONTENT_LANGUAGE).unwrap(), "en");
+
+        let (_, res, body) = call(conflict, "fr").await;
+        assert_eq!(body["message"], "用户名或邮箱已存在");
+        assert_eq!(res.headers().get(CONTENT_LANGUAGE).unwrap(), "zh-CN");
+    }
+
+    #[tokio::test]
+    async fn internal_error_does_not_leak_details() {
+        let (status, _, body) = call(internal, "en").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body,
+            json!({ "code": "internal", "message": "Internal server error" })
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_fields_lists_every_error_per_field() {
+        let (status, _, body) = call(invalid_fields, "en").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body,
+            json!({
+                "code": "validation_failed",
+                "message": "Some fields are invalid",
+                "fields": {
+                    "email": [
+                        { "code": "email_invalid", "message": "Invalid email address" },
+                    ],
+                    "username": [
+                        {
+                            "code": "username_length",
+                            "message": "Username must be 3-32 characters long",
+                        },
+                        {
+                            "code": "username_charset",
+                            "message": "Username may only contain letters, digits and _-. and must start with a letter or digit",
+                        },
+                    ],
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_sets_retry_after() {
+        let (status, res, body) = call(rate_limited, "en").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "30");
+        assert_eq!(body["code"], "too_many_failures");
+    }
+}
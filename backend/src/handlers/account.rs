@@ -0,0 +1,267 @@
+//账号设置：已登录用户修改密码、修改邮箱
+//两个操作都要先输入当前密码；成功后其他设备上的会话全部下线，并记一条审计日志
+use chrono::{Duration, Utc};
+use salvo::prelude::*;
+use serde::Deserialize;
+
+use super::auth::{
+    check_user_password, end_other_sessions, ensure_not_locked, is_duplicate_key,
+    record_login_failure,
+};
+use super::{parse_json, parse_valid};
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::middleware::auth::current_user;
+use crate::services::user_service::UserRow;
+use crate::services::{audit_service, email_change_service, user_service};
+use crate::state::AppState;
+use crate::utils::mailer::Mail;
+use crate::utils::{auth, client, login_throttle};
+use crate::validation::{self, FieldErrors, Validate};
+
+//修改密码请求：当前密码+新密码
+#[derive(Deserialize)]
+pub struct ChangePasswordReq {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+impl Validate for ChangePasswordReq {
+    fn validate(&mut self, state: &AppState, errors: &mut FieldErrors) {
+        //当前密码保持原样输入，校验时再规范化(见check_user_password)
+        self.new_password = validation::normalize_password(&self.new_password);
+
+        validation::check_required(
+            errors,
+            "current_password",
+            &self.current_password,
+            ErrorCode::PasswordRequired,
+        );
+        //包含用户名/邮箱的检查在handler里做(要用到当前用户)
+        validation::check_password(
+            errors,
+            "new_password",
+            &self.new_password,
+            &state.password_policy,
+            &[],
+        );
+    }
+}
+
+//修改邮箱请求：当前密码+新邮箱
+#[derive(Deserialize)]
+pub struct ChangeEmailReq {
+    pub current_password: String,
+    pub new_email: String,
+}
+
+impl Validate for ChangeEmailReq {
+    fn validate(&mut self, _state: &AppState, errors: &mut FieldErrors) {
+        self.new_email = validation::normalize(&self.new_email);
+
+        validation::check_required(
+            errors,
+            "current_password",
+            &self.current_password,
+            ErrorCode::PasswordRequired,
+        );
+        validation::check_email(errors, "new_email", &self.new_email);
+    }
+}
+
+//确认修改邮箱请求：发到新邮箱的链接里的token
+#[derive(Deserialize)]
+pub struct ConfirmEmailChangeReq {
+    pub token: String,
+}
+
+//修改密码:POST /api/account/password
+//流程：
+// 1.parse JSON，校验新密码(不能包含用户名/邮箱)
+// 2.校验当前密码，错误次数和登录共用一套计数，错太多次一样会被锁
+// 3.hash新密码并更新
+// 4.除了当前会话，其他会话全部下线
+// 5.记审计日志
+#[handler]
+pub async fn change_password(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let current = current_user(depot);
+    let user = &current.user;
+
+    let body: ChangePasswordReq = parse_valid(req, state).await?;
+    validation::ensure_new_password(state, "new_password", &body.new_password, user)?;
+
+    verify_current_password(state, req, user, &body.current_password).await?;
+
+    let password_hash = auth::hash_password(&state.argon2, &body.new_password)?;
+    user_service::update_password_hash(&state.db, user.id, &password_hash).await?;
+
+    end_other_sessions(state, user.id, current.session_id()).await?;
+
+    audit_service::record(
+        &state.db,
+        user.id,
+        audit_service::PASSWORD_CHANGED,
+        "",
+        &client::client_ip(req, state.trusted_proxy_hops),
+        &client::user_agent(req),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//修改邮箱:POST /api/account/email
+//流程：
+// 1.parse JSON
+// 2.新邮箱和现在的一样 -> 400
+// 3.校验当前密码
+// 4.新邮箱已经被别人用了 -> 409
+// 5.生成一次性令牌(库里只存摘要)，把确认链接发到新邮箱
+// 6.记审计日志
+//这一步不改users.email，要等新邮箱的主人点了链接才生效
+#[handler]
+pub async fn change_email(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let current = current_user(depot);
+    let user = &current.user;
+
+    let body: ChangeEmailReq = parse_valid(req, state).await?;
+
+    if body.new_email.eq_ignore_ascii_case(&user.email) {
+        return Err(AppError::Validation(ErrorCode::EmailUnchanged));
+    }
+
+    verify_current_password(state, req, user, &body.current_password).await?;
+
+    //提前查一次给出明确提示；确认时还会再靠唯一索引兜底
+    if user_service::find_user_by_account(&state.db, &body.new_email)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict(ErrorCode::EmailTaken));
+    }
+
+    let token = auth::generate_opaque_token();
+    let expires_at = Utc::now() + Duration::seconds(state.email_verify_expire_seconds);
+    email_change_service::create_email_change(
+        &state.db,
+        user.id,
+        &body.new_email,
+        current.session_id(),
+        &auth::hash_opaque_token(&token),
+        expires_at,
+    )
+    .await?;
+
+    let mail = Mail {
+        to: body.new_email.clone(),
+        subject: "确认修改邮箱".to_string(),
+        body: format!(
+            "{}，你好：\n\n你正在把账号邮箱改为这个地址，请在{}小时内打开下面的链接完成修改：\n{}/confirm-email-change?token={}\n\n如果不是你本人操作，请忽略这封邮件。",
+            user.username,
+            state.email_verify_expire_seconds / 3600,
+            state.app_base_url,
+            token
+        ),
+    };
+    send_in_background(state, mail);
+
+    audit_service::record(
+        &state.db,
+        user.id,
+        audit_service::EMAIL_CHANGE_REQUESTED,
+        &body.new_email,
+        &client::client_ip(req, state.trusted_proxy_hops),
+        &client::user_agent(req),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//确认修改邮箱:POST /api/account/email/confirm
+//不需要登录：用户可能在另一台设备上打开邮件
+//流程：
+// 1.使用令牌(只能用一次，过期无效)
+// 2.更新users.email，新邮箱在这期间被别人注册了 -> 409
+// 3.除了发起修改的那个会话，其他会话全部下线
+// 4.记审计日志，并通知旧邮箱
+#[handler]
+pub async fn confirm_email_change(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: ConfirmEmailChangeReq = parse_json(req).await?;
+
+    let token_hash = auth::hash_opaque_token(body.token.trim());
+    let row = email_change_service::consume_email_change(&state.db, &token_hash)
+        .await?
+        .ok_or(AppError::Validation(ErrorCode::EmailChangeTokenInvalid))?;
+
+    let user = user_service::find_user_by_id(&state.db, row.user_id)
+        .await?
+        .ok_or(AppError::Validation(ErrorCode::EmailChangeTokenInvalid))?;
+
+    //和注册一样靠唯一索引兜底：并发时两个账号不会改成同一个邮箱
+    if let Err(e) = user_service::change_email(&state.db, user.id, &row.new_email).await {
+        if is_duplicate_key(&e) {
+            return Err(AppError::Conflict(ErrorCode::EmailTaken));
+        }
+        return Err(e.into());
+    }
+
+    end_other_sessions(state, user.id, &row.session_id).await?;
+
+    audit_service::record(
+        &state.db,
+        user.id,
+        audit_service::EMAIL_CHANGED,
+        &format!("{} -> {}", user.email, row.new_email),
+        &client::client_ip(req, state.trusted_proxy_hops),
+        &client::user_agent(req),
+    )
+    .await?;
+
+    //通知旧邮箱：万一不是本人操作，用户还有机会发现
+    let mail = Mail {
+        to: user.email.clone(),
+        subject: "账号邮箱已修改".to_string(),
+        body: format!(
+            "{}，你好：\n\n你的账号邮箱已经改为{}。\n\n如果不是你本人操作，请尽快通过找回密码重新设置密码并联系管理员。",
+            user.username, row.new_email
+        ),
+    };
+    send_in_background(state, mail);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//校验当前密码：和登录共用失败计数，账号或IP被锁定期间直接返回429
+async fn verify_current_password(
+    state: &AppState,
+    req: &Request,
+    user: &UserRow,
+    password: &str,
+) -> AppResult<()> {
+    let user_key = login_throttle::user_key(user.id);
+    let ip_key = login_throttle::ip_key(&client::client_ip(req, state.trusted_proxy_hops));
+    ensure_not_locked(state, &[&user_key, &ip_key]).await?;
+
+    if !check_user_password(state, user, password).await? {
+        record_login_failure(state, &user_key, &ip_key).await?;
+        return Err(AppError::Validation(ErrorCode::PasswordIncorrect));
+    }
+
+    Ok(())
+}
+
+//邮件放到后台发送，不拖慢接口
+fn send_in_background(state: &AppState, mail: Mail) {
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(mail).await {
+            eprintln!("发送邮件失败:{e}");
+        }
+    });
+}
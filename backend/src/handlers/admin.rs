@@ -0,0 +1,241 @@
+//管理接口：路由组上挂了require_auth + require_role("admin")
+use salvo::prelude::*;
+use serde::Serialize;
+
+use super::auth::sign_out_everywhere;
+use super::password::send_reset_link;
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::middleware::auth::current_user;
+use crate::services::role_service::{self, ADMIN_ROLE};
+use crate::services::user_service::{self, AdminUserRow, UserFilter, UserStatus};
+use crate::state::AppState;
+use crate::utils::auth;
+
+//分页参数的默认值和上限
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+//用户列表的返回
+#[derive(Serialize)]
+pub struct UserListResp {
+    pub items: Vec<AdminUserRow>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+//单个用户的返回：基本信息+角色
+#[derive(Serialize)]
+pub struct UserDetailResp {
+    #[serde(flatten)]
+    pub user: AdminUserRow,
+    pub roles: Vec<String>,
+}
+
+//用户列表:GET /api/admin/users?page=1&page_size=20&username=xx&email=xx&status=active
+//username/email是包含匹配；status可选active/disabled/deleted，不传时列出所有没删除的
+#[handler]
+pub async fn list_users(req: &mut Request, depot: &Depot) -> AppResult<Json<UserListResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let page = req.query::<i64>("page").unwrap_or(1).max(1);
+    let page_size = req
+        .query::<i64>("page_size")
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let status = match req.query::<String>("status").as_deref() {
+        None | Some("") => None,
+        Some("active") => Some(UserStatus::Active),
+        Some("disabled") => Some(UserStatus::Disabled),
+        Some("deleted") => Some(UserStatus::Deleted),
+        Some(_) => return Err(AppError::Validation(ErrorCode::InvalidParameter)),
+    };
+    let non_empty = |v: Option<String>| v.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let filter = UserFilter {
+        username: non_empty(req.query::<String>("username")),
+        email: non_empty(req.query::<String>("email")),
+        status,
+    };
+
+    let offset = (page - 1).saturating_mul(page_size);
+    let items = user_service::list_users(&state.db, &filter, offset, page_size).await?;
+    let total = user_service::count_users(&state.db, &filter).await?;
+
+    Ok(Json(UserListResp {
+        items,
+        total,
+        page,
+        page_size,
+    }))
+}
+
+//用户详情:GET /api/admin/users/{id}
+#[handler]
+pub async fn get_user(req: &mut Request, depot: &Depot) -> AppResult<Json<UserDetailResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = load_target_user(state, req).await?;
+    let roles = role_service::list_user_roles(&state.db, user.id).await?;
+
+    Ok(Json(UserDetailResp { user, roles }))
+}
+
+//禁用:POST /api/admin/users/{id}/disable
+//禁用后不能登录，已经登录的地方全部下线；最后一个管理员不能禁用
+#[handler]
+pub async fn disable_user(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = load_target_user(state, req).await?;
+    //不能禁用自己，否则可能把最后一个管理员锁在外面
+    if user.id == current_user(depot).user.id {
+        return Err(AppError::Conflict(ErrorCode::CannotModifySelf));
+    }
+    if user.deleted_at.is_some() {
+        return Err(AppError::Conflict(ErrorCode::UserDeleted));
+    }
+    ensure_not_last_admin(state, &user).await?;
+
+    user_service::set_user_disabled(&state.db, user.id, true).await?;
+    sign_out_everywhere(state, user.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//启用:POST /api/admin/users/{id}/enable
+#[handler]
+pub async fn enable_user(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = load_target_user(state, req).await?;
+    if user.deleted_at.is_some() {
+        return Err(AppError::Conflict(ErrorCode::UserDeleted));
+    }
+
+    user_service::set_user_disabled(&state.db, user.id, false).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//强制重置密码:POST /api/admin/users/{id}/force-password-reset
+//旧密码立即失效，所有地方下线，并给用户发一封重置密码邮件
+#[handler]
+pub async fn force_password_reset(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = load_target_user(state, req).await?;
+    if user.deleted_at.is_some() {
+        return Err(AppError::Conflict(ErrorCode::UserDeleted));
+    }
+    //发邮件需要完整的UserRow
+    let user = user_service::find_user_by_id(&state.db, user.id)
+        .await?
+        .ok_or(AppError::NotFound(ErrorCode::UserNotFound))?;
+
+    //换成一个谁也不知道的随机密码，旧密码就不能再登录了
+    let password_hash = auth::hash_password(&state.argon2, &auth::generate_opaque_token())?;
+    user_service::update_password_hash(&state.db, user.id, &password_hash).await?;
+    sign_out_everywhere(state, user.id).await?;
+    send_reset_link(state, &user, "管理员要求你重新设置密码").await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//删除:DELETE /api/admin/users/{id}?hard=true
+//默认软删除(只打标记，可以查到)；hard=true时连同关联数据一起删掉，不能恢复；最后一个管理员不能删除
+#[handler]
+pub async fn delete_user(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let hard = req.query::<bool>("hard").unwrap_or(false);
+    let user = load_target_user(state, req).await?;
+    if user.id == current_user(depot).user.id {
+        return Err(AppError::Conflict(ErrorCode::CannotModifySelf));
+    }
+    if !hard && user.deleted_at.is_some() {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    ensure_not_last_admin(state, &user).await?;
+
+    sign_out_everywhere(state, user.id).await?;
+    if hard {
+        user_service::hard_delete_user(&state.db, user.id).await?;
+    } else {
+        user_service::soft_delete_user(&state.db, user.id).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//给用户加角色:PUT /api/admin/users/{id}/roles/{role}
+//用户在下一次刷新token后拿到新角色
+#[handler]
+pub async fn grant_role(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let (Some(user_id), Some(role)) = (req.param::<i64>("id"), req.param::<String>("role")) else {
+        return Err(AppError::Validation(ErrorCode::InvalidParameter));
+    };
+
+    if user_service::find_user_by_id(&state.db, user_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::NotFound(ErrorCode::UserNotFound));
+    }
+
+    if !role_service::assign_role(&state.db, user_id, &role).await? {
+        return Err(AppError::NotFound(ErrorCode::RoleNotFound));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//去掉用户的角色:DELETE /api/admin/users/{id}/roles/{role}
+//最后一个管理员不能去掉，否则就没人能管理了
+#[handler]
+pub async fn revoke_role(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let (Some(user_id), Some(role)) = (req.param::<i64>("id"), req.param::<String>("role")) else {
+        return Err(AppError::Validation(ErrorCode::InvalidParameter));
+    };
+
+    if role == ADMIN_ROLE {
+        ensure_not_last_admin(state, &load_target_user(state, req).await?).await?;
+    }
+
+    if !role_service::remove_role(&state.db, user_id, &role).await? {
+        return Err(AppError::NotFound(ErrorCode::RoleNotAssigned));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//禁用、删除、去掉管理员角色之前检查：不能让最后一个还能登录的管理员失效，否则就没人能管理了
+//禁用/删除了的管理员本来就登录不了，不算在内
+async fn ensure_not_last_admin(state: &AppState, user: &AdminUserRow) -> AppResult<()> {
+    if user.deleted_at.is_some() || user.disabled_at.is_some() {
+        return Ok(());
+    }
+    let roles = role_service::list_user_roles(&state.db, user.id).await?;
+    if !roles.iter().any(|r| r == ADMIN_ROLE) {
+        return Ok(());
+    }
+    if role_service::count_users_with_role(&state.db, ADMIN_ROLE).await? <= 1 {
+        return Err(AppError::Conflict(ErrorCode::LastAdmin));
+    }
+    Ok(())
+}
+
+//按路径里的{id}查用户(包括已删除的)
+async fn load_target_user(state: &AppState, req: &Request) -> AppResult<AdminUserRow> {
+    let user_id = req
+        .param::<i64>("id")
+        .ok_or(AppError::Validation(ErrorCode::InvalidParameter))?;
+
+    user_service::find_admin_user_by_id(&state.db, user_id)
+        .await?
+        .ok_or(AppError::NotFound(ErrorCode::UserNotFound))
+}
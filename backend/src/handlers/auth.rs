@@ -4,27 +4,22 @@ use std::str;
 use salvo::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::services::user_service;
-use crate::state::AppState;
-use crate::utils::auth;
-
-//统一错误响应结构
-//先统一返回{"message":"XXX"}
-//给正确的HTTP码
-//- 400:参数/验证码错误
-//- 401:未登录/token无效/密码错误
-//- 409:用户名或邮箱重复
-//- 500:服务器内部错误
-#[derive(Serialize)]
-struct ErrorResp {
-    message: &'static str,
-}
+use chrono::{DateTime, Duration, Utc};
 
-//设置status code + 输出JSON错误信息
-fn render_error(res: &mut Response, code: StatusCode, msg: &'static str) {
-    res.status_code(code);
-    res.render(Json(ErrorResp { message: msg }));
-}
+use super::challenge::{PowSolution, ensure_human};
+use super::email::send_verification_email;
+use super::{parse_json, parse_valid};
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::i18n::Locale;
+use crate::middleware::auth::current_user;
+use crate::services::user_service::UserRow;
+use crate::services::{
+    revocation_service, role_service, session_service, token_service, user_service,
+};
+use crate::state::AppState;
+use crate::utils::auth::PasswordMatch;
+use crate::utils::{auth, client, login_throttle};
+use crate::validation::{self, FieldErrors, Validate};
 
 //请求/响应结构(和前端对齐)
 //注册请求
@@ -33,21 +28,89 @@ pub struct RegisterReq {
     pub username: String,
     pub email: String,
     pub password: String,
+    //人机验证：captcha_id+captcha，或者pow(工作量证明)，二选一
+    #[serde(default)]
     pub captcha_id: String,
+    #[serde(default)]
     pub captcha: String,
+    #[serde(default)]
+    pub pow: Option<PowSolution>,
+}
+impl Validate for RegisterReq {
+    fn validate(&mut self, state: &AppState, errors: &mut FieldErrors) {
+        self.username = validation::normalize(&self.username);
+        self.email = validation::normalize(&self.email);
+        self.password = validation::normalize_password(&self.password);
+
+        validation::check_username(errors, "username", &self.username);
+        validation::check_email(errors, "email", &self.email);
+        validation::check_password(
+            errors,
+            "password",
+            &self.password,
+            &state.password_policy,
+            &[&self.username, &self.email],
+        );
+    }
 }
 //token部分
+//token:短期access token(JWT)，请求接口时放在Authorization里
+//refresh_token:长期不透明令牌，只用来换新的token，每次使用后都会轮换
 #[derive(Serialize)]
 pub struct TokenResp {
     pub token: String,
+    pub refresh_token: String,
+    //access token多少秒后过期
+    pub expires_in: i64,
+}
+//开启了两步验证时，登录接口返回这个而不是TokenResp
+//前端拿mfa_token+验证码去调用/api/auth/login/2fa换真正的token
+#[derive(Serialize)]
+pub struct MfaRequiredResp {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    //mfa_token多少秒后过期
+    pub expires_in: i64,
+}
+//登录的返回：直接拿到token，或者需要两步验证
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResp {
+    Tokens(TokenResp),
+    MfaRequired(MfaRequiredResp),
+}
+//刷新请求
+#[derive(Deserialize)]
+pub struct RefreshReq {
+    pub refresh_token: String,
 }
 //注册请求
 #[derive(Deserialize)]
 pub struct LoginReq {
     pub account: String,
     pub password: String,
+    //人机验证：captcha_id+captcha，或者pow(工作量证明)，二选一
+    #[serde(default)]
     pub captcha_id: String,
+    #[serde(default)]
     pub captcha: String,
+    #[serde(default)]
+    pub pow: Option<PowSolution>,
+}
+//登录只检查有没有填：密码规则改过之后，老密码也要能登录
+//密码保持原样输入，校验时再规范化(见check_user_password)
+impl Validate for LoginReq {
+    fn validate(&mut self, _state: &AppState, errors: &mut FieldErrors) {
+        self.account = validation::normalize(&self.account);
+
+        validation::check_required(errors, "account", &self.account, ErrorCode::AccountRequired);
+        validation::check_required(
+            errors,
+            "password",
+            &self.password,
+            ErrorCode::PasswordRequired,
+        );
+    }
 }
 //用户信息部分
 #[derive(Serialize)]
@@ -55,221 +118,459 @@ pub struct MeResp {
     pub id: i64,
     pub username: String,
     pub email: String,
+    //邮箱是否已经验证
+    pub email_verified: bool,
+    //角色(前端用来决定显不显示管理入口)
+    pub roles: Vec<String>,
+    //用户选的界面语言，为空表示跟随浏览器
+    pub locale: Option<String>,
+}
+//设置界面语言：传null表示跟随浏览器
+#[derive(Deserialize)]
+pub struct UpdateLocaleReq {
+    pub locale: Option<String>,
 }
 
 //注册:POST /api/auth/register
 //流程：
 // 1.parse JSON
-// 2.校验验证码
+// 2.校验验证码或工作量证明
 // 3.参数基础校验(比如用户名、邮箱、密码格式)
 // 4.hash密码
 // 5.insert users插入用户
-// 6.签发JWT token返回
+// 6.发送邮箱验证邮件
+// 7.签发JWT token返回
 #[handler]
-pub async fn register(req: &mut Request, depot: &Depot, res: &mut Response) {
+pub async fn register(req: &mut Request, depot: &Depot) -> AppResult<Json<TokenResp>> {
     let state = depot.obtain::<AppState>().expect("AppState未注入");
-    //解析JSON body
-    let body: RegisterReq = match req.parse_json().await {
-        Ok(v) => v,
-        Err(_) => {
-            render_error(res, StatusCode::BAD_REQUEST, "请求体不是合法JSON");
-            return;
-        }
-    };
+    //解析JSON body并校验参数(用户名/邮箱/密码格式)
+    let body: RegisterReq = parse_valid(req, state).await?;
 
-    //验证码校验
-    let captcha_ok = state
-        .captcha_store
-        .verify_and_consume(&body.captcha_id, &body.captcha);
-    if !captcha_ok {
-        render_error(res, StatusCode::BAD_REQUEST, "验证码错误或已经过期");
-        return;
-    }
+    //验证码(或工作量证明)校验
+    ensure_human(
+        state,
+        req,
+        &body.captcha_id,
+        &body.captcha,
+        body.pow.as_ref(),
+    )
+    .await?;
 
-    //基础参数校验(后续再加)
-    let username = body.username.trim();
-    let email = body.email.trim();
-    if username.len() < 3 || username.len() > 32 {
-        render_error(res, StatusCode::BAD_REQUEST, "用户名长度需要在3-32之间");
-    }
-    if !email.contains('@') {
-        render_error(res, StatusCode::BAD_REQUEST, "邮箱格式错误");
-        return;
-    }
-    if body.password.len() < 6 {
-        render_error(res, StatusCode::BAD_REQUEST, "密码长度至少6位");
-        return;
-    }
+    let (username, email) = (body.username.as_str(), body.email.as_str());
 
     //密码哈希
-    let password_hash = match auth::hash_password(&body.password) {
-        Ok(v) => v,
-        Err(_) => {
-            render_error(res, StatusCode::BAD_REQUEST, "密码处理失败");
-            return;
-        }
-    };
+    let password_hash = auth::hash_password(&state.argon2, &body.password)?;
 
     //写入数据库
     let user_id = match user_service::create_user(&state.db, username, email, &password_hash).await
     {
         Ok(id) => id,
-        Err(e) => {
-            //唯一索引冲突(用户名/邮箱重复)在MySQL常见错误码是1062
-            //sqlx会把它包在Database.error里
-            if let Some(db_err) = e.downcast_ref::<sqlx::Error>() {
-                if let sqlx::Error::Database(dbe) = db_err {
-                    if dbe.code().as_deref() == Some("1062") {
-                        render_error(res, StatusCode::CONFLICT, "用户名或邮箱已存在");
-                        return;
-                    }
-                }
-            }
-            render_error(res, StatusCode::INTERNAL_SERVER_ERROR, "数据写入错误");
-            return;
-        }
+        //唯一索引冲突(用户名/邮箱重复)在MySQL常见错误码是1062
+        //sqlx会把它包在Database.error里
+        Err(e) if is_duplicate_key(&e) => return Err(AppError::Conflict(ErrorCode::UserExists)),
+        Err(e) => return Err(e.into()),
     };
 
-    //签发JWT token
-    let token = match auth::issue_jwt(&state.jwt_secret, state.jwt_expire_seconds, user_id) {
-        Ok(t) => t,
-        Err(_) => {
-            render_error(res, StatusCode::INTERNAL_SERVER_ERROR, "token生成失败");
-            return;
-        }
-    };
+    //发验证邮件：失败不影响注册，用户之后可以重发
+    if let Err(e) = send_verification_email(state, user_id, username, email).await {
+        eprintln!("创建邮箱验证令牌失败:{e}");
+    }
 
-    //返回给前端:{token}
-    res.render(Json(TokenResp { token }));
+    //签发access token + refresh token
+    //返回给前端:{token, refresh_token, expires_in}
+    let tokens = issue_token_pair(state, user_id, req).await?;
+    Ok(Json(tokens))
 }
 
 //登录:POST /api/auth/login
 //流程：
 // 1.parse Json
-// 2.校验验证码
-// 3.通过username或email查用户
-// 4.verify校验密码
-// 5.签发token返回
+// 2.IP被锁定 -> 429
+// 3.校验验证码或工作量证明
+// 4.通过username或email查用户，账号被锁定 -> 429
+// 5.verify校验密码，失败时账号和IP各记一次失败
+// 6.哈希参数过时了就按新参数重新哈希
+// 7.开启了两步验证 -> 返回中间token；否则清零失败计数，签发token返回
 #[handler]
-pub async fn login(req: &mut Request, depot: &Depot, res: &mut Response) {
+pub async fn login(req: &mut Request, depot: &Depot) -> AppResult<Json<LoginResp>> {
     let state = depot.obtain::<AppState>().expect("AppState未注入");
 
-    //解析JSON body
-    let body: LoginReq = match req.parse_json().await {
-        Ok(v) => v,
-        Err(_) => {
-            render_error(res, StatusCode::BAD_REQUEST, "请求体不是合法JSON");
-            return;
-        }
-    };
+    //解析JSON body，账号和密码不能为空
+    let body: LoginReq = parse_valid(req, state).await?;
 
-    //验证码校验
-    let captcha_ok = state
-        .captcha_store
-        .verify_and_consume(&body.captcha_id, &body.captcha);
-    if !captcha_ok {
-        render_error(res, StatusCode::BAD_REQUEST, "验证码错误或已经过期");
-        return;
-    }
+    //这个IP失败太多次，先锁着(验证码也不用校验了)
+    let ip_key = login_throttle::ip_key(&client::client_ip(req, state.trusted_proxy_hops));
+    ensure_not_locked(state, &[&ip_key]).await?;
 
-    //基础校验
-    let account = body.account.trim();
-    if account.is_empty() || body.password.is_empty() {
-        render_error(res, StatusCode::BAD_REQUEST, "账号或密码不能为空");
-        return;
-    }
+    //验证码(或工作量证明)校验
+    ensure_human(
+        state,
+        req,
+        &body.captcha_id,
+        &body.captcha,
+        body.pow.as_ref(),
+    )
+    .await?;
+
+    let account = body.account.as_str();
 
     //通过account查询用户
-    let user = match user_service::find_user_by_account(&state.db, account).await {
-        Ok(v) => v,
-        Err(_) => {
-            render_error(res, StatusCode::INTERNAL_SERVER_ERROR, "数据库查询失败");
-            return;
-        }
-    };
-    //账号不存在或密码错误统一返回账号或密码错误
-    let Some(user) = user else {
-        render_error(res, StatusCode::BAD_REQUEST, "账号或密码错误");
-        return;
+    let user = user_service::find_user_by_account(&state.db, account).await?;
+
+    //账号锁定检查放在校验密码之前：锁定期间密码对了也不放行
+    let account_key = match &user {
+        Some(user) => login_throttle::user_key(user.id),
+        None => login_throttle::account_key(account),
     };
+    ensure_not_locked(state, &[&account_key]).await?;
 
-    //校验密码
-    let ok = match auth::verify_password(&body.password, &user.password_hash) {
-        Ok(v) => v,
-        Err(_) => {
-            render_error(res, StatusCode::INTERNAL_SERVER_ERROR, "密码校验失败");
-            return;
-        }
+    //账号不存在或密码错误统一返回账号或密码错误，失败次数照样记
+    let ok = match &user {
+        Some(user) => check_user_password(state, user, &body.password).await?,
+        None => false,
     };
-    if !ok {
-        render_error(res, StatusCode::BAD_REQUEST, "账号或密码错误");
-        return;
+    let Some(user) = user.filter(|_| ok) else {
+        record_login_failure(state, &account_key, &ip_key).await?;
+        return Err(AppError::Validation(ErrorCode::InvalidCredentials));
     };
 
+    //密码对了才告诉对方账号被禁用，避免被用来探测账号状态
+    if user.disabled_at.is_some() {
+        return Err(AppError::Forbidden(ErrorCode::AccountDisabled));
+    }
+
+    //开启了两步验证：先不发token，等验证码通过
+    if user.totp_enabled_at.is_some() {
+        let mfa_token = auth::issue_mfa_challenge(
+            &state.jwt_keys,
+            state.mfa_challenge_expire_seconds,
+            user.id,
+        )?;
+        return Ok(Json(LoginResp::MfaRequired(MfaRequiredResp {
+            mfa_required: true,
+            mfa_token,
+            expires_in: state.mfa_challenge_expire_seconds,
+        })));
+    }
+
+    //登录成功，失败计数清零(开启了两步验证的要等第二步通过才清)
+    reset_login_failures(state, &[&account_key, &ip_key]).await?;
+
     //签发token
-    let token = match auth::issue_jwt(&state.jwt_secret, state.jwt_expire_seconds, user.id) {
-        Ok(t) => t,
-        Err(_) => {
-            render_error(res, StatusCode::INTERNAL_SERVER_ERROR, "token生成失败");
-            return;
-        }
-    };
+    let tokens = issue_token_pair(state, user.id, req).await?;
+    Ok(Json(LoginResp::Tokens(tokens)))
+}
+
+//刷新:POST /api/auth/refresh
+//流程：
+// 1.parse JSON
+// 2.用refresh token的摘要查库
+// 3.已经轮换过的token又出现 -> 说明被盗用，整个family作废
+// 4.轮换：旧token标记已用，同family下发新token
+// 5.签发新的access token
+#[handler]
+pub async fn refresh(req: &mut Request, depot: &Depot) -> AppResult<Json<TokenResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: RefreshReq = parse_json(req).await?;
+
+    let token_hash = auth::hash_opaque_token(body.refresh_token.trim());
+    let row = token_service::find_refresh_token_by_hash(&state.db, &token_hash)
+        .await?
+        .ok_or(AppError::Unauthorized(ErrorCode::RefreshTokenInvalid))?;
+
+    if row.revoked_at.is_some() {
+        return Err(AppError::Unauthorized(ErrorCode::RefreshTokenRevoked));
+    }
+
+    //重放检测：这个token之前已经换过新token了
+    //整个会话下线，access token也一起失效
+    if row.rotated_at.is_some() {
+        end_session(state, row.user_id, &row.family_id).await?;
+        token_service::revoke_refresh_family(&state.db, &row.family_id).await?;
+        return Err(AppError::Unauthorized(ErrorCode::RefreshTokenReused));
+    }
+
+    if row.expires_at < Utc::now() {
+        return Err(AppError::Unauthorized(ErrorCode::RefreshTokenExpired));
+    }
+
+    //角色每次刷新都重新查，改过的角色最晚一个access token有效期后生效
+    let roles = role_service::list_user_roles(&state.db, row.user_id).await?;
 
-    res.render(Json(TokenResp { token }));
+    //轮换
+    let refresh_token = auth::generate_opaque_token();
+    let expires_at = Utc::now() + Duration::seconds(state.refresh_expire_seconds);
+    let rotated = token_service::rotate_refresh_token(
+        &state.db,
+        &row,
+        &auth::hash_opaque_token(&refresh_token),
+        expires_at,
+    )
+    .await?;
+    //并发请求抢先轮换了同一个token，同样按重放处理
+    if !rotated {
+        let _ = token_service::revoke_refresh_family(&state.db, &row.family_id).await;
+        return Err(AppError::Unauthorized(ErrorCode::RefreshTokenReused));
+    }
+
+    //refresh token的family_id就是会话id，会话跟着新token一起续期
+    let ip = client::client_ip(req, state.trusted_proxy_hops);
+    if let Err(e) = session_service::renew_session(&state.db, &row.family_id, &ip, expires_at).await
+    {
+        eprintln!("更新会话活跃时间失败:{e}");
+    }
+
+    let token = auth::issue_jwt(
+        &state.jwt_keys,
+        state.jwt_expire_seconds,
+        row.user_id,
+        &row.family_id,
+        &roles,
+    )?;
+
+    Ok(Json(TokenResp {
+        token,
+        refresh_token,
+        expires_in: state.jwt_expire_seconds,
+    }))
 }
 
 //Me: GET /api/auth/me
 //前端用途：1.校验token是否有效  2.获取当前用户信息
 //约定：
 //请求头带Authorization: Bearer <token>
-//验证签名后从token里拿user_id,再去数据库查用户信息返回
+//挂在require_auth后面：验证签名、从token里拿user_id查用户都在hoop里做完了
+#[handler]
+pub async fn me(depot: &Depot) -> Json<MeResp> {
+    //require_auth已经验证过token并查出了用户
+    let current = current_user(depot);
+    let user = &current.user;
+
+    //返回给前端(不要返回password_hash)
+    Json(MeResp {
+        id: user.id,
+        username: user.username.clone(),
+        email: user.email.clone(),
+        email_verified: user.email_verified_at.is_some(),
+        roles: current.claims.roles.clone(),
+        locale: user.locale.clone(),
+    })
+}
+
+//设置界面语言:PUT /api/auth/locale
+//之后的错误提示按这个语言返回，不再看Accept-Language
 #[handler]
-pub async fn me(req: &mut Request, depot: &Depot, res: &mut Response) {
+pub async fn update_locale(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
     let state = depot.obtain::<AppState>().expect("AppState未注入");
 
-    //解析Authorization header
-    //大概长这样：Authorization: Bearer xxxxx.yyyyy.zzzzz
-    let token = match parse_bearer_token(req) {
-        Some(t) => t,
-        None => {
-            render_error(res, StatusCode::UNAUTHORIZED, "缺少token");
-            return;
-        }
-    };
+    let user_id = current_user(depot).user.id;
 
-    //验证token(验证签名+检查exp)
-    let claims = match auth::verify_jwt(&state.jwt_secret, &token) {
-        Ok(c) => c,
-        Err(_) => {
-            render_error(res, StatusCode::UNAUTHORIZED, "token无效或已经过期");
-            return;
-        }
-    };
+    let body: UpdateLocaleReq = parse_json(req).await?;
 
-    //使用user_id查数据库
-    let user = match user_service::find_user_by_id(&state.db, claims.sub).await {
-        Ok(v) => v,
-        Err(_) => {
-            render_error(res, StatusCode::INTERNAL_SERVER_ERROR, "数据库查询失败");
-            return;
+    //统一存成标准写法，比如"en-US"存成"en"
+    let locale = match body.locale.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(tag) => {
+            Some(Locale::from_tag(tag).ok_or(AppError::Validation(ErrorCode::InvalidParameter))?)
         }
     };
 
-    let Some(user) = user else {
-        render_error(res, StatusCode::UNAUTHORIZED, "用户不存在");
-        return;
-    };
+    user_service::set_user_locale(&state.db, user_id, locale.map(Locale::tag)).await?;
 
-    //返回给前端(不要返回password_hash)
-    res.render(Json(MeResp {
-        id: user.id,
-        username: user.username,
-        email: user.email,
-    }));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//登出:POST /api/auth/logout
+//流程：
+// 1.require_auth校验当前access token
+// 2.删除当前会话，这次登录的refresh token全部作废
+// 3.把access token的jti写进吊销表+内存缓存，直到它本来的exp为止
+#[handler]
+pub async fn logout(depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+    let current = current_user(depot);
+    let claims = &current.claims;
+
+    end_session(state, claims.sub, current.session_id()).await?;
+
+    let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_else(Utc::now);
+    revocation_service::revoke_token(&state.db, &claims.jti, claims.sub, expires_at).await?;
+    state
+        .revocation_store
+        .insert(claims.jti.clone(), expires_at);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//校验用户输入的密码(原样输入)，对上了顺便升级哈希，失败不影响这次校验：
+//规范化之前生成的老哈希，或者哈希参数调整过(新加了pepper/换了pepper)，趁手上有明文密码按规范化之后的密码和新参数重新哈希
+pub(crate) async fn check_user_password(
+    state: &AppState,
+    user: &UserRow,
+    input: &str,
+) -> anyhow::Result<bool> {
+    let matched = auth::verify_user_password(&state.argon2, input, &user.password_hash)?;
+    if matched == PasswordMatch::Mismatched {
+        return Ok(false);
+    }
+
+    if (matched == PasswordMatch::Legacy || auth::needs_rehash(&state.argon2, &user.password_hash))
+        && let Err(e) = rehash_password(state, user, &validation::normalize_password(input)).await
+    {
+        eprintln!("重新哈希密码失败:{e}");
+    }
+    Ok(true)
+}
+
+//按现在的argon2配置重新哈希密码并写回
+async fn rehash_password(state: &AppState, user: &UserRow, password: &str) -> anyhow::Result<()> {
+    let new_hash = auth::hash_password(&state.argon2, password)?;
+    user_service::rehash_password(&state.db, user.id, &user.password_hash, &new_hash).await?;
+    Ok(())
+}
+
+//登录/注册成功后签发一对新token
+//同时记录一个新会话；refresh token开启一个新的family(id和会话相同)，之后的轮换都留在这个family里
+pub(crate) async fn issue_token_pair(
+    state: &AppState,
+    user_id: i64,
+    req: &Request,
+) -> anyhow::Result<TokenResp> {
+    let session_id = auth::generate_opaque_token();
+    //会话和refresh token同时过期
+    let expires_at = Utc::now() + Duration::seconds(state.refresh_expire_seconds);
+    session_service::create_session(
+        &state.db,
+        &session_id,
+        user_id,
+        &client::user_agent(req),
+        &client::client_ip(req, state.trusted_proxy_hops),
+        expires_at,
+    )
+    .await?;
+
+    let roles = role_service::list_user_roles(&state.db, user_id).await?;
+    let token = auth::issue_jwt(
+        &state.jwt_keys,
+        state.jwt_expire_seconds,
+        user_id,
+        &session_id,
+        &roles,
+    )?;
+
+    let refresh_token = auth::generate_opaque_token();
+    token_service::create_refresh_token(
+        &state.db,
+        user_id,
+        &session_id,
+        &auth::hash_opaque_token(&refresh_token),
+        expires_at,
+    )
+    .await?;
+
+    Ok(TokenResp {
+        token,
+        refresh_token,
+        expires_in: state.jwt_expire_seconds,
+    })
+}
+
+//结束一个会话：删除会话记录+作废这个会话的refresh token
+//返回false表示会话不存在(或者不是这个用户的)
+pub(crate) async fn end_session(
+    state: &AppState,
+    user_id: i64,
+    session_id: &str,
+) -> anyhow::Result<bool> {
+    if !session_service::delete_session(&state.db, user_id, session_id).await? {
+        return Ok(false);
+    }
+    token_service::revoke_refresh_family(&state.db, session_id).await?;
+    Ok(true)
+}
+
+//除了session_id这个会话，其他会话全部下线：会话删除+refresh token作废
+//access token在下一次请求时因为会话不在了被拒绝
+pub(crate) async fn end_other_sessions(
+    state: &AppState,
+    user_id: i64,
+    session_id: &str,
+) -> anyhow::Result<()> {
+    session_service::delete_other_sessions(&state.db, user_id, session_id).await?;
+    token_service::revoke_other_refresh_families(&state.db, user_id, session_id).await?;
+    Ok(())
+}
+
+//让用户所有已登录的地方下线：会话全部删除，refresh token全部作废，之前签发的access token全部吊销
+//重置密码、管理员禁用/删除账号时使用
+pub(crate) async fn sign_out_everywhere(state: &AppState, user_id: i64) -> anyhow::Result<()> {
+    let not_before = Utc::now();
+    let expires_at = not_before + Duration::seconds(state.jwt_expire_seconds);
+
+    session_service::delete_user_sessions(&state.db, user_id).await?;
+    token_service::revoke_user_refresh_tokens(&state.db, user_id).await?;
+    revocation_service::revoke_user_tokens(&state.db, user_id, not_before, expires_at).await?;
+    state
+        .revocation_store
+        .insert_user(user_id, not_before, expires_at);
+
+    Ok(())
+}
+
+//这些key里有正在锁定的就返回429，Retry-After取最晚的解锁时间
+pub(crate) async fn ensure_not_locked(state: &AppState, keys: &[&str]) -> AppResult<()> {
+    let mut latest = None;
+    for key in keys {
+        let until = state.login_attempts.locked_until(key).await?;
+        latest = latest.max(until);
+    }
+
+    match latest {
+        Some(until) => Err(AppError::RateLimited {
+            code: ErrorCode::TooManyFailures,
+            retry_after: (until - Utc::now()).num_seconds().max(1),
+        }),
+        None => Ok(()),
+    }
+}
+
+//记一次登录失败：账号和IP各记一次，阈值不同
+pub(crate) async fn record_login_failure(
+    state: &AppState,
+    account_key: &str,
+    ip_key: &str,
+) -> anyhow::Result<()> {
+    let throttle = &state.login_throttle;
+    state
+        .login_attempts
+        .record_failure(account_key, throttle.account_max_failures)
+        .await?;
+    state
+        .login_attempts
+        .record_failure(ip_key, throttle.ip_max_failures)
+        .await?;
+    Ok(())
+}
+
+//登录成功：失败计数清零
+pub(crate) async fn reset_login_failures(state: &AppState, keys: &[&str]) -> anyhow::Result<()> {
+    for key in keys {
+        state.login_attempts.reset(key).await?;
+    }
+    Ok(())
+}
+
+//是不是唯一索引冲突(MySQL错误码1062)
+pub(crate) fn is_duplicate_key(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<sqlx::Error>(),
+        Some(sqlx::Error::Database(dbe)) if dbe.code().as_deref() == Some("1062")
+    )
 }
 
 //从Authorization header里解析Bearer token
-fn parse_bearer_token(req: &Request) -> Option<String> {
+pub(crate) fn parse_bearer_token(req: &Request) -> Option<String> {
     let raw = req.headers().get("authorization")?.to_str().ok()?;
     let raw = raw.trim();
 
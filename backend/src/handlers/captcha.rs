@@ -1,26 +1,49 @@
-use base64::{Engine as _, engine::general_purpose};
 use chrono::{Duration, Utc};
 use rand::{Rng, distributions::Alphanumeric};
 use salvo::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::state::{AppState, CaptchaEntry};
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::state::AppState;
+use crate::utils::captcha_render::{self, CaptchaKind};
+use crate::utils::captcha_store::CaptchaEntry;
+use crate::utils::client;
 
 #[derive(Serialize)]
 struct CaptchaResp {
     captcha_id: String,
+    //image/svg/math/audio
+    kind: &'static str,
+    //data URL(PNG或SVG)，前端可以直接显示
     image: String,
+    //语音验证码才有：data URL(WAV)，内容和图片是同一个答案
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<String>,
+    //图片的实际尺寸(像素)
+    width: u32,
+    height: u32,
     //多少秒后过期
     expires_in: i64,
-    // //直接把答案展示出来，正式使用时删掉这部分
-    // #[serde(skip_serializing_if = "Option::is_none")]
-    // debug_answer: Option<String>,
+    //DEBUG_CAPTCHA=true时直接把答案返回，方便本地调试；线上不要打开
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debug_answer: Option<String>,
 }
 
 //生成验证码：图片+id+服务端存储答案
+//GET /api/captcha?scene=login|register，按场景使用不同的长度、尺寸、难度，不传用通用配置
+//kind=image(默认PNG)|svg|math(算术题)|audio(PNG+语音)，不管哪种都存答案，校验方式一样
 #[handler]
-pub async fn get_captcha(depot: &Depot) -> Json<CaptchaResp> {
+pub async fn get_captcha(req: &mut Request, depot: &Depot) -> AppResult<Json<CaptchaResp>> {
     let state = depot.obtain::<AppState>().expect("AppState未注入");
+    let config = state
+        .captcha_presets
+        .get(req.query::<String>("scene").as_deref().unwrap_or_default());
+    let kind = match req.query::<String>("kind").as_deref() {
+        None | Some("") => CaptchaKind::Image,
+        Some(kind) => {
+            CaptchaKind::parse(kind).ok_or(AppError::Validation(ErrorCode::InvalidParameter))?
+        }
+    };
 
     //生成captcha_id
     let captcha_id: String = rand::thread_rng()
@@ -29,34 +52,33 @@ pub async fn get_captcha(depot: &Depot) -> Json<CaptchaResp> {
         .map(char::from)
         .collect();
 
-    //通过captcha crate生成PNG
-    let mut cap = captcha::Captcha::new();
-    cap.add_chars(5);
-    let answer = cap.chars_as_string(); //取出答案后存储
-    let png_bytes = cap.view(180, 60).as_png().expect("生成验证码图片失败");
+    //画图片(和语音)比较耗CPU，放到阻塞线程池里
+    //captcha crate里用的是线程本地的随机数，本来也不能跨await
+    let render_config = config.clone();
+    let rendered =
+        tokio::task::spawn_blocking(move || captcha_render::render(kind, &render_config))
+            .await
+            .map_err(|e| anyhow::anyhow!("生成验证码失败:{e}"))??;
 
     //服务端保存答案
-    let expires_in = 120; //过期时间120s
+    let expires_in = config.ttl_seconds;
     let entry = CaptchaEntry {
-        answer: answer.clone(),
+        answer: rendered.answer.clone(),
         expires_at: Utc::now() + Duration::seconds(expires_in),
+        ip: client::client_ip(req, state.trusted_proxy_hops),
     };
-    state.captcha_store.insert(captcha_id.clone(), entry);
-
-    //base64：前端可以直接显示
-    let b64 = general_purpose::STANDARD.encode(png_bytes);
-    let image = format!("data:image/png;base64,{}", b64);
+    state.captcha_store.insert(&captcha_id, entry).await?;
 
-    Json(CaptchaResp {
+    Ok(Json(CaptchaResp {
         captcha_id,
-        image,
+        kind: kind.as_str(),
+        image: rendered.image,
+        audio: rendered.audio,
+        width: config.width,
+        height: config.height,
         expires_in,
-        // debug_answer: if state.debug_captcha {
-        //     Some(answer)
-        // } else {
-        //     None
-        // },
-    })
+        debug_answer: state.debug_captcha.then_some(rendered.answer),
+    }))
 }
 
 #[derive(Deserialize)]
@@ -73,18 +95,19 @@ struct VerifyResp {
 //用来测试“验证码校验是否工作”
 //真正的登录接口里：会把 captcha_id + code 跟用户名密码一起提交
 #[handler]
-pub async fn verify_captcha(req: &mut Request, depot: &Depot) -> Json<VerifyResp> {
+pub async fn verify_captcha(req: &mut Request, depot: &Depot) -> AppResult<Json<VerifyResp>> {
     let state = depot.obtain::<AppState>().expect("AppState 未注入");
 
     //从body解析json
     let body: VerifyReq = match req.parse_json().await {
         Ok(v) => v,
-        Err(_) => return Json(VerifyResp { ok: false }),
+        Err(_) => return Ok(Json(VerifyResp { ok: false })),
     };
 
     let ok = state
         .captcha_store
-        .verify_and_consume(&body.captcha_id, &body.code);
+        .verify_and_consume(&body.captcha_id, &body.code)
+        .await?;
 
-    Json(VerifyResp { ok })
+    Ok(Json(VerifyResp { ok }))
 }
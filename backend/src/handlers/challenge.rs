@@ -0,0 +1,83 @@
+//工作量证明：图片验证码的替代方式，客户端在后台算出答案，用户什么都不用做
+//API客户端、看不清验证码的用户可以用它代替captcha_id+captcha
+use salvo::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::state::AppState;
+use crate::utils::{client, login_throttle};
+
+#[derive(Serialize)]
+pub struct PowChallengeResp {
+    //提交时原样带上
+    pub challenge: String,
+    //sha256(challenge + ":" + solution)开头至少要有几个0比特
+    pub difficulty: u32,
+    pub algorithm: &'static str,
+    //多少秒后过期
+    pub expires_in: i64,
+}
+
+//登录/注册时提交的工作量证明
+#[derive(Deserialize)]
+pub struct PowSolution {
+    pub challenge: String,
+    pub solution: String,
+}
+
+//获取challenge:GET /api/challenge/pow
+//难度按这个IP最近登录失败的次数往上加，失败越多越难算
+#[handler]
+pub async fn get_pow_challenge(
+    req: &mut Request,
+    depot: &Depot,
+) -> AppResult<Json<PowChallengeResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let ip = client::client_ip(req, state.trusted_proxy_hops);
+    let failures = state
+        .login_attempts
+        .recent_failures(&login_throttle::ip_key(&ip))
+        .await?;
+    //challenge自带签名和过期时间，发的时候不用存；用过之后记到pow_nonces里防止重复用
+    let challenge = state.pow.issue(state.pow.difficulty(failures), &ip);
+
+    Ok(Json(PowChallengeResp {
+        challenge: challenge.challenge,
+        difficulty: challenge.difficulty,
+        algorithm: "sha256",
+        expires_in: state.pow.ttl_seconds(),
+    }))
+}
+
+//人机验证：交了工作量证明就校验它，否则校验图片验证码
+pub(crate) async fn ensure_human(
+    state: &AppState,
+    req: &Request,
+    captcha_id: &str,
+    captcha: &str,
+    pow: Option<&PowSolution>,
+) -> AppResult<()> {
+    let Some(solution) = pow else {
+        let captcha_ok = state
+            .captcha_store
+            .verify_and_consume(captcha_id, captcha)
+            .await?;
+        if !captcha_ok {
+            return Err(AppError::Validation(ErrorCode::CaptchaInvalid));
+        }
+        return Ok(());
+    };
+
+    let ip = client::client_ip(req, state.trusted_proxy_hops);
+    let (nonce, expires_at) = state
+        .pow
+        .verify(&solution.challenge, solution.solution.trim(), &ip)
+        .ok_or(AppError::Validation(ErrorCode::PowInvalid))?;
+    let unused = state.pow_nonces.mark_spent(&nonce, expires_at).await?;
+    if !unused {
+        return Err(AppError::Validation(ErrorCode::PowInvalid));
+    }
+
+    Ok(())
+}
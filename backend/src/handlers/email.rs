@@ -0,0 +1,125 @@
+//邮箱验证：注册后发验证邮件，用户点链接后标记为已验证
+use chrono::{Duration, Utc};
+use salvo::prelude::*;
+use serde::Deserialize;
+
+use super::parse_json;
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::middleware::auth::current_user;
+use crate::services::{email_verification_service, user_service};
+use crate::state::AppState;
+use crate::utils::auth;
+use crate::utils::mailer::Mail;
+
+//验证请求：邮件链接里的token
+#[derive(Deserialize)]
+pub struct VerifyEmailReq {
+    pub token: String,
+}
+
+//生成验证令牌并把验证链接发到email
+//注册和重发都走这里；发信放到后台，不拖慢接口
+pub(crate) async fn send_verification_email(
+    state: &AppState,
+    user_id: i64,
+    username: &str,
+    email: &str,
+) -> anyhow::Result<()> {
+    let token = auth::generate_opaque_token();
+    let expires_at = Utc::now() + Duration::seconds(state.email_verify_expire_seconds);
+    email_verification_service::create_verification_token(
+        &state.db,
+        user_id,
+        email,
+        &auth::hash_opaque_token(&token),
+        expires_at,
+    )
+    .await?;
+
+    let mail = Mail {
+        to: email.to_string(),
+        subject: "验证你的邮箱".to_string(),
+        body: format!(
+            "{}，你好：\n\n请在{}小时内打开下面的链接完成邮箱验证：\n{}/verify-email?token={}\n\n如果不是你本人操作，请忽略这封邮件。",
+            username,
+            state.email_verify_expire_seconds / 3600,
+            state.app_base_url,
+            token
+        ),
+    };
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(mail).await {
+            eprintln!("发送验证邮件失败:{e}");
+        }
+    });
+
+    Ok(())
+}
+
+//验证邮箱:POST /api/auth/email/verify
+//不需要登录：用户可能在另一台设备上打开邮件
+#[handler]
+pub async fn verify_email(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: VerifyEmailReq = parse_json(req).await?;
+
+    let token_hash = auth::hash_opaque_token(body.token.trim());
+    let (user_id, email) =
+        email_verification_service::consume_verification_token(&state.db, &token_hash)
+            .await?
+            .ok_or(AppError::Validation(ErrorCode::VerifyTokenInvalid))?;
+
+    //没有标记上：要么已经验证过了(重复点链接，算成功)，要么用户后来换了邮箱，这个链接验证的是旧邮箱
+    if !user_service::mark_email_verified(&state.db, user_id, &email).await? {
+        let verified = user_service::find_user_by_id(&state.db, user_id)
+            .await?
+            .is_some_and(|user| user.email_verified_at.is_some());
+        if !verified {
+            return Err(AppError::Validation(ErrorCode::VerifyTokenInvalid));
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//重发验证邮件:POST /api/auth/email/resend
+//需要登录；两次发送之间至少间隔email_resend_interval_seconds
+#[handler]
+pub async fn resend_verification(depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = &current_user(depot).user;
+
+    if user.email_verified_at.is_some() {
+        return Err(AppError::Validation(ErrorCode::EmailAlreadyVerified));
+    }
+
+    //限流：距离上一封还不到间隔时间
+    if let Some(last_sent_at) = email_verification_service::last_sent_at(&state.db, user.id).await?
+    {
+        let next_allowed = last_sent_at + Duration::seconds(state.email_resend_interval_seconds);
+        let wait = (next_allowed - Utc::now()).num_seconds();
+        if wait > 0 {
+            return Err(AppError::RateLimited {
+                code: ErrorCode::EmailSendTooFrequent,
+                retry_after: wait,
+            });
+        }
+    }
+
+    send_verification_email(state, user.id, &user.username, &user.email).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//hoop：要求当前用户已经验证过邮箱
+//挂在require_auth后面，没验证返回403
+#[handler]
+pub async fn require_verified_email(depot: &mut Depot) -> AppResult<()> {
+    if current_user(depot).user.email_verified_at.is_none() {
+        return Err(AppError::Forbidden(ErrorCode::EmailNotVerified));
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use jsonwebtoken::jwk::JwkSet;
+use salvo::http::header::CACHE_CONTROL;
+use salvo::prelude::*;
+
+use crate::state::AppState;
+
+//JWT公钥:GET /.well-known/jwks.json
+//不需要登录；只有公钥，用HS256时是空列表
+//允许缓存一会儿，轮换密钥时新公钥要提前配置上，等缓存过期之后再切换签名密钥
+#[handler]
+pub async fn jwks(depot: &Depot, res: &mut Response) -> Json<JwkSet> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    res.headers_mut()
+        .insert(CACHE_CONTROL, "public, max-age=300".parse().unwrap());
+
+    Json(state.jwt_keys.jwks().clone())
+}
@@ -1,3 +1,42 @@
+use salvo::prelude::*;
+use serde::de::DeserializeOwned;
+
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::state::AppState;
+use crate::validation::{FieldErrors, Validate};
+
+pub mod account;
+pub mod admin;
 pub mod auth;
 pub mod captcha;
+pub mod challenge;
+pub mod email;
 pub mod health;
+pub mod jwks;
+pub mod passkey;
+pub mod password;
+pub mod session;
+pub mod two_factor;
+
+//解析JSON请求体，格式不对统一返回400 invalid_json
+pub(crate) async fn parse_json<T: DeserializeOwned>(req: &mut Request) -> AppResult<T> {
+    req.parse_json::<T>()
+        .await
+        .map_err(|_| AppError::Validation(ErrorCode::InvalidJson))
+}
+
+//解析JSON请求体并校验字段，有错误时返回422，列出所有字段的错误
+pub(crate) async fn parse_valid<T: DeserializeOwned + Validate>(
+    req: &mut Request,
+    state: &AppState,
+) -> AppResult<T> {
+    let mut body: T = parse_json(req).await?;
+
+    let mut errors = FieldErrors::default();
+    body.validate(state, &mut errors);
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+
+    Ok(body)
+}
@@ -0,0 +1,264 @@
+//通行密钥(WebAuthn/passkey)：注册和登录
+//两个流程都分start/finish两步：
+// start：服务端生成challenge和选项，前端交给navigator.credentials.create()/get()
+// finish：前端把认证器的返回原样提交，服务端校验签名
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{Duration, Utc};
+use salvo::prelude::*;
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, Passkey, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse, Uuid,
+};
+
+use super::auth::{TokenResp, is_duplicate_key, issue_token_pair};
+use super::parse_json;
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::middleware::auth::current_user;
+use crate::services::{passkey_service, user_service};
+use crate::state::{AppState, PasskeyChallenge};
+use crate::utils::auth;
+use crate::validation;
+
+//注册start的返回：challenge_id(finish时带回来)+给navigator.credentials.create()的选项
+#[derive(Serialize)]
+pub struct RegistrationStartResp {
+    pub challenge_id: String,
+    pub options: CreationChallengeResponse,
+}
+
+//注册finish请求：name是给用户自己区分用的名字，比如"我的手机"
+#[derive(Deserialize)]
+pub struct RegistrationFinishReq {
+    pub challenge_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Serialize)]
+pub struct PasskeyResp {
+    pub id: i64,
+    pub name: String,
+}
+
+//登录start请求：账号(用户名或邮箱)
+#[derive(Deserialize)]
+pub struct AuthenticationStartReq {
+    pub account: String,
+}
+
+//登录start的返回：challenge_id+给navigator.credentials.get()的选项
+#[derive(Serialize)]
+pub struct AuthenticationStartResp {
+    pub challenge_id: String,
+    pub options: RequestChallengeResponse,
+}
+
+#[derive(Deserialize)]
+pub struct AuthenticationFinishReq {
+    pub challenge_id: String,
+    pub credential: PublicKeyCredential,
+}
+
+//注册start:POST /api/auth/passkey/register/start
+//需要登录：通行密钥是加在已有账号上的
+#[handler]
+pub async fn start_registration(depot: &Depot) -> AppResult<Json<RegistrationStartResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = &current_user(depot).user;
+
+    //已经注册过的凭据不让同一个认证器重复注册
+    let exclude_credentials = passkey_service::list_passkeys_by_user(&state.db, user.id)
+        .await?
+        .iter()
+        .filter_map(|row| serde_json::from_str::<Passkey>(&row.passkey).ok())
+        .map(|pk| pk.cred_id().clone())
+        .collect::<Vec<_>>();
+
+    let (options, registration) = state
+        .webauthn
+        .start_passkey_registration(
+            webauthn_user_id(user.id),
+            &user.username,
+            &user.username,
+            Some(exclude_credentials),
+        )
+        .map_err(|e| anyhow::anyhow!("通行密钥选项生成失败:{e}"))?;
+
+    let challenge_id = auth::generate_opaque_token();
+    state.passkey_challenges.insert(
+        challenge_id.clone(),
+        PasskeyChallenge::Registration {
+            user_id: user.id,
+            state: registration,
+        },
+        Utc::now() + Duration::seconds(state.passkey_challenge_expire_seconds),
+    );
+
+    Ok(Json(RegistrationStartResp {
+        challenge_id,
+        options,
+    }))
+}
+
+//注册finish:POST /api/auth/passkey/register/finish
+//校验认证器返回的attestation，通过后保存公钥
+#[handler]
+pub async fn finish_registration(req: &mut Request, depot: &Depot) -> AppResult<Json<PasskeyResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = &current_user(depot).user;
+
+    let body: RegistrationFinishReq = parse_json(req).await?;
+
+    //challenge必须是这个用户自己发起的注册
+    let registration = match state.passkey_challenges.take(&body.challenge_id) {
+        Some(PasskeyChallenge::Registration { user_id, state }) if user_id == user.id => state,
+        _ => return Err(AppError::Validation(ErrorCode::PasskeyChallengeInvalid)),
+    };
+
+    let passkey = state
+        .webauthn
+        .finish_passkey_registration(&body.credential, &registration)
+        .map_err(|_| AppError::Validation(ErrorCode::PasskeyVerifyFailed))?;
+
+    let passkey_json = serde_json::to_string(&passkey)?;
+    let name = body
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or("通行密钥")
+        .chars()
+        .take(64)
+        .collect::<String>();
+
+    let id = passkey_service::create_passkey(
+        &state.db,
+        user.id,
+        &credential_id_string(&passkey),
+        &name,
+        &passkey_json,
+    )
+    .await
+    .map_err(|e| {
+        //同一个凭据id已经注册过(唯一索引冲突)
+        if is_duplicate_key(&e) {
+            AppError::Conflict(ErrorCode::PasskeyAlreadyRegistered)
+        } else {
+            AppError::Internal(e)
+        }
+    })?;
+
+    Ok(Json(PasskeyResp { id, name }))
+}
+
+//登录start:POST /api/auth/passkey/login/start
+//用账号查出这个用户的通行密钥，生成challenge
+#[handler]
+pub async fn start_authentication(
+    req: &mut Request,
+    depot: &Depot,
+) -> AppResult<Json<AuthenticationStartResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: AuthenticationStartReq = parse_json(req).await?;
+
+    let account = validation::normalize(&body.account);
+    if account.is_empty() {
+        return Err(AppError::Validation(ErrorCode::AccountRequired));
+    }
+
+    //账号不存在和没有通行密钥返回同样的错误
+    let user = user_service::find_user_by_account(&state.db, &account)
+        .await?
+        .ok_or(AppError::Validation(ErrorCode::PasskeyNotAvailable))?;
+    let passkeys = load_passkeys(state, user.id).await?;
+    if passkeys.is_empty() {
+        return Err(AppError::Validation(ErrorCode::PasskeyNotAvailable));
+    }
+
+    let credentials = passkeys.into_iter().map(|(_, pk)| pk).collect::<Vec<_>>();
+    let (options, authentication) = state
+        .webauthn
+        .start_passkey_authentication(&credentials)
+        .map_err(|e| anyhow::anyhow!("通行密钥选项生成失败:{e}"))?;
+
+    let challenge_id = auth::generate_opaque_token();
+    state.passkey_challenges.insert(
+        challenge_id.clone(),
+        PasskeyChallenge::Authentication {
+            user_id: user.id,
+            state: authentication,
+        },
+        Utc::now() + Duration::seconds(state.passkey_challenge_expire_seconds),
+    );
+
+    Ok(Json(AuthenticationStartResp {
+        challenge_id,
+        options,
+    }))
+}
+
+//登录finish:POST /api/auth/passkey/login/finish
+//校验认证器返回的assertion，通过后签发和密码登录一样的token
+//通行密钥本身就包含"持有设备+设备解锁"两个因素，所以不再要求TOTP
+#[handler]
+pub async fn finish_authentication(req: &mut Request, depot: &Depot) -> AppResult<Json<TokenResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: AuthenticationFinishReq = parse_json(req).await?;
+
+    let (user_id, authentication) = match state.passkey_challenges.take(&body.challenge_id) {
+        Some(PasskeyChallenge::Authentication { user_id, state }) => (user_id, state),
+        _ => return Err(AppError::Validation(ErrorCode::PasskeyChallengeInvalid)),
+    };
+
+    let result = state
+        .webauthn
+        .finish_passkey_authentication(&body.credential, &authentication)
+        .map_err(|_| AppError::Unauthorized(ErrorCode::PasskeyVerifyFailed))?;
+
+    //更新签名计数器，防止凭据被克隆后重复使用
+    let (id, mut passkey) = load_passkeys(state, user_id)
+        .await?
+        .into_iter()
+        .find(|(_, pk)| pk.cred_id() == result.cred_id())
+        .ok_or(AppError::Unauthorized(ErrorCode::PasskeyRemoved))?;
+    passkey.update_credential(&result);
+    passkey_service::update_passkey_after_login(&state.db, id, &serde_json::to_string(&passkey)?)
+        .await?;
+
+    //challenge发出之后账号可能被禁用或删除了
+    let user = user_service::find_user_by_id(&state.db, user_id)
+        .await?
+        .ok_or(AppError::Unauthorized(ErrorCode::UserNotFound))?;
+    if user.disabled_at.is_some() {
+        return Err(AppError::Forbidden(ErrorCode::AccountDisabled));
+    }
+
+    let tokens = issue_token_pair(state, user_id, req).await?;
+
+    Ok(Json(tokens))
+}
+
+//查出用户的通行密钥并反序列化：(记录id, Passkey)
+async fn load_passkeys(state: &AppState, user_id: i64) -> anyhow::Result<Vec<(i64, Passkey)>> {
+    passkey_service::list_passkeys_by_user(&state.db, user_id)
+        .await?
+        .into_iter()
+        .map(|row| Ok((row.id, serde_json::from_str::<Passkey>(&row.passkey)?)))
+        .collect()
+}
+
+//WebAuthn里的user handle：固定由用户id推出来，同一个用户的所有通行密钥共用
+fn webauthn_user_id(user_id: i64) -> Uuid {
+    Uuid::from_u64_pair(0, user_id as u64)
+}
+
+//凭据id转成字符串存库(base64url)
+fn credential_id_string(passkey: &Passkey) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(passkey.cred_id())
+}
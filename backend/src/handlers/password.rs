@@ -0,0 +1,170 @@
+//找回密码：发重置邮件 + 凭邮件里的一次性令牌设置新密码
+use chrono::{Duration, Utc};
+use salvo::prelude::*;
+use serde::Deserialize;
+
+use super::auth::sign_out_everywhere;
+use super::challenge::{PowSolution, ensure_human};
+use super::parse_valid;
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::services::user_service::UserRow;
+use crate::services::{password_reset_service, user_service};
+use crate::state::AppState;
+use crate::utils::auth;
+use crate::utils::mailer::Mail;
+use crate::validation::{self, FieldErrors, Validate};
+
+//找回密码请求：账号(用户名或邮箱)+人机验证
+#[derive(Deserialize)]
+pub struct ForgotPasswordReq {
+    pub account: String,
+    //人机验证：captcha_id+captcha，或者pow(工作量证明)，二选一
+    #[serde(default)]
+    pub captcha_id: String,
+    #[serde(default)]
+    pub captcha: String,
+    #[serde(default)]
+    pub pow: Option<PowSolution>,
+}
+
+impl Validate for ForgotPasswordReq {
+    fn validate(&mut self, _state: &AppState, errors: &mut FieldErrors) {
+        self.account = validation::normalize(&self.account);
+
+        validation::check_required(errors, "account", &self.account, ErrorCode::AccountRequired);
+    }
+}
+
+//重置密码请求：邮件链接里的token+新密码
+#[derive(Deserialize)]
+pub struct ResetPasswordReq {
+    pub token: String,
+    pub password: String,
+}
+
+impl Validate for ResetPasswordReq {
+    fn validate(&mut self, state: &AppState, errors: &mut FieldErrors) {
+        self.token = self.token.trim().to_string();
+        self.password = validation::normalize_password(&self.password);
+
+        //这时还不知道是哪个用户，包含用户名/邮箱的检查等查到令牌之后再做
+        validation::check_password(
+            errors,
+            "password",
+            &self.password,
+            &state.password_policy,
+            &[],
+        );
+    }
+}
+
+//找回密码:POST /api/auth/password/forgot
+//流程：
+// 1.parse JSON
+// 2.校验验证码(或工作量证明)
+// 3.通过username或email查用户
+// 4.在后台生成一次性令牌(库里只存摘要)，把带令牌的链接发到用户邮箱
+//不管账号存不存在都返回同样的结果，防止被用来探测哪些账号注册过：
+//两种情况在响应之前做的事一样(都只查一次用户)，写令牌和发信都放到后台，响应时间看不出账号是否存在
+#[handler]
+pub async fn forgot_password(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: ForgotPasswordReq = parse_valid(req, state).await?;
+
+    //验证码(或工作量证明)校验
+    ensure_human(
+        state,
+        req,
+        &body.captcha_id,
+        &body.captcha,
+        body.pow.as_ref(),
+    )
+    .await?;
+
+    if let Some(user) = user_service::find_user_by_account(&state.db, &body.account).await? {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = send_reset_link(&state, &user, "你正在重置密码").await {
+                eprintln!("生成重置密码令牌失败:{e}");
+            }
+        });
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//重置密码:POST /api/auth/password/reset
+//流程：
+// 1.parse JSON
+// 2.校验新密码
+// 3.用令牌查出用户，新密码不能包含用户名/邮箱(这一步不作废令牌，用户可以换个密码再提交)
+// 4.使用令牌(只能用一次，过期无效)
+// 5.hash新密码并更新
+// 6.让这个用户所有已登录的地方下线：会话全部删除，refresh token全部作废，之前签发的access token全部吊销
+#[handler]
+pub async fn reset_password(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: ResetPasswordReq = parse_valid(req, state).await?;
+
+    let token_hash = auth::hash_opaque_token(&body.token);
+    let user = match password_reset_service::find_reset_token_user(&state.db, &token_hash).await? {
+        Some(user_id) => user_service::find_user_by_id(&state.db, user_id).await?,
+        None => None,
+    }
+    .ok_or(AppError::Validation(ErrorCode::ResetTokenInvalid))?;
+
+    validation::ensure_new_password(state, "password", &body.password, &user)?;
+
+    let user_id = password_reset_service::consume_reset_token(&state.db, &token_hash)
+        .await?
+        .ok_or(AppError::Validation(ErrorCode::ResetTokenInvalid))?;
+
+    let password_hash = auth::hash_password(&state.argon2, &body.password)?;
+    user_service::update_password_hash(&state.db, user_id, &password_hash).await?;
+
+    //所有旧会话下线
+    sign_out_everywhere(state, user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//生成一次性重置令牌(库里只存摘要)，把带令牌的链接发到用户邮箱
+//邮件放到后台发送：发信很慢
+pub(crate) async fn send_reset_link(
+    state: &AppState,
+    user: &UserRow,
+    reason: &str,
+) -> anyhow::Result<()> {
+    let token = auth::generate_opaque_token();
+    let expires_at = Utc::now() + Duration::seconds(state.password_reset_expire_seconds);
+    password_reset_service::create_reset_token(
+        &state.db,
+        user.id,
+        &auth::hash_opaque_token(&token),
+        expires_at,
+    )
+    .await?;
+
+    let mail = Mail {
+        to: user.email.clone(),
+        subject: "重置密码".to_string(),
+        body: format!(
+            "{}，你好：\n\n{}，请在{}分钟内打开下面的链接设置新密码：\n{}/forget?token={}\n\n如果不是你本人操作，请忽略这封邮件。",
+            user.username,
+            reason,
+            state.password_reset_expire_seconds / 60,
+            state.app_base_url,
+            token
+        ),
+    };
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(mail).await {
+            eprintln!("发送重置密码邮件失败:{e}");
+        }
+    });
+
+    Ok(())
+}
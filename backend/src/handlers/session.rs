@@ -0,0 +1,78 @@
+//登录会话管理：查看自己在哪些设备上登录过、把某个设备踢下线
+use chrono::{DateTime, Utc};
+use salvo::prelude::*;
+use serde::Serialize;
+
+use super::auth::{end_other_sessions, end_session};
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::middleware::auth::current_user;
+use crate::services::session_service;
+use crate::state::AppState;
+
+//会话列表里的一项
+#[derive(Serialize)]
+pub struct SessionResp {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub user_agent: String,
+    pub ip: String,
+    //是不是发起这次请求的会话
+    pub current: bool,
+}
+
+//会话列表:GET /api/auth/sessions
+#[handler]
+pub async fn list_sessions(depot: &Depot) -> AppResult<Json<Vec<SessionResp>>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let current = current_user(depot);
+
+    let rows = session_service::list_sessions_by_user(&state.db, current.user.id).await?;
+
+    let sessions: Vec<SessionResp> = rows
+        .into_iter()
+        .map(|row| SessionResp {
+            current: row.id == current.session_id(),
+            id: row.id,
+            created_at: row.created_at,
+            last_seen_at: row.last_seen_at,
+            user_agent: row.user_agent,
+            ip: row.ip,
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+//下线某个会话:DELETE /api/auth/sessions/{id}
+//会话删除后，它的access token在下一次请求时就会被拒绝，refresh token也不能再用
+#[handler]
+pub async fn delete_session(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user_id = current_user(depot).user.id;
+
+    let id = req
+        .param::<String>("id")
+        .ok_or(AppError::Validation(ErrorCode::InvalidParameter))?;
+
+    if !end_session(state, user_id, &id).await? {
+        return Err(AppError::NotFound(ErrorCode::SessionNotFound));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//下线除当前会话以外的所有会话:POST /api/auth/sessions/sign-out-others
+#[handler]
+pub async fn sign_out_others(depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let current = current_user(depot);
+    let (user_id, sid) = (current.user.id, current.session_id());
+
+    end_other_sessions(state, user_id, sid).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
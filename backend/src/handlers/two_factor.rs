@@ -0,0 +1,222 @@
+//两步验证(TOTP)：开启、确认、关闭，以及登录时的第二步
+use chrono::{DateTime, Utc};
+use salvo::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::auth::{
+    TokenResp, check_user_password, ensure_not_locked, issue_token_pair, record_login_failure,
+    reset_login_failures,
+};
+use super::parse_json;
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::middleware::auth::current_user;
+use crate::services::user_service::UserRow;
+use crate::services::{recovery_code_service, revocation_service, user_service};
+use crate::state::AppState;
+use crate::utils::{auth, client, login_throttle, totp};
+
+//一次开启生成多少个恢复码
+const RECOVERY_CODE_COUNT: usize = 10;
+//同一个中间token最多能试几次验证码，超过就只能重新登录
+const MAX_MFA_ATTEMPTS: u32 = 5;
+
+//开启第一步的返回：密钥(手动输入用)+otpauth链接(生成二维码用)
+#[derive(Serialize)]
+pub struct TotpSetupResp {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+//提交一个验证码(TOTP或者恢复码)
+#[derive(Deserialize)]
+pub struct TotpCodeReq {
+    pub code: String,
+}
+
+//开启成功：返回恢复码，只展示这一次
+#[derive(Serialize)]
+pub struct RecoveryCodesResp {
+    pub recovery_codes: Vec<String>,
+}
+
+//关闭两步验证：需要密码+验证码
+#[derive(Deserialize)]
+pub struct DisableTotpReq {
+    pub password: String,
+    pub code: String,
+}
+
+//登录第二步：登录接口返回的mfa_token+验证码
+#[derive(Deserialize)]
+pub struct Login2faReq {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+//开启第一步:POST /api/auth/2fa/setup
+//生成新密钥先存起来(还没生效)，返回给前端展示二维码
+#[handler]
+pub async fn setup_totp(depot: &Depot) -> AppResult<Json<TotpSetupResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = &current_user(depot).user;
+    if user.totp_enabled_at.is_some() {
+        return Err(AppError::Conflict(ErrorCode::TotpAlreadyEnabled));
+    }
+
+    let secret = totp::generate_secret();
+    if !user_service::set_pending_totp_secret(&state.db, user.id, &secret).await? {
+        return Err(AppError::Conflict(ErrorCode::TotpAlreadyEnabled));
+    }
+
+    let otpauth_uri = totp::provisioning_uri(&state.totp_issuer, &user.username, &secret);
+    Ok(Json(TotpSetupResp {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+//开启第二步:POST /api/auth/2fa/confirm
+//用验证器App里的第一个验证码确认，通过后正式开启，并生成恢复码
+#[handler]
+pub async fn confirm_totp(req: &mut Request, depot: &Depot) -> AppResult<Json<RecoveryCodesResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = &current_user(depot).user;
+
+    let body: TotpCodeReq = parse_json(req).await?;
+
+    if user.totp_enabled_at.is_some() {
+        return Err(AppError::Conflict(ErrorCode::TotpAlreadyEnabled));
+    }
+    let Some(secret) = user.totp_secret.as_deref() else {
+        return Err(AppError::Validation(ErrorCode::TotpSetupRequired));
+    };
+    let Some(step) = totp::verify_code(secret, &body.code, Utc::now().timestamp()) else {
+        return Err(AppError::Validation(ErrorCode::MfaCodeInvalid));
+    };
+
+    if !user_service::enable_totp(&state.db, user.id, step).await? {
+        return Err(AppError::Conflict(ErrorCode::TotpAlreadyEnabled));
+    }
+
+    //恢复码和密码一样用argon2哈希保存
+    let recovery_codes = totp::generate_recovery_codes(RECOVERY_CODE_COUNT);
+    let code_hashes = recovery_codes
+        .iter()
+        .map(|c| auth::hash_password(&state.argon2, &totp::normalize_recovery_code(c)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    recovery_code_service::replace_recovery_codes(&state.db, user.id, &code_hashes).await?;
+
+    Ok(Json(RecoveryCodesResp { recovery_codes }))
+}
+
+//关闭:POST /api/auth/2fa/disable
+//需要当前密码+一个验证码(TOTP或者恢复码)，防止token被偷之后直接关掉
+#[handler]
+pub async fn disable_totp(req: &mut Request, depot: &Depot) -> AppResult<StatusCode> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let user = &current_user(depot).user;
+
+    let body: DisableTotpReq = parse_json(req).await?;
+
+    if user.totp_enabled_at.is_none() {
+        return Err(AppError::Validation(ErrorCode::TotpNotEnabled));
+    }
+
+    if !check_user_password(state, user, &body.password).await? {
+        return Err(AppError::Validation(ErrorCode::PasswordIncorrect));
+    }
+
+    if !verify_second_factor(state, user, &body.code).await? {
+        return Err(AppError::Validation(ErrorCode::MfaCodeInvalid));
+    }
+
+    user_service::disable_totp(&state.db, user.id).await?;
+    recovery_code_service::delete_recovery_codes(&state.db, user.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//登录第二步:POST /api/auth/login/2fa
+//流程：
+// 1.校验中间token(签名+exp+类型+没用过)
+// 2.校验验证码：TOTP或者恢复码
+// 3.中间token作废(只能用一次)，签发真正的token
+#[handler]
+pub async fn login_2fa(req: &mut Request, depot: &Depot) -> AppResult<Json<TokenResp>> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let body: Login2faReq = parse_json(req).await?;
+
+    let claims = auth::verify_mfa_challenge(&state.jwt_keys, body.mfa_token.trim())
+        .map_err(|_| AppError::Unauthorized(ErrorCode::MfaChallengeExpired))?;
+    if state.revocation_store.is_revoked(&claims)
+        || state.mfa_attempts.get(&claims.jti) >= MAX_MFA_ATTEMPTS
+    {
+        return Err(AppError::Unauthorized(ErrorCode::MfaChallengeExpired));
+    }
+
+    let user = user_service::find_user_by_id(&state.db, claims.sub)
+        .await?
+        .filter(|u| u.totp_enabled_at.is_some())
+        .ok_or(AppError::Unauthorized(ErrorCode::MfaChallengeExpired))?;
+    if user.disabled_at.is_some() {
+        return Err(AppError::Forbidden(ErrorCode::AccountDisabled));
+    }
+
+    //和密码登录共用失败计数：换着中间token猜验证码一样会被锁
+    let account_key = login_throttle::user_key(user.id);
+    let ip_key = login_throttle::ip_key(&client::client_ip(req, state.trusted_proxy_hops));
+    ensure_not_locked(state, &[&account_key, &ip_key]).await?;
+
+    let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_else(Utc::now);
+    if !verify_second_factor(state, &user, &body.code).await? {
+        record_login_failure(state, &account_key, &ip_key).await?;
+        //失败次数到上限后中间token直接作废
+        if state.mfa_attempts.increment(&claims.jti, expires_at) >= MAX_MFA_ATTEMPTS {
+            return Err(AppError::Unauthorized(ErrorCode::MfaTooManyAttempts));
+        }
+        return Err(AppError::Validation(ErrorCode::MfaCodeInvalid));
+    }
+
+    //中间token只能用一次
+    revocation_service::revoke_token(&state.db, &claims.jti, claims.sub, expires_at).await?;
+    state.mfa_attempts.remove(&claims.jti);
+    state.revocation_store.insert(claims.jti, expires_at);
+
+    reset_login_failures(state, &[&account_key, &ip_key]).await?;
+
+    let tokens = issue_token_pair(state, user.id, req).await?;
+
+    Ok(Json(tokens))
+}
+
+//校验第二因素：先当作TOTP验证码，不是的话再当作恢复码
+//TOTP同一个时间步只能用一次，恢复码用过就作废
+async fn verify_second_factor(
+    state: &AppState,
+    user: &UserRow,
+    code: &str,
+) -> anyhow::Result<bool> {
+    if let Some(secret) = user.totp_secret.as_deref()
+        && let Some(step) = totp::verify_code(secret, code, Utc::now().timestamp())
+    {
+        return user_service::consume_totp_step(&state.db, user.id, step).await;
+    }
+
+    let normalized = totp::normalize_recovery_code(code);
+    if normalized.len() != 10 {
+        return Ok(false);
+    }
+    for (id, code_hash) in
+        recovery_code_service::list_unused_recovery_codes(&state.db, user.id).await?
+    {
+        if auth::verify_password(&state.argon2, &normalized, &code_hash)? {
+            return recovery_code_service::mark_recovery_code_used(&state.db, id).await;
+        }
+    }
+
+    Ok(false)
+}
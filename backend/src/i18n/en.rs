@@ -0,0 +1,77 @@
+//English
+use crate::error::ErrorCode;
+
+pub fn error_message(code: ErrorCode) -> &'static str {
+    match code {
+        ErrorCode::InvalidJson => "Request body is not valid JSON",
+        ErrorCode::InvalidParameter => "Invalid parameter",
+        ErrorCode::ValidationFailed => "Some fields are invalid",
+        ErrorCode::Internal => "Internal server error",
+        ErrorCode::TooManyRequests => "Too many requests, please try again later",
+        ErrorCode::CaptchaInvalid => "Captcha is incorrect or has expired",
+        ErrorCode::PowInvalid => "Proof-of-work challenge is invalid or has expired",
+        ErrorCode::UsernameLength => "Username must be 3-32 characters long",
+        ErrorCode::UsernameCharset => {
+            "Username may only contain letters, digits and _-. and must start with a letter or digit"
+        }
+        ErrorCode::EmailInvalid => "Invalid email address",
+        ErrorCode::PasswordTooShort => "Password is too short",
+        ErrorCode::PasswordTooLong => "Password is too long",
+        ErrorCode::PasswordCharClasses => {
+            "Password needs more kinds of characters (lowercase, uppercase, digits, symbols)"
+        }
+        ErrorCode::PasswordTooWeak => "Password is too easy to guess, please choose a stronger one",
+        ErrorCode::PasswordContainsPersonalInfo => {
+            "Password must not contain your username or email"
+        }
+        ErrorCode::PasswordBreached => {
+            "This password has appeared in a data breach, please choose another one"
+        }
+        ErrorCode::PasswordRequired => "Password is required",
+        ErrorCode::AccountRequired => "Account is required",
+        ErrorCode::InvalidCredentials => "Incorrect account or password",
+        ErrorCode::PasswordIncorrect => "Incorrect password",
+        ErrorCode::UserExists => "Username or email already exists",
+        ErrorCode::TooManyFailures => "Too many failed attempts, please try again later",
+        ErrorCode::AccountDisabled => "This account has been disabled",
+        ErrorCode::TokenMissing => "Missing token",
+        ErrorCode::TokenInvalid => "Token is invalid or has expired",
+        ErrorCode::TokenRevoked => "Token is no longer valid, please sign in again",
+        ErrorCode::SessionExpired => "Your session has expired, please sign in again",
+        ErrorCode::SessionNotFound => "Session not found",
+        ErrorCode::RefreshTokenInvalid => "Invalid refresh token",
+        ErrorCode::RefreshTokenExpired => "Refresh token has expired",
+        ErrorCode::RefreshTokenRevoked => "Refresh token is no longer valid, please sign in again",
+        ErrorCode::RefreshTokenReused => {
+            "Refresh token has already been used, please sign in again"
+        }
+        ErrorCode::ResetTokenInvalid => "Reset link is invalid or has expired",
+        ErrorCode::VerifyTokenInvalid => "Verification link is invalid or has expired",
+        ErrorCode::EmailAlreadyVerified => "Email is already verified",
+        ErrorCode::EmailNotVerified => "Please verify your email first",
+        ErrorCode::EmailSendTooFrequent => {
+            "Emails are being sent too often, please try again later"
+        }
+        ErrorCode::EmailUnchanged => "The new email is the same as the current one",
+        ErrorCode::EmailTaken => "This email is already in use",
+        ErrorCode::EmailChangeTokenInvalid => "Confirmation link is invalid or has expired",
+        ErrorCode::TotpAlreadyEnabled => "Two-factor authentication is already enabled",
+        ErrorCode::TotpNotEnabled => "Two-factor authentication is not enabled",
+        ErrorCode::TotpSetupRequired => "Please request a two-factor secret first",
+        ErrorCode::MfaCodeInvalid => "Incorrect verification code",
+        ErrorCode::MfaChallengeExpired => "Two-factor verification timed out, please sign in again",
+        ErrorCode::MfaTooManyAttempts => "Too many incorrect codes, please sign in again",
+        ErrorCode::PasskeyNotAvailable => "No passkey is available for this account",
+        ErrorCode::PasskeyChallengeInvalid => "Passkey request is invalid or has expired",
+        ErrorCode::PasskeyVerifyFailed => "Passkey verification failed",
+        ErrorCode::PasskeyAlreadyRegistered => "This passkey is already registered",
+        ErrorCode::PasskeyRemoved => "This passkey has been removed",
+        ErrorCode::PermissionDenied => "Permission denied",
+        ErrorCode::UserNotFound => "User not found",
+        ErrorCode::UserDeleted => "User has been deleted",
+        ErrorCode::RoleNotFound => "Role not found",
+        ErrorCode::RoleNotAssigned => "User does not have this role",
+        ErrorCode::LastAdmin => "At least one administrator must remain",
+        ErrorCode::CannotModifySelf => "You cannot perform this action on yourself",
+    }
+}
@@ -0,0 +1,80 @@
+//多语言：错误提示按语言查对应的文案
+//语言的选择顺序：用户在个人设置里选的语言(users.locale) > 请求头Accept-Language > 默认简体中文
+mod en;
+mod zh_cn;
+
+use salvo::http::header::ACCEPT_LANGUAGE;
+use salvo::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorCode;
+
+//支持的语言；加新语言时在这里加一项，再加一个同名的文案文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en")]
+    En,
+}
+
+impl Locale {
+    //语言标签，比如"zh-CN"，也是存进users.locale的值
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::En => "en",
+        }
+    }
+
+    //解析一个语言标签：只看主语言部分，大小写不敏感
+    //"zh"/"zh-CN"/"zh-Hans-CN"都算简体中文，"en"/"en-US"都算英文
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        match primary.as_str() {
+            "zh" => Some(Locale::ZhCn),
+            "en" => Some(Locale::En),
+            _ => None,
+        }
+    }
+
+    //按Accept-Language选语言，比如"en-US,en;q=0.9,zh-CN;q=0.8"
+    //按q值从高到低找第一个支持的语言，q相同时保持原来的顺序；都不支持返回None
+    pub fn negotiate(accept_language: &str) -> Option<Self> {
+        let mut candidates = accept_language
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let tag = parts.next()?.trim();
+                let q = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (!tag.is_empty() && q > 0.0).then_some((tag, q))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        candidates
+            .into_iter()
+            .find_map(|(tag, _)| Self::from_tag(tag))
+    }
+
+    //从请求头里选语言，没带或者都不支持时用默认语言
+    pub fn from_request(req: &Request) -> Self {
+        req.headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::negotiate)
+            .unwrap_or_default()
+    }
+
+    //错误码对应的提示文案
+    pub fn error_message(self, code: ErrorCode) -> &'static str {
+        match self {
+            Locale::ZhCn => zh_cn::error_message(code),
+            Locale::En => en::error_message(code),
+        }
+    }
+}
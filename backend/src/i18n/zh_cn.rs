@@ -0,0 +1,65 @@
+//简体中文
+use crate::error::ErrorCode;
+
+pub fn error_message(code: ErrorCode) -> &'static str {
+    match code {
+        ErrorCode::InvalidJson => "请求体不是合法JSON",
+        ErrorCode::InvalidParameter => "参数错误",
+        ErrorCode::ValidationFailed => "请求参数有误",
+        ErrorCode::Internal => "服务器内部错误",
+        ErrorCode::TooManyRequests => "请求太频繁，请稍后再试",
+        ErrorCode::CaptchaInvalid => "验证码错误或已经过期",
+        ErrorCode::PowInvalid => "人机验证没有通过或已经过期，请重新获取",
+        ErrorCode::UsernameLength => "用户名长度需要在3-32之间",
+        ErrorCode::UsernameCharset => "用户名只能包含字母、数字和_-.，并以字母或数字开头",
+        ErrorCode::EmailInvalid => "邮箱格式错误",
+        ErrorCode::PasswordTooShort => "密码太短",
+        ErrorCode::PasswordTooLong => "密码太长",
+        ErrorCode::PasswordCharClasses => "密码需要包含更多种类的字符(大小写字母、数字、符号)",
+        ErrorCode::PasswordTooWeak => "密码太容易被猜到，请换一个更复杂的密码",
+        ErrorCode::PasswordContainsPersonalInfo => "密码不能包含用户名或邮箱",
+        ErrorCode::PasswordBreached => "这个密码出现在已泄露的密码列表里，请换一个",
+        ErrorCode::PasswordRequired => "密码不能为空",
+        ErrorCode::AccountRequired => "账号不能为空",
+        ErrorCode::InvalidCredentials => "账号或密码错误",
+        ErrorCode::PasswordIncorrect => "密码错误",
+        ErrorCode::UserExists => "用户名或邮箱已存在",
+        ErrorCode::TooManyFailures => "失败次数过多，请稍后再试",
+        ErrorCode::AccountDisabled => "账号已被禁用",
+        ErrorCode::TokenMissing => "缺少token",
+        ErrorCode::TokenInvalid => "token无效或已经过期",
+        ErrorCode::TokenRevoked => "token已失效，请重新登录",
+        ErrorCode::SessionExpired => "登录已失效，请重新登录",
+        ErrorCode::SessionNotFound => "会话不存在",
+        ErrorCode::RefreshTokenInvalid => "refresh token无效",
+        ErrorCode::RefreshTokenExpired => "refresh token已经过期",
+        ErrorCode::RefreshTokenRevoked => "refresh token已失效，请重新登录",
+        ErrorCode::RefreshTokenReused => "refresh token已被使用，请重新登录",
+        ErrorCode::ResetTokenInvalid => "重置链接无效或已经过期",
+        ErrorCode::VerifyTokenInvalid => "验证链接无效或已经过期",
+        ErrorCode::EmailAlreadyVerified => "邮箱已经验证过了",
+        ErrorCode::EmailNotVerified => "请先验证邮箱",
+        ErrorCode::EmailSendTooFrequent => "发送太频繁，请稍后再试",
+        ErrorCode::EmailUnchanged => "新邮箱和现在的邮箱一样",
+        ErrorCode::EmailTaken => "这个邮箱已经被使用了",
+        ErrorCode::EmailChangeTokenInvalid => "确认链接无效或已经过期",
+        ErrorCode::TotpAlreadyEnabled => "两步验证已经开启",
+        ErrorCode::TotpNotEnabled => "两步验证未开启",
+        ErrorCode::TotpSetupRequired => "请先获取两步验证密钥",
+        ErrorCode::MfaCodeInvalid => "验证码错误",
+        ErrorCode::MfaChallengeExpired => "两步验证已超时，请重新登录",
+        ErrorCode::MfaTooManyAttempts => "验证码错误次数过多，请重新登录",
+        ErrorCode::PasskeyNotAvailable => "该账号没有可用的通行密钥",
+        ErrorCode::PasskeyChallengeInvalid => "通行密钥请求无效或已经过期",
+        ErrorCode::PasskeyVerifyFailed => "通行密钥校验失败",
+        ErrorCode::PasskeyAlreadyRegistered => "这个通行密钥已经注册过了",
+        ErrorCode::PasskeyRemoved => "通行密钥已被删除",
+        ErrorCode::PermissionDenied => "没有权限",
+        ErrorCode::UserNotFound => "用户不存在",
+        ErrorCode::UserDeleted => "用户已删除",
+        ErrorCode::RoleNotFound => "角色不存在",
+        ErrorCode::RoleNotAssigned => "用户没有这个角色",
+        ErrorCode::LastAdmin => "至少要保留一个管理员",
+        ErrorCode::CannotModifySelf => "不能对自己执行这个操作",
+    }
+}
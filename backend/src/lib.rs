@@ -0,0 +1,12 @@
+//后端库：main.rs只负责读配置、建连接池和启动服务，路由和全局状态都在这里组装
+//拆成库是为了tests/下的集成测试可以直接拿到同一套路由和AppState
+pub mod app;
+pub mod config;
+pub mod error;
+pub mod handlers;
+pub mod i18n;
+pub mod middleware;
+pub mod services;
+pub mod state;
+pub mod utils;
+pub mod validation;
@@ -1,18 +1,8 @@
-//创建路由，注入全局状态
-mod config;
-mod handlers;
-mod services;
-mod state;
-mod utils;
-
-use std::sync::Arc;
-
-use salvo::affix_state;
+//读取配置、建立连接池，然后启动服务
+use backend::app::{self, Limiters};
+use backend::config::{database::create_mysql_pool, settings::Settings};
+use backend::services::role_service;
 use salvo::prelude::*;
-use tokio::time::{Duration as TokioDuration, sleep};
-
-use config::{database::create_mysql_pool, settings::Settings};
-use state::{AppState, CaptchaStore};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -20,42 +10,18 @@ async fn main() -> anyhow::Result<()> {
     let settings = Settings::from_env()?;
     //建立数据库连接池
     let db = create_mysql_pool(&settings.database_url).await?;
-    //创建验证码存储（存在进程内存)
-    let captcha_store = Arc::new(CaptchaStore::default());
-
-    //组装全局状态，注入到salvo的Depot里
-    let state = AppState {
-        db,
-        captcha_store: captcha_store.clone(),
-        debug_captcha: settings.debug_captcha,
-
-        //把jwt从settings注入到全局状态
-        jwt_secret: settings.jwt_secret.clone(),
-        jwt_expire_seconds: settings.jwt_expire_seconds,
-    };
-
-    //每60s清理一次过期验证码
-    tokio::spawn(async move {
-        loop {
-            sleep(TokioDuration::from_secs(60)).await;
-            captcha_store.cleanup_expired();
+    //还没有管理员时，把ADMIN_BOOTSTRAP_ACCOUNT指定的账号设为管理员
+    if let Some(account) = &settings.admin_bootstrap_account {
+        match role_service::bootstrap_admin(&db, account).await? {
+            Some(user_id) => println!("已将用户{user_id}({account})设为管理员"),
+            None => println!("跳过管理员初始化：已经有管理员，或者账号{account}不存在"),
         }
-    });
+    }
 
-    //路由
-    let router = Router::new()
-        //健康检测
-        .push(Router::with_path("health").get(handlers::health::health))
-        //验证码
-        .push(Router::with_path("api/captcha").get(handlers::captcha::get_captcha))
-        //验证码校验
-        .push(Router::with_path("api/captcha/verify").post(handlers::captcha::verify_captcha))
-        //接口路径对齐前端
-        .push(Router::with_path("api/auth/register").post(handlers::auth::register))
-        .push(Router::with_path("api/auth/login").post(handlers::auth::login))
-        .push(Router::with_path("api/auth/me").get(handlers::auth::me))
-        //注入全局状态：让全部的handler都能拿到Appstate
-        .hoop(affix_state::inject(state));
+    let state = app::build_state(&settings, db).await?;
+    let limiters = Limiters::new(&settings);
+    app::spawn_background_tasks(&state, &limiters);
+    let router = app::router(state, &limiters);
 
     //启动服务
     let addr = format!("{}:{}", settings.server_host, settings.server_port);
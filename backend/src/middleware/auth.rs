@@ -0,0 +1,122 @@
+//登录校验hoop：挂在需要登录的路由组上
+//校验access token(签名+exp+吊销+会话)，查出当前用户放进Depot，handler里用current_user(depot)取
+//需要特定角色的路由再加一层require_role
+use chrono::{Duration, Utc};
+use salvo::prelude::*;
+
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::handlers::auth::parse_bearer_token;
+use crate::services::user_service::UserRow;
+use crate::services::{session_service, user_service};
+use crate::state::AppState;
+use crate::utils::auth::{self, Claims};
+use crate::utils::client;
+
+//会话最近活跃时间的更新间隔(秒)
+const SESSION_TOUCH_INTERVAL_SECONDS: i64 = 60;
+
+//当前登录的用户+这次请求带的token内容
+pub struct CurrentUser {
+    pub user: UserRow,
+    pub claims: Claims,
+}
+
+impl CurrentUser {
+    //当前会话id：通过require_auth的token一定带着sid
+    pub fn session_id(&self) -> &str {
+        self.claims.sid.as_deref().unwrap_or_default()
+    }
+}
+
+//hoop：没登录/token无效统一返回401，后面的handler不会执行
+#[handler]
+pub async fn require_auth(req: &mut Request, depot: &mut Depot) -> AppResult<()> {
+    let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+    let current = authenticate(state, req).await?;
+
+    depot.inject(current);
+    Ok(())
+}
+
+//在require_auth后面的handler里取当前用户
+pub fn current_user(depot: &Depot) -> &CurrentUser {
+    depot
+        .obtain::<CurrentUser>()
+        .expect("CurrentUser未注入，路由上没有挂require_auth")
+}
+
+//hoop：要求当前用户有某个角色，挂在require_auth后面
+//比如 Router::new().hoop(require_auth).hoop(require_role("admin"))
+pub fn require_role(role: &'static str) -> RequireRole {
+    RequireRole { role }
+}
+
+pub struct RequireRole {
+    role: &'static str,
+}
+
+#[async_trait]
+impl Handler for RequireRole {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        //按token里的角色判断：角色变更在下一次刷新token时生效
+        if !current_user(depot)
+            .claims
+            .roles
+            .iter()
+            .any(|r| r == self.role)
+        {
+            AppError::Forbidden(ErrorCode::PermissionDenied)
+                .write(req, depot, res)
+                .await;
+            ctrl.skip_rest();
+        }
+    }
+}
+
+//校验请求里的access token
+//解析Authorization header(大概长这样：Authorization: Bearer xxxxx.yyyyy.zzzzz)
+//验证签名+exp，查吊销列表，确认所属的会话还在，最后查出用户
+async fn authenticate(state: &AppState, req: &Request) -> AppResult<CurrentUser> {
+    let token = parse_bearer_token(req).ok_or(AppError::Unauthorized(ErrorCode::TokenMissing))?;
+
+    let claims = auth::verify_jwt(&state.jwt_keys, &token)
+        .map_err(|_| AppError::Unauthorized(ErrorCode::TokenInvalid))?;
+
+    if state.revocation_store.is_revoked(&claims) {
+        return Err(AppError::Unauthorized(ErrorCode::TokenRevoked));
+    }
+
+    //会话被删除(登出/远程下线)后，这个会话签发的token立即失效
+    let sid = claims
+        .sid
+        .as_deref()
+        .ok_or(AppError::Unauthorized(ErrorCode::TokenInvalid))?;
+    let session = session_service::find_session(&state.db, sid)
+        .await?
+        .filter(|s| s.user_id == claims.sub)
+        .ok_or(AppError::Unauthorized(ErrorCode::SessionExpired))?;
+
+    //最近活跃时间不需要每次请求都写库，超过间隔才更新
+    if Utc::now() - session.last_seen_at > Duration::seconds(SESSION_TOUCH_INTERVAL_SECONDS) {
+        let ip = client::client_ip(req, state.trusted_proxy_hops);
+        if let Err(e) = session_service::touch_session(&state.db, sid, &ip).await {
+            eprintln!("更新会话活跃时间失败:{e}");
+        }
+    }
+
+    let user = user_service::find_user_by_id(&state.db, claims.sub)
+        .await?
+        .ok_or(AppError::Unauthorized(ErrorCode::UserNotFound))?;
+    if user.disabled_at.is_some() {
+        return Err(AppError::Forbidden(ErrorCode::AccountDisabled));
+    }
+
+    Ok(CurrentUser { user, claims })
+}
@@ -0,0 +1,194 @@
+//接口限流：令牌桶
+//每个key一个桶，桶里最多requests个令牌，按requests/period_seconds的速度匀速补充，每个请求消耗一个
+//每个请求都按IP算；登录了的再按用户id算一次，两个桶都要有令牌才放行
+//同一个key在不同路由组(scope)里各算各的
+use std::sync::Arc;
+use std::time::Instant;
+
+use dashmap::DashMap;
+use salvo::http::header::{HeaderName, HeaderValue};
+use salvo::prelude::*;
+
+use crate::config::settings::RateQuota;
+use crate::error::{AppError, ErrorCode};
+use crate::handlers::auth::parse_bearer_token;
+use crate::state::AppState;
+use crate::utils::{auth, client};
+
+//IETF RateLimit header草案里的三个header
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+//一个桶：剩余令牌数+上次补充的时间
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+//一次取令牌的结果
+struct Decision {
+    allowed: bool,
+    remaining: u32,
+    //多少秒后桶会补满
+    reset_seconds: u64,
+    //被拒绝时多少秒后能拿到下一个令牌
+    retry_after_seconds: u64,
+}
+
+//限流hoop：挂在路由组上，clone出来的副本共享同一组桶
+#[derive(Clone)]
+pub struct RateLimiter {
+    scope: &'static str,
+    quota: RateQuota,
+    buckets: Arc<DashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(scope: &'static str, quota: RateQuota) -> Self {
+        Self {
+            scope,
+            quota,
+            buckets: Arc::new(DashMap::new()),
+        }
+    }
+
+    //每秒补充多少个令牌
+    fn refill_rate(&self) -> f64 {
+        self.quota.requests as f64 / self.quota.period_seconds.max(1) as f64
+    }
+
+    //先按经过的时间补令牌，再尝试取一个
+    fn acquire(&self, key: &str) -> Decision {
+        let capacity = self.quota.requests as f64;
+        let rate = self.refill_rate();
+        let now = Instant::now();
+
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated_at = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let seconds_until = |tokens: f64| {
+            if rate > 0.0 {
+                ((tokens - bucket.tokens).max(0.0) / rate).ceil() as u64
+            } else {
+                self.quota.period_seconds
+            }
+        };
+        Decision {
+            allowed,
+            remaining: bucket.tokens.floor() as u32,
+            reset_seconds: seconds_until(capacity),
+            retry_after_seconds: seconds_until(1.0).max(1),
+        }
+    }
+
+    //清理已经补满的桶：补满的桶和新建的没有区别，不用留着
+    pub fn cleanup_expired(&self) {
+        let capacity = self.quota.requests as f64;
+        let rate = self.refill_rate();
+        let now = Instant::now();
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+            bucket.tokens + elapsed * rate < capacity
+        });
+    }
+}
+
+//限流的key：客户端IP，带着合法access token的再加上用户id
+//只按用户id算的话，换着账号就能绕过；只按IP算的话，同一个出口IP后面的用户会互相影响
+//这里只验签不查库，吊销/会话检查留给后面的handler
+fn client_keys(state: &AppState, req: &Request) -> Vec<String> {
+    let mut keys = vec![format!(
+        "ip:{}",
+        client::client_ip(req, state.trusted_proxy_hops)
+    )];
+    if let Some(token) = parse_bearer_token(req)
+        && let Ok(claims) = auth::verify_jwt(&state.jwt_keys, &token)
+    {
+        keys.push(format!("user:{}", claims.sub));
+    }
+    keys
+}
+
+#[async_trait]
+impl Handler for RateLimiter {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let state = depot.obtain::<AppState>().expect("AppState未注入");
+
+        //依次取令牌，有一个桶被拒绝就停下，后面的桶不再扣
+        let mut decisions = Vec::new();
+        for key in client_keys(state, req) {
+            let decision = self.acquire(&format!("{}:{key}", self.scope));
+            let allowed = decision.allowed;
+            decisions.push(decision);
+            if !allowed {
+                break;
+            }
+        }
+        //header里报告最紧的那个桶：被拒绝的优先，其次剩余最少的
+        let decision = decisions
+            .into_iter()
+            .min_by_key(|d| (d.allowed, d.remaining))
+            .expect("至少有一个IP的key");
+
+        let headers = res.headers_mut();
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.quota.requests));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(decision.reset_seconds));
+
+        if !decision.allowed {
+            AppError::RateLimited {
+                code: ErrorCode::TooManyRequests,
+                retry_after: decision.retry_after_seconds as i64,
+            }
+            .write(req, depot, res)
+            .await;
+            ctrl.skip_rest();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests: u32, period_seconds: u64) -> RateLimiter {
+        RateLimiter::new(
+            "test",
+            RateQuota {
+                requests,
+                period_seconds,
+            },
+        )
+    }
+
+    #[test]
+    fn cleanup_drops_only_full_buckets() {
+        let limiter = limiter(2, 60);
+        limiter.acquire("test:ip:a");
+        limiter.cleanup_expired();
+        assert_eq!(limiter.buckets.len(), 1);
+
+        let fast = self::limiter(1_000_000, 1);
+        fast.acquire("test:ip:a");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        fast.cleanup_expired();
+        assert!(fast.buckets.is_empty());
+    }
+}
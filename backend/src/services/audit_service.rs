@@ -0,0 +1,37 @@
+use chrono::Utc;
+use sqlx::{MySql, Pool};
+
+//审计日志里的操作类型
+pub const PASSWORD_CHANGED: &str = "password_changed";
+pub const EMAIL_CHANGE_REQUESTED: &str = "email_change_requested";
+pub const EMAIL_CHANGED: &str = "email_changed";
+
+//detail最多保存多少个字符(和表结构一致)
+const MAX_DETAIL_CHARS: usize = 512;
+
+//记一条审计日志
+//detail是给人看的补充说明，比如改邮箱时的新旧地址
+pub async fn record(
+    db: &Pool<MySql>,
+    user_id: i64,
+    action: &str,
+    detail: &str,
+    ip: &str,
+    user_agent: &str,
+) -> anyhow::Result<()> {
+    let detail = detail.chars().take(MAX_DETAIL_CHARS).collect::<String>();
+    sqlx::query(
+        r#"INSERT INTO audit_logs (user_id, action, detail, ip, user_agent, created_at)
+           VALUES (?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(user_id)
+    .bind(action)
+    .bind(detail)
+    .bind(ip)
+    .bind(user_agent)
+    .bind(Utc::now())
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
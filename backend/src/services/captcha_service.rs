@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use sqlx::{MySql, Pool};
+
+//保存一条验证码答案
+pub async fn insert_captcha(
+    db: &Pool<MySql>,
+    id: &str,
+    answer: &str,
+    ip: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    sqlx::query(r#"INSERT INTO captchas (id, answer, ip, expires_at) VALUES (?, ?, ?, ?)"#)
+        .bind(id)
+        .bind(answer)
+        .bind(ip)
+        .bind(expires_at)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//校验并使用验证码，答案对返回true
+//答案对(并且还没猜错到上限)：删掉这一行
+//答案错：猜错次数+1，达到max_attempts就删掉
+//比较和删除在同一条DELETE里完成，同一个验证码并发提交也只有一个能成功
+pub async fn consume_captcha(
+    db: &Pool<MySql>,
+    id: &str,
+    answer: &str,
+    max_attempts: u32,
+) -> anyhow::Result<bool> {
+    let now = Utc::now();
+    let result = sqlx::query(
+        r#"DELETE FROM captchas
+           WHERE id = ? AND answer = ? AND expires_at >= ? AND attempts < ?"#,
+    )
+    .bind(id)
+    .bind(answer)
+    .bind(now)
+    .bind(max_attempts)
+    .execute(db)
+    .await?;
+
+    if result.rows_affected() == 1 {
+        return Ok(true);
+    }
+
+    sqlx::query(r#"UPDATE captchas SET attempts = attempts + 1 WHERE id = ?"#)
+        .bind(id)
+        .execute(db)
+        .await?;
+    sqlx::query(r#"DELETE FROM captchas WHERE id = ? AND (attempts >= ? OR expires_at < ?)"#)
+        .bind(id)
+        .bind(max_attempts)
+        .bind(now)
+        .execute(db)
+        .await?;
+
+    Ok(false)
+}
+
+//这个IP名下最多留keep个验证码，多出来的按过期时间从早到晚删掉
+pub async fn trim_captchas_by_ip(db: &Pool<MySql>, ip: &str, keep: usize) -> anyhow::Result<u64> {
+    let count: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM captchas WHERE ip = ?"#)
+        .bind(ip)
+        .fetch_one(db)
+        .await?;
+    let excess = count - keep as i64;
+    if excess <= 0 {
+        return Ok(0);
+    }
+
+    let result = sqlx::query(
+        r#"DELETE FROM captchas
+           WHERE ip = ?
+           ORDER BY expires_at
+           LIMIT ?"#,
+    )
+    .bind(ip)
+    .bind(excess)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//整张表最多留keep个验证码，多出来的按过期时间从早到晚删掉
+pub async fn trim_captchas(db: &Pool<MySql>, keep: usize) -> anyhow::Result<u64> {
+    let count: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM captchas"#)
+        .fetch_one(db)
+        .await?;
+    let excess = count - keep as i64;
+    if excess <= 0 {
+        return Ok(0);
+    }
+
+    let result = sqlx::query(
+        r#"DELETE FROM captchas
+           ORDER BY expires_at
+           LIMIT ?"#,
+    )
+    .bind(excess)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//清理过期的验证码
+pub async fn delete_expired_captchas(db: &Pool<MySql>) -> anyhow::Result<u64> {
+    let result = sqlx::query(r#"DELETE FROM captchas WHERE expires_at < ?"#)
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,85 @@
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use sqlx::{MySql, Pool};
+
+//一次修改邮箱的请求(已经通过令牌确认的)
+#[derive(Debug, Clone, FromRow)]
+pub struct EmailChangeRow {
+    pub user_id: i64,
+    pub new_email: String,
+    pub session_id: String,
+}
+
+//保存一个修改邮箱的请求
+//同一个用户之前没确认的请求全部作废，只有最新一封邮件里的链接有效
+pub async fn create_email_change(
+    db: &Pool<MySql>,
+    user_id: i64,
+    new_email: &str,
+    session_id: &str,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"UPDATE email_change_requests
+           SET used_at = ?
+           WHERE user_id = ? AND used_at IS NULL"#,
+    )
+    .bind(now)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"INSERT INTO email_change_requests (user_id, new_email, session_id, token_hash, expires_at, created_at)
+           VALUES (?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(user_id)
+    .bind(new_email)
+    .bind(session_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+//使用确认令牌：有效(没用过+没过期)就标记为已用，并返回这次请求
+pub async fn consume_email_change(
+    db: &Pool<MySql>,
+    token_hash: &str,
+) -> anyhow::Result<Option<EmailChangeRow>> {
+    let now = Utc::now();
+    let marked = sqlx::query(
+        r#"UPDATE email_change_requests
+           SET used_at = ?
+           WHERE token_hash = ? AND used_at IS NULL AND expires_at >= ?"#,
+    )
+    .bind(now)
+    .bind(token_hash)
+    .bind(now)
+    .execute(db)
+    .await?;
+
+    if marked.rows_affected() != 1 {
+        return Ok(None);
+    }
+
+    let row = sqlx::query_as::<_, EmailChangeRow>(
+        r#"SELECT user_id, new_email, session_id
+           FROM email_change_requests
+           WHERE token_hash = ?
+           LIMIT 1"#,
+    )
+    .bind(token_hash)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row)
+}
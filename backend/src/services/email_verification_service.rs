@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use sqlx::{MySql, Pool};
+
+//保存一个新的邮箱验证令牌
+//同一个用户之前没用过的令牌全部作废，只有最新一封邮件里的链接有效
+pub async fn create_verification_token(
+    db: &Pool<MySql>,
+    user_id: i64,
+    email: &str,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"UPDATE email_verification_tokens
+           SET used_at = ?
+           WHERE user_id = ? AND used_at IS NULL"#,
+    )
+    .bind(now)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"INSERT INTO email_verification_tokens (user_id, email, token_hash, expires_at, created_at)
+           VALUES (?, ?, ?, ?, ?)"#,
+    )
+    .bind(user_id)
+    .bind(email)
+    .bind(token_hash)
+    .bind(expires_at)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+//使用邮箱验证令牌：有效(没用过+没过期)就标记为已用，并返回(user_id, email)
+pub async fn consume_verification_token(
+    db: &Pool<MySql>,
+    token_hash: &str,
+) -> anyhow::Result<Option<(i64, String)>> {
+    let now = Utc::now();
+    let marked = sqlx::query(
+        r#"UPDATE email_verification_tokens
+           SET used_at = ?
+           WHERE token_hash = ? AND used_at IS NULL AND expires_at >= ?"#,
+    )
+    .bind(now)
+    .bind(token_hash)
+    .bind(now)
+    .execute(db)
+    .await?;
+
+    if marked.rows_affected() != 1 {
+        return Ok(None);
+    }
+
+    let row = sqlx::query_as::<_, (i64, String)>(
+        r#"SELECT user_id, email FROM email_verification_tokens WHERE token_hash = ? LIMIT 1"#,
+    )
+    .bind(token_hash)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row)
+}
+
+//最近一次给这个用户发验证邮件的时间(重发限流用)
+pub async fn last_sent_at(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<Option<DateTime<Utc>>> {
+    let sent_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+        r#"SELECT created_at
+           FROM email_verification_tokens
+           WHERE user_id = ?
+           ORDER BY created_at DESC
+           LIMIT 1"#,
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(sent_at)
+}
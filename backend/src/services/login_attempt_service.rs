@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use sqlx::{MySql, Pool};
+
+//查询key当前的锁定截止时间(已经过了的不算)
+pub async fn find_locked_until(
+    db: &Pool<MySql>,
+    key: &str,
+) -> anyhow::Result<Option<DateTime<Utc>>> {
+    let locked_until: Option<Option<DateTime<Utc>>> = sqlx::query_scalar(
+        r#"SELECT locked_until
+           FROM login_attempts
+           WHERE attempt_key = ? AND locked_until > ?
+           LIMIT 1"#,
+    )
+    .bind(key)
+    .bind(Utc::now())
+    .fetch_optional(db)
+    .await?;
+
+    Ok(locked_until.flatten())
+}
+
+//记一次失败，返回窗口内累计的失败次数
+//窗口从最后一次失败和锁定结束里晚的那个开始算，这个时间在window_start之前的话，计数从1重新开始
+//(只按最后一次失败算的话，锁得比窗口还久时解锁后计数已经清零，退避永远涨不上去)
+pub async fn record_failure(
+    db: &Pool<MySql>,
+    key: &str,
+    window_start: DateTime<Utc>,
+) -> anyhow::Result<u32> {
+    let now = Utc::now();
+    let mut tx = db.begin().await?;
+
+    //UPDATE子句从左到右执行：先用旧的last_failure_at判断窗口，再更新它
+    sqlx::query(
+        r#"INSERT INTO login_attempts (attempt_key, failures, last_failure_at)
+           VALUES (?, 1, ?)
+           ON DUPLICATE KEY UPDATE
+             failures = IF(GREATEST(last_failure_at, COALESCE(locked_until, last_failure_at)) < ?,
+                           1, failures + 1),
+             last_failure_at = VALUES(last_failure_at)"#,
+    )
+    .bind(key)
+    .bind(now)
+    .bind(window_start)
+    .execute(&mut *tx)
+    .await?;
+
+    let failures: i32 =
+        sqlx::query_scalar(r#"SELECT failures FROM login_attempts WHERE attempt_key = ?"#)
+            .bind(key)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    tx.commit().await?;
+    Ok(failures.max(0) as u32)
+}
+
+//窗口内累计的失败次数；最后一次失败和锁定结束都在window_start之前的话算0
+pub async fn find_recent_failures(
+    db: &Pool<MySql>,
+    key: &str,
+    window_start: DateTime<Utc>,
+) -> anyhow::Result<u32> {
+    let failures: Option<i32> = sqlx::query_scalar(
+        r#"SELECT failures
+           FROM login_attempts
+           WHERE attempt_key = ?
+             AND GREATEST(last_failure_at, COALESCE(locked_until, last_failure_at)) >= ?
+           LIMIT 1"#,
+    )
+    .bind(key)
+    .bind(window_start)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(failures.unwrap_or(0).max(0) as u32)
+}
+
+//设置锁定截止时间
+pub async fn set_locked_until(
+    db: &Pool<MySql>,
+    key: &str,
+    locked_until: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE login_attempts SET locked_until = ? WHERE attempt_key = ?"#)
+        .bind(locked_until)
+        .bind(key)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//登录成功：清掉计数
+pub async fn delete_attempts(db: &Pool<MySql>, key: &str) -> anyhow::Result<()> {
+    sqlx::query(r#"DELETE FROM login_attempts WHERE attempt_key = ?"#)
+        .bind(key)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//清理没用的记录：最后一次失败和锁定结束都在window_start之前(还在锁定中的不会被清掉)
+pub async fn delete_stale_attempts(
+    db: &Pool<MySql>,
+    window_start: DateTime<Utc>,
+) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        r#"DELETE FROM login_attempts
+           WHERE GREATEST(last_failure_at, COALESCE(locked_until, last_failure_at)) < ?"#,
+    )
+    .bind(window_start)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
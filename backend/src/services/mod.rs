@@ -1 +1,14 @@
+pub mod audit_service;
+pub mod captcha_service;
+pub mod email_change_service;
+pub mod email_verification_service;
+pub mod login_attempt_service;
+pub mod passkey_service;
+pub mod password_reset_service;
+pub mod pow_nonce_service;
+pub mod recovery_code_service;
+pub mod revocation_service;
+pub mod role_service;
+pub mod session_service;
+pub mod token_service;
 pub mod user_service;
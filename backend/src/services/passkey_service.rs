@@ -0,0 +1,71 @@
+use chrono::Utc;
+use sqlx::FromRow;
+use sqlx::{MySql, Pool};
+
+//webauthn_credentials表的一行
+//passkey是webauthn-rs的Passkey序列化成的JSON，用的时候再反序列化
+#[derive(Debug, Clone, FromRow)]
+pub struct PasskeyRow {
+    pub id: i64,
+    pub passkey: String,
+}
+
+//保存一个新注册的通行密钥，返回新记录的id
+pub async fn create_passkey(
+    db: &Pool<MySql>,
+    user_id: i64,
+    credential_id: &str,
+    name: &str,
+    passkey_json: &str,
+) -> anyhow::Result<i64> {
+    let result = sqlx::query(
+        r#"INSERT INTO webauthn_credentials (user_id, credential_id, name, passkey)
+           VALUES (?, ?, ?, ?)"#,
+    )
+    .bind(user_id)
+    .bind(credential_id)
+    .bind(name)
+    .bind(passkey_json)
+    .execute(db)
+    .await?;
+
+    Ok(result.last_insert_id() as i64)
+}
+
+//查出用户所有的通行密钥
+pub async fn list_passkeys_by_user(
+    db: &Pool<MySql>,
+    user_id: i64,
+) -> anyhow::Result<Vec<PasskeyRow>> {
+    let rows = sqlx::query_as::<_, PasskeyRow>(
+        r#"SELECT id, passkey
+           FROM webauthn_credentials
+           WHERE user_id = ?
+           ORDER BY id"#,
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+//登录成功后更新凭据(签名计数器等)和最近使用时间
+pub async fn update_passkey_after_login(
+    db: &Pool<MySql>,
+    id: i64,
+    passkey_json: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"UPDATE webauthn_credentials
+           SET passkey = ?, last_used_at = ?
+           WHERE id = ?"#,
+    )
+    .bind(passkey_json)
+    .bind(Utc::now())
+    .bind(id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
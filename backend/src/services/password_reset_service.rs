@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use sqlx::{MySql, Pool};
+
+//保存一个新的找回密码令牌
+//同一个用户之前没用过的令牌全部作废，只有最新一封邮件里的链接有效
+pub async fn create_reset_token(
+    db: &Pool<MySql>,
+    user_id: i64,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"UPDATE password_reset_tokens
+           SET used_at = ?
+           WHERE user_id = ? AND used_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
+           VALUES (?, ?, ?)"#,
+    )
+    .bind(user_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+//查令牌属于哪个用户，不作废令牌(无效、已用、过期都返回None)
+pub async fn find_reset_token_user(
+    db: &Pool<MySql>,
+    token_hash: &str,
+) -> anyhow::Result<Option<i64>> {
+    let user_id = sqlx::query_scalar::<_, i64>(
+        r#"SELECT user_id FROM password_reset_tokens
+           WHERE token_hash = ? AND used_at IS NULL AND expires_at >= ?
+           LIMIT 1"#,
+    )
+    .bind(token_hash)
+    .bind(Utc::now())
+    .fetch_optional(db)
+    .await?;
+
+    Ok(user_id)
+}
+
+//使用找回密码令牌：有效(没用过+没过期)就标记为已用，并返回对应的user_id
+//带条件的UPDATE保证同一个令牌只能被用一次
+pub async fn consume_reset_token(
+    db: &Pool<MySql>,
+    token_hash: &str,
+) -> anyhow::Result<Option<i64>> {
+    let now = Utc::now();
+    let marked = sqlx::query(
+        r#"UPDATE password_reset_tokens
+           SET used_at = ?
+           WHERE token_hash = ? AND used_at IS NULL AND expires_at >= ?"#,
+    )
+    .bind(now)
+    .bind(token_hash)
+    .bind(now)
+    .execute(db)
+    .await?;
+
+    if marked.rows_affected() != 1 {
+        return Ok(None);
+    }
+
+    let user_id = sqlx::query_scalar::<_, i64>(
+        r#"SELECT user_id FROM password_reset_tokens WHERE token_hash = ? LIMIT 1"#,
+    )
+    .bind(token_hash)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(user_id)
+}
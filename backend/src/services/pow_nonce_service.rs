@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use sqlx::{MySql, Pool};
+
+//还没过期的已用随机串数量
+pub async fn count_pow_nonces(db: &Pool<MySql>) -> anyhow::Result<i64> {
+    let count =
+        sqlx::query_scalar::<_, i64>(r#"SELECT COUNT(*) FROM pow_nonces WHERE expires_at >= ?"#)
+            .bind(Utc::now())
+            .fetch_one(db)
+            .await?;
+
+    Ok(count)
+}
+
+//记下一个已用的随机串；返回false表示之前已经记过(同一个challenge提交了第二次)
+//靠主键保证：并发提交同一个challenge，只有一个能插进去
+pub async fn insert_pow_nonce(
+    db: &Pool<MySql>,
+    nonce: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(r#"INSERT IGNORE INTO pow_nonces (nonce, expires_at) VALUES (?, ?)"#)
+        .bind(nonce)
+        .bind(expires_at)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//清理challenge已经过期的随机串：过期的challenge本身就校验不过，不用再记着
+pub async fn delete_expired_pow_nonces(db: &Pool<MySql>) -> anyhow::Result<u64> {
+    let result = sqlx::query(r#"DELETE FROM pow_nonces WHERE expires_at < ?"#)
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
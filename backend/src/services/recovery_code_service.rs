@@ -0,0 +1,69 @@
+use chrono::Utc;
+use sqlx::{MySql, Pool};
+
+//换一组新的恢复码：旧的全部删掉
+pub async fn replace_recovery_codes(
+    db: &Pool<MySql>,
+    user_id: i64,
+    code_hashes: &[String],
+) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query(r#"DELETE FROM recovery_codes WHERE user_id = ?"#)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for code_hash in code_hashes {
+        sqlx::query(r#"INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)"#)
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+//查出还没用过的恢复码：(id, code_hash)
+pub async fn list_unused_recovery_codes(
+    db: &Pool<MySql>,
+    user_id: i64,
+) -> anyhow::Result<Vec<(i64, String)>> {
+    let rows = sqlx::query_as::<_, (i64, String)>(
+        r#"SELECT id, code_hash
+           FROM recovery_codes
+           WHERE user_id = ? AND used_at IS NULL"#,
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+//标记恢复码已用；返回false表示已经被别的请求用掉了
+pub async fn mark_recovery_code_used(db: &Pool<MySql>, id: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"UPDATE recovery_codes
+           SET used_at = ?
+           WHERE id = ? AND used_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//删除用户所有恢复码(关闭两步验证时)
+pub async fn delete_recovery_codes(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<()> {
+    sqlx::query(r#"DELETE FROM recovery_codes WHERE user_id = ?"#)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use sqlx::{MySql, Pool};
+
+use crate::state::RevocationStore;
+
+//把一个jti写进吊销表
+//INSERT IGNORE：同一个token重复登出不算错误
+pub async fn revoke_token(
+    db: &Pool<MySql>,
+    jti: &str,
+    user_id: i64,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"INSERT IGNORE INTO revoked_tokens (jti, user_id, expires_at)
+           VALUES (?, ?, ?)"#,
+    )
+    .bind(jti)
+    .bind(user_id)
+    .bind(expires_at)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+//查出所有还没过期的吊销记录(启动时/定时同步内存缓存用)
+pub async fn list_active_revocations(
+    db: &Pool<MySql>,
+) -> anyhow::Result<Vec<(String, DateTime<Utc>)>> {
+    let rows = sqlx::query_as::<_, (String, DateTime<Utc>)>(
+        r#"SELECT jti, expires_at
+           FROM revoked_tokens
+           WHERE expires_at >= ?"#,
+    )
+    .bind(Utc::now())
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+//删除已经过期的吊销记录：token本身都过期了，没必要再记着
+pub async fn delete_expired_revocations(db: &Pool<MySql>) -> anyhow::Result<u64> {
+    let result = sqlx::query(r#"DELETE FROM revoked_tokens WHERE expires_at < ?"#)
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+//按用户吊销：not_before之前签发的access token全部作废
+//同一个用户再次吊销时覆盖成更新的时间
+pub async fn revoke_user_tokens(
+    db: &Pool<MySql>,
+    user_id: i64,
+    not_before: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"INSERT INTO revoked_user_tokens (user_id, not_before, expires_at)
+           VALUES (?, ?, ?)
+           ON DUPLICATE KEY UPDATE not_before = VALUES(not_before), expires_at = VALUES(expires_at)"#,
+    )
+    .bind(user_id)
+    .bind(not_before)
+    .bind(expires_at)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+//查出所有还没过期的按用户吊销记录：(user_id, not_before, expires_at)
+pub async fn list_active_user_revocations(
+    db: &Pool<MySql>,
+) -> anyhow::Result<Vec<(i64, DateTime<Utc>, DateTime<Utc>)>> {
+    let rows = sqlx::query_as::<_, (i64, DateTime<Utc>, DateTime<Utc>)>(
+        r#"SELECT user_id, not_before, expires_at
+           FROM revoked_user_tokens
+           WHERE expires_at >= ?"#,
+    )
+    .bind(Utc::now())
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+//删除已经过期的按用户吊销记录
+pub async fn delete_expired_user_revocations(db: &Pool<MySql>) -> anyhow::Result<u64> {
+    let result = sqlx::query(r#"DELETE FROM revoked_user_tokens WHERE expires_at < ?"#)
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+//删掉库里过期的吊销记录，再把还有效的记录同步进内存缓存
+//启动时调用一次，之后由后台任务定时调用(多实例之间靠它同步)
+pub async fn sync_revocation_store(
+    db: &Pool<MySql>,
+    store: &RevocationStore,
+) -> anyhow::Result<()> {
+    delete_expired_revocations(db).await?;
+    delete_expired_user_revocations(db).await?;
+
+    for (jti, expires_at) in list_active_revocations(db).await? {
+        store.insert(jti, expires_at);
+    }
+    for (user_id, not_before, expires_at) in list_active_user_revocations(db).await? {
+        store.insert_user(user_id, not_before, expires_at);
+    }
+
+    store.cleanup_expired();
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use sqlx::{MySql, Pool};
+
+use super::user_service;
+
+//管理员角色名
+pub const ADMIN_ROLE: &str = "admin";
+
+//用户拥有的角色名，按名字排序
+pub async fn list_user_roles(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<Vec<String>> {
+    let roles = sqlx::query_scalar::<_, String>(
+        r#"SELECT r.name
+           FROM user_roles ur
+           JOIN roles r ON r.id = ur.role_id
+           WHERE ur.user_id = ?
+           ORDER BY r.name"#,
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(roles)
+}
+
+//给用户加一个角色；返回false表示角色不存在
+//已经有这个角色的话什么都不做
+pub async fn assign_role(db: &Pool<MySql>, user_id: i64, role: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"INSERT IGNORE INTO user_roles (user_id, role_id)
+           SELECT ?, id FROM roles WHERE name = ?"#,
+    )
+    .bind(user_id)
+    .bind(role)
+    .execute(db)
+    .await?;
+
+    if result.rows_affected() == 1 {
+        return Ok(true);
+    }
+    role_exists(db, role).await
+}
+
+//去掉用户的一个角色；返回false表示用户本来就没有这个角色
+pub async fn remove_role(db: &Pool<MySql>, user_id: i64, role: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"DELETE ur FROM user_roles ur
+           JOIN roles r ON r.id = ur.role_id
+           WHERE ur.user_id = ? AND r.name = ?"#,
+    )
+    .bind(user_id)
+    .bind(role)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//拥有某个角色、而且还能登录(没禁用、没删除)的用户数量
+pub async fn count_users_with_role(db: &Pool<MySql>, role: &str) -> anyhow::Result<i64> {
+    let count = sqlx::query_scalar::<_, i64>(
+        r#"SELECT COUNT(*)
+           FROM user_roles ur
+           JOIN roles r ON r.id = ur.role_id
+           JOIN users u ON u.id = ur.user_id
+           WHERE r.name = ? AND u.deleted_at IS NULL AND u.disabled_at IS NULL"#,
+    )
+    .bind(role)
+    .fetch_one(db)
+    .await?;
+
+    Ok(count)
+}
+
+async fn role_exists(db: &Pool<MySql>, role: &str) -> anyhow::Result<bool> {
+    let found = sqlx::query_scalar::<_, i64>(r#"SELECT id FROM roles WHERE name = ? LIMIT 1"#)
+        .bind(role)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(found.is_some())
+}
+
+//启动时把指定账号设为管理员(只在还没有任何管理员时生效)
+//返回被设为管理员的用户id；已经有管理员或者账号不存在时返回None
+pub async fn bootstrap_admin(db: &Pool<MySql>, account: &str) -> anyhow::Result<Option<i64>> {
+    if count_users_with_role(db, ADMIN_ROLE).await? > 0 {
+        return Ok(None);
+    }
+
+    let Some(user) = user_service::find_user_by_account(db, account).await? else {
+        return Ok(None);
+    };
+    if !assign_role(db, user.id, ADMIN_ROLE).await? {
+        anyhow::bail!("角色{ADMIN_ROLE}不存在，请先执行migrations");
+    }
+
+    Ok(Some(user.id))
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use sqlx::{MySql, Pool};
+
+//sessions表的一行
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct SessionRow {
+    pub id: String,
+    pub user_id: i64,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    //和refresh token family一起过期，刷新时往后延
+    pub expires_at: DateTime<Utc>,
+    pub user_agent: String,
+    pub ip: String,
+}
+
+//登录/注册成功时记录一个会话
+pub async fn create_session(
+    db: &Pool<MySql>,
+    id: &str,
+    user_id: i64,
+    user_agent: &str,
+    ip: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO sessions (id, user_id, created_at, last_seen_at, expires_at, user_agent, ip)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(now)
+    .bind(now)
+    .bind(expires_at)
+    .bind(user_agent)
+    .bind(ip)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+//通过id查会话
+pub async fn find_session(db: &Pool<MySql>, id: &str) -> anyhow::Result<Option<SessionRow>> {
+    let row = sqlx::query_as::<_, SessionRow>(
+        r#"SELECT id, user_id, created_at, last_seen_at, expires_at, user_agent, ip
+           FROM sessions
+           WHERE id = ?
+           LIMIT 1"#,
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row)
+}
+
+//更新最近活跃时间和IP
+pub async fn touch_session(db: &Pool<MySql>, id: &str, ip: &str) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE sessions SET last_seen_at = ?, ip = ? WHERE id = ?"#)
+        .bind(Utc::now())
+        .bind(ip)
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//刷新token时：更新最近活跃时间和IP，过期时间跟着新的refresh token往后延
+pub async fn renew_session(
+    db: &Pool<MySql>,
+    id: &str,
+    ip: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE sessions SET last_seen_at = ?, ip = ?, expires_at = ? WHERE id = ?"#)
+        .bind(Utc::now())
+        .bind(ip)
+        .bind(expires_at)
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//用户所有没过期的会话，最近活跃的在前面
+pub async fn list_sessions_by_user(
+    db: &Pool<MySql>,
+    user_id: i64,
+) -> anyhow::Result<Vec<SessionRow>> {
+    let rows = sqlx::query_as::<_, SessionRow>(
+        r#"SELECT id, user_id, created_at, last_seen_at, expires_at, user_agent, ip
+           FROM sessions
+           WHERE user_id = ? AND expires_at > ?
+           ORDER BY last_seen_at DESC"#,
+    )
+    .bind(user_id)
+    .bind(Utc::now())
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+//删除用户的一个会话；返回false表示会话不存在(或者不是这个用户的)
+pub async fn delete_session(db: &Pool<MySql>, user_id: i64, id: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query(r#"DELETE FROM sessions WHERE id = ? AND user_id = ?"#)
+        .bind(id)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//删除用户除了keep_id以外的所有会话
+pub async fn delete_other_sessions(
+    db: &Pool<MySql>,
+    user_id: i64,
+    keep_id: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(r#"DELETE FROM sessions WHERE user_id = ? AND id <> ?"#)
+        .bind(user_id)
+        .bind(keep_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//删除用户所有会话
+pub async fn delete_user_sessions(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<()> {
+    sqlx::query(r#"DELETE FROM sessions WHERE user_id = ?"#)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//删除过期的会话(后台任务定时调用)：refresh token已经过期，这个会话不可能再续上了
+pub async fn delete_expired_sessions(db: &Pool<MySql>) -> anyhow::Result<u64> {
+    let result = sqlx::query(r#"DELETE FROM sessions WHERE expires_at < ?"#)
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use sqlx::{MySql, Pool};
+
+//refresh_tokens表的一行
+//不查token_hash本身：调用方就是拿hash来查的
+#[derive(Debug, Clone, FromRow)]
+pub struct RefreshTokenRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub family_id: String,
+    pub expires_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+//保存一条新的refresh token(登录/注册时开启一个新family)
+pub async fn create_refresh_token(
+    db: &Pool<MySql>,
+    user_id: i64,
+    family_id: &str,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<i64> {
+    let result = sqlx::query(
+        r#"INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+           VALUES (?, ?, ?, ?)"#,
+    )
+    .bind(user_id)
+    .bind(family_id)
+    .bind(token_hash)
+    .bind(expires_at)
+    .execute(db)
+    .await?;
+
+    Ok(result.last_insert_id() as i64)
+}
+
+//通过摘要查refresh token
+pub async fn find_refresh_token_by_hash(
+    db: &Pool<MySql>,
+    token_hash: &str,
+) -> anyhow::Result<Option<RefreshTokenRow>> {
+    let row = sqlx::query_as::<_, RefreshTokenRow>(
+        r#"SELECT id, user_id, family_id, expires_at, rotated_at, revoked_at
+           FROM refresh_tokens
+           WHERE token_hash = ?
+           LIMIT 1"#,
+    )
+    .bind(token_hash)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row)
+}
+
+//轮换：把旧token标记为已轮换，同时在同一个family里插入新token
+//返回false表示旧token在这期间已经被别的请求轮换/作废了(并发重放)，调用方要按盗用处理
+pub async fn rotate_refresh_token(
+    db: &Pool<MySql>,
+    old: &RefreshTokenRow,
+    new_token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<bool> {
+    let mut tx = db.begin().await?;
+
+    //带条件的UPDATE是原子的：两个请求同时拿同一个token来换，只有一个能改到这一行
+    let marked = sqlx::query(
+        r#"UPDATE refresh_tokens
+           SET rotated_at = ?
+           WHERE id = ? AND rotated_at IS NULL AND revoked_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(old.id)
+    .execute(&mut *tx)
+    .await?;
+
+    if marked.rows_affected() != 1 {
+        tx.rollback().await?;
+        return Ok(false);
+    }
+
+    sqlx::query(
+        r#"INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+           VALUES (?, ?, ?, ?)"#,
+    )
+    .bind(old.user_id)
+    .bind(&old.family_id)
+    .bind(new_token_hash)
+    .bind(expires_at)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+//作废整个family(检测到重放时使用)
+pub async fn revoke_refresh_family(db: &Pool<MySql>, family_id: &str) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"UPDATE refresh_tokens
+           SET revoked_at = ?
+           WHERE family_id = ? AND revoked_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(family_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+//作废某个用户所有的refresh token(重置密码后全部下线)
+pub async fn revoke_user_refresh_tokens(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"UPDATE refresh_tokens
+           SET revoked_at = ?
+           WHERE user_id = ? AND revoked_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+//作废某个用户除了keep_family_id以外的所有refresh token("退出其他设备")
+pub async fn revoke_other_refresh_families(
+    db: &Pool<MySql>,
+    user_id: i64,
+    keep_family_id: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"UPDATE refresh_tokens
+           SET revoked_at = ?
+           WHERE user_id = ? AND family_id <> ? AND revoked_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(user_id)
+    .bind(keep_family_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+//删除过期的refresh token(后台任务定时调用)：过期之后拿来刷新也会被拒绝，留着没用
+pub async fn delete_expired_refresh_tokens(db: &Pool<MySql>) -> anyhow::Result<u64> {
+    let result = sqlx::query(r#"DELETE FROM refresh_tokens WHERE expires_at < ?"#)
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -1,6 +1,10 @@
 use anyhow::Ok;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use sqlx::{MySql, Pool};
+use sqlx::{MySql, Pool, QueryBuilder};
+
+use crate::utils::login_throttle;
 
 //定义从数据库查出来的一行用户长什么样
 //FromRow：可以让sqlx把SELECT选取的结果自动映射到这个结构体
@@ -11,6 +15,44 @@ pub struct UserRow {
     pub username: String,
     pub email: String,
     pub password_hash: String,
+    //为空表示邮箱还没验证
+    pub email_verified_at: Option<DateTime<Utc>>,
+    //两步验证：密钥(开启流程中/已开启)、开启时间(为空表示没开启)
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    //为空表示正常；不为空表示被管理员禁用
+    pub disabled_at: Option<DateTime<Utc>>,
+    //用户选的界面语言(比如"en")，为空表示跟随浏览器
+    pub locale: Option<String>,
+}
+
+//管理接口里的一行用户(不带密码哈希和TOTP密钥)
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct AdminUserRow {
+    pub id: i64,
+    pub username: String,
+    pub email: String,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+//管理接口按状态筛选
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatus {
+    Active,
+    Disabled,
+    Deleted,
+}
+
+//管理接口的筛选条件：用户名/邮箱是包含匹配；不指定状态时不包括已删除的
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub status: Option<UserStatus>,
 }
 
 //创建用户(注册时使用)
@@ -45,9 +87,10 @@ pub async fn find_user_by_account(
     //通过query_as把结果映射到UserRow
     //fetch_optional:查到->Some(UserRow),没查到->Ok(None)
     let user = sqlx::query_as::<_, UserRow>(
-        r#"SELECT id, username, email, password_hash
+        r#"SELECT id, username, email, password_hash, email_verified_at,
+                  totp_secret, totp_enabled_at, disabled_at, locale
            FROM users
-           WHERE username = ? OR email = ?
+           WHERE (username = ? OR email = ?) AND deleted_at IS NULL
            LIMIT 1"#,
     )
     .bind(account)
@@ -62,9 +105,10 @@ pub async fn find_user_by_account(
 //从token里拿到user_id,用user_id查数据库,返回用户信息
 pub async fn find_user_by_id(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<Option<UserRow>> {
     let user = sqlx::query_as::<_, UserRow>(
-        r#"SELECT id, username, email, password_hash
+        r#"SELECT id, username, email, password_hash, email_verified_at,
+                  totp_secret, totp_enabled_at, disabled_at, locale
            FROM users
-           WHERE id = ?
+           WHERE id = ? AND deleted_at IS NULL
            LIMIT 1"#,
     )
     .bind(user_id)
@@ -73,3 +117,295 @@ pub async fn find_user_by_id(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<O
 
     Ok(user)
 }
+
+//管理接口：分页查询用户，按id倒序(新注册的在前)
+pub async fn list_users(
+    db: &Pool<MySql>,
+    filter: &UserFilter,
+    offset: i64,
+    limit: i64,
+) -> anyhow::Result<Vec<AdminUserRow>> {
+    let mut qb = QueryBuilder::<MySql>::new(
+        r#"SELECT id, username, email, email_verified_at, totp_enabled_at, disabled_at, deleted_at
+           FROM users"#,
+    );
+    push_user_filter(&mut qb, filter);
+    qb.push(" ORDER BY id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let rows = qb.build_query_as::<AdminUserRow>().fetch_all(db).await?;
+    Ok(rows)
+}
+
+//管理接口：符合筛选条件的用户总数(分页用)
+pub async fn count_users(db: &Pool<MySql>, filter: &UserFilter) -> anyhow::Result<i64> {
+    let mut qb = QueryBuilder::<MySql>::new("SELECT COUNT(*) FROM users");
+    push_user_filter(&mut qb, filter);
+
+    let total = qb.build_query_scalar::<i64>().fetch_one(db).await?;
+    Ok(total)
+}
+
+//拼WHERE条件：值都通过bind传，不直接拼进SQL
+fn push_user_filter(qb: &mut QueryBuilder<'_, MySql>, filter: &UserFilter) {
+    qb.push(" WHERE ");
+    match filter.status {
+        None => qb.push("deleted_at IS NULL"),
+        Some(UserStatus::Active) => qb.push("deleted_at IS NULL AND disabled_at IS NULL"),
+        Some(UserStatus::Disabled) => qb.push("deleted_at IS NULL AND disabled_at IS NOT NULL"),
+        Some(UserStatus::Deleted) => qb.push("deleted_at IS NOT NULL"),
+    };
+    if let Some(username) = &filter.username {
+        qb.push(" AND username LIKE ")
+            .push_bind(format!("%{}%", escape_like(username)));
+    }
+    if let Some(email) = &filter.email {
+        qb.push(" AND email LIKE ")
+            .push_bind(format!("%{}%", escape_like(email)));
+    }
+}
+
+//LIKE里的%和_是通配符，用户输入的要转义
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+//管理接口：通过id查用户(包括已删除的)
+pub async fn find_admin_user_by_id(
+    db: &Pool<MySql>,
+    user_id: i64,
+) -> anyhow::Result<Option<AdminUserRow>> {
+    let row = sqlx::query_as::<_, AdminUserRow>(
+        r#"SELECT id, username, email, email_verified_at, totp_enabled_at, disabled_at, deleted_at
+           FROM users
+           WHERE id = ?
+           LIMIT 1"#,
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row)
+}
+
+//禁用/启用账号
+pub async fn set_user_disabled(
+    db: &Pool<MySql>,
+    user_id: i64,
+    disabled: bool,
+) -> anyhow::Result<()> {
+    let disabled_at = disabled.then(Utc::now);
+    sqlx::query(r#"UPDATE users SET disabled_at = ? WHERE id = ? AND deleted_at IS NULL"#)
+        .bind(disabled_at)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//软删除：只打标记，数据都还在
+pub async fn soft_delete_user(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE users SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL"#)
+        .bind(Utc::now())
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//硬删除：用户和所有关联数据一起删掉
+//吊销记录(revoked_tokens/revoked_user_tokens)留着，到期后由后台任务清理；审计日志也留着
+pub async fn hard_delete_user(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<bool> {
+    let mut tx = db.begin().await?;
+
+    for table in [
+        "sessions",
+        "refresh_tokens",
+        "password_reset_tokens",
+        "email_verification_tokens",
+        "email_change_requests",
+        "recovery_codes",
+        "webauthn_credentials",
+        "user_roles",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE user_id = ?"))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query(r#"DELETE FROM login_attempts WHERE attempt_key = ?"#)
+        .bind(login_throttle::user_key(user_id))
+        .execute(&mut *tx)
+        .await?;
+
+    let result = sqlx::query(r#"DELETE FROM users WHERE id = ?"#)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(result.rows_affected() == 1)
+}
+
+//更新密码哈希(重置密码/修改密码)
+pub async fn update_password_hash(
+    db: &Pool<MySql>,
+    user_id: i64,
+    password_hash: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE users SET password_hash = ? WHERE id = ?"#)
+        .bind(password_hash)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//登录时按新参数重新哈希后写回
+//只有库里还是旧哈希时才更新：期间密码被改过的话不能用旧密码覆盖回去
+pub async fn rehash_password(
+    db: &Pool<MySql>,
+    user_id: i64,
+    old_hash: &str,
+    new_hash: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"UPDATE users SET password_hash = ?
+           WHERE id = ? AND password_hash = ?"#,
+    )
+    .bind(new_hash)
+    .bind(user_id)
+    .bind(old_hash)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//修改邮箱：新邮箱是点确认链接换过来的，所以直接算已验证
+//新邮箱被别人占用时会触发唯一索引冲突(1062)
+pub async fn change_email(db: &Pool<MySql>, user_id: i64, new_email: &str) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"UPDATE users
+           SET email = ?, email_verified_at = ?
+           WHERE id = ? AND deleted_at IS NULL"#,
+    )
+    .bind(new_email)
+    .bind(Utc::now())
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+//标记邮箱已验证
+//只有当前邮箱还是发验证邮件时的那个地址才算数，中途改过邮箱的旧链接不生效
+//返回是否真的更新了
+pub async fn mark_email_verified(
+    db: &Pool<MySql>,
+    user_id: i64,
+    email: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"UPDATE users
+           SET email_verified_at = ?
+           WHERE id = ? AND email = ? AND email_verified_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(user_id)
+    .bind(email)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//设置界面语言：None表示清空，之后跟随浏览器
+pub async fn set_user_locale(
+    db: &Pool<MySql>,
+    user_id: i64,
+    locale: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(r#"UPDATE users SET locale = ? WHERE id = ?"#)
+        .bind(locale)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+//开启两步验证第一步：保存待确认的TOTP密钥
+//已经开启的用户不能覆盖(否则正在用的验证器App就失效了)
+pub async fn set_pending_totp_secret(
+    db: &Pool<MySql>,
+    user_id: i64,
+    secret: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"UPDATE users
+           SET totp_secret = ?, totp_last_step = NULL
+           WHERE id = ? AND totp_enabled_at IS NULL"#,
+    )
+    .bind(secret)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//开启两步验证第二步：第一个验证码通过后正式开启
+pub async fn enable_totp(db: &Pool<MySql>, user_id: i64, step: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"UPDATE users
+           SET totp_enabled_at = ?, totp_last_step = ?
+           WHERE id = ? AND totp_secret IS NOT NULL AND totp_enabled_at IS NULL"#,
+    )
+    .bind(Utc::now())
+    .bind(step)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//记录用过的时间步：只有比上次用过的更新才能成功，同一个验证码不能用两次
+pub async fn consume_totp_step(db: &Pool<MySql>, user_id: i64, step: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"UPDATE users
+           SET totp_last_step = ?
+           WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)"#,
+    )
+    .bind(step)
+    .bind(user_id)
+    .bind(step)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+//关闭两步验证
+pub async fn disable_totp(db: &Pool<MySql>, user_id: i64) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"UPDATE users
+           SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL
+           WHERE id = ?"#,
+    )
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
@@ -4,60 +4,125 @@ use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use sqlx::{MySql, Pool};
 
-//单条验证码记录：存储正确答案+过期时间
-#[derive(Clone, Debug)]
-pub struct CaptchaEntry {
-    pub answer: String,
-    pub expires_at: DateTime<Utc>,
-}
+use webauthn_rs::Webauthn;
+use webauthn_rs::prelude::{PasskeyAuthentication, PasskeyRegistration};
 
-//验证码存储
-//DashMap是并发安全的HashMap
+use crate::config::settings::{Argon2Settings, CaptchaPresets, LoginThrottleSettings};
+use crate::utils::auth::{Claims, PasswordPolicy};
+use crate::utils::captcha_store::CaptchaStore;
+use crate::utils::jwt_keys::JwtKeys;
+use crate::utils::login_throttle::LoginAttemptTracker;
+use crate::utils::mailer::Mailer;
+use crate::utils::pow::ProofOfWork;
+use crate::utils::pow_nonce_store::PowNonceStore;
+
+//access token吊销列表的内存缓存
+//map:单个token，jti -> token过期时间(登出)
+//users:整个用户，user_id -> (not_before, 记录过期时间)，not_before之前签发的token全部作废(重置密码)
+//真正的数据在MySQL里，这里只是为了每次请求不用查库
+//本实例吊销时直接写入；其他实例吊销的记录靠后台任务定时从库里同步过来
 #[derive(Clone, Debug, Default)]
-pub struct CaptchaStore {
-    pub map: DashMap<String, CaptchaEntry>,
+pub struct RevocationStore {
+    pub map: DashMap<String, DateTime<Utc>>,
+    pub users: DashMap<i64, (DateTime<Utc>, DateTime<Utc>)>,
 }
-impl CaptchaStore {
-    //插入一条验证码记录
-    pub fn insert(&self, id: String, entry: CaptchaEntry) {
-        self.map.insert(id, entry);
+impl RevocationStore {
+    //记录一个被吊销的jti
+    pub fn insert(&self, jti: String, expires_at: DateTime<Utc>) {
+        self.map.insert(jti, expires_at);
+    }
+
+    //记录一个用户的吊销时间点
+    pub fn insert_user(&self, user_id: i64, not_before: DateTime<Utc>, expires_at: DateTime<Utc>) {
+        self.users.insert(user_id, (not_before, expires_at));
     }
 
-    //验证码校验
-    //找不到/过期(删除)/不匹配->失败
-    //匹配->成功(删除)
-    pub fn verify_and_consume(&self, id: &str, user_input: &str) -> bool {
+    //token是否已经被吊销：jti单独登出过，或者签发时间早于这个用户的吊销时间点
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        if self.map.contains_key(&claims.jti) {
+            return true;
+        }
+        match self.users.get(&claims.sub) {
+            Some(entry) => (claims.iat as i64) < entry.0.timestamp(),
+            None => false,
+        }
+    }
+
+    //token本身已经过期的记录不需要再留着
+    pub fn cleanup_expired(&self) {
         let now = Utc::now();
+        self.map.retain(|_, v| *v >= now);
+        self.users.retain(|_, v| v.1 >= now);
+    }
+}
 
-        //没找到captcha_id
-        let Some(entry) = self.map.get(id) else {
-            return false;
-        };
+//通行密钥的进行中状态
+//WebAuthn的注册/登录都分两步：start生成challenge给浏览器，finish校验认证器的签名
+//两步之间的状态必须留在服务端(不能交给客户端保存，否则可以重放)
+#[derive(Clone, Debug)]
+pub enum PasskeyChallenge {
+    Registration {
+        user_id: i64,
+        state: PasskeyRegistration,
+    },
+    Authentication {
+        user_id: i64,
+        state: PasskeyAuthentication,
+    },
+}
 
-        //有id但是过期了
-        if entry.expires_at < now {
-            //删掉
-            drop(entry);
-            self.map.remove(id);
-            return false;
+//通行密钥challenge存储，用法和CaptchaStore一样：challenge_id -> (状态, 过期时间)
+#[derive(Clone, Debug, Default)]
+pub struct PasskeyChallengeStore {
+    pub map: DashMap<String, (PasskeyChallenge, DateTime<Utc>)>,
+}
+impl PasskeyChallengeStore {
+    pub fn insert(&self, id: String, challenge: PasskeyChallenge, expires_at: DateTime<Utc>) {
+        self.map.insert(id, (challenge, expires_at));
+    }
+
+    //取出challenge：不管成功失败都删掉，一个challenge只能用一次
+    //找不到/过期 -> None
+    pub fn take(&self, id: &str) -> Option<PasskeyChallenge> {
+        let (_, (challenge, expires_at)) = self.map.remove(id)?;
+        if expires_at < Utc::now() {
+            return None;
         }
+        Some(challenge)
+    }
 
-        //校验答案：忽略大小写+去空格
-        let ok = entry.answer.eq_ignore_ascii_case(user_input.trim());
+    pub fn cleanup_expired(&self) {
+        let now = Utc::now();
+        self.map.retain(|_, v| v.1 >= now);
+    }
+}
 
-        drop(entry);
+//失败次数计数：key -> (失败次数, 记录过期时间)
+//目前用来限制同一个两步验证中间token能试几次验证码
+#[derive(Clone, Debug, Default)]
+pub struct AttemptCounter {
+    pub map: DashMap<String, (u32, DateTime<Utc>)>,
+}
+impl AttemptCounter {
+    //失败次数+1，返回累计的失败次数
+    pub fn increment(&self, key: &str, expires_at: DateTime<Utc>) -> u32 {
+        let mut entry = self.map.entry(key.to_string()).or_insert((0, expires_at));
+        entry.0 += 1;
+        entry.0
+    }
 
-        if ok {
-            //验证码用掉就删除
-            self.map.remove(id);
-        }
+    //当前失败次数
+    pub fn get(&self, key: &str) -> u32 {
+        self.map.get(key).map(|v| v.0).unwrap_or(0)
+    }
 
-        ok
+    pub fn remove(&self, key: &str) {
+        self.map.remove(key);
     }
-    //定时清理过期验证码(后续给后台定时任务使用)
+
     pub fn cleanup_expired(&self) {
         let now = Utc::now();
-        self.map.retain(|_, v| v.expires_at >= now);
+        self.map.retain(|_, v| v.1 >= now);
     }
 }
 
@@ -65,9 +130,39 @@ impl CaptchaStore {
 #[derive(Clone)]
 pub struct AppState {
     pub db: Pool<MySql>,
-    pub captcha_store: Arc<CaptchaStore>,
+    pub captcha_store: Arc<dyn CaptchaStore>,
+    //验证码图片的生成参数(登录/注册/通用)
+    pub captcha_presets: Arc<CaptchaPresets>,
+    //工作量证明(验证码的替代方式)
+    pub pow: Arc<ProofOfWork>,
+    //已经用过的工作量证明随机串(跟验证码分开存)
+    pub pow_nonces: Arc<dyn PowNonceStore>,
+    pub revocation_store: Arc<RevocationStore>,
+    pub mfa_attempts: Arc<AttemptCounter>,
+    pub passkey_challenges: Arc<PasskeyChallengeStore>,
     pub debug_captcha: bool,
+    pub trusted_proxy_hops: usize,
     //JWT配置*登录注册接口需要用
-    pub jwt_secret: String,
+    pub jwt_keys: Arc<JwtKeys>,
     pub jwt_expire_seconds: i64,
+    pub refresh_expire_seconds: i64,
+    //发邮件(找回密码/邮箱验证)
+    pub mailer: Arc<dyn Mailer>,
+    pub app_base_url: String,
+    pub password_reset_expire_seconds: i64,
+    pub email_verify_expire_seconds: i64,
+    pub email_resend_interval_seconds: i64,
+    //两步验证配置
+    pub totp_issuer: String,
+    pub mfa_challenge_expire_seconds: i64,
+    //通行密钥
+    pub webauthn: Arc<Webauthn>,
+    pub passkey_challenge_expire_seconds: i64,
+    //登录失败锁定
+    pub login_attempts: Arc<dyn LoginAttemptTracker>,
+    pub login_throttle: LoginThrottleSettings,
+    //密码规则
+    pub password_policy: Arc<PasswordPolicy>,
+    //密码哈希参数
+    pub argon2: Argon2Settings,
 }
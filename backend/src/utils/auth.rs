@@ -1,20 +1,46 @@
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Ok;
 use argon2::{
-    Argon2,
+    Algorithm, Argon2, KeyId, Params, ParamsBuilder, Version,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
 };
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
-use rand_core::OsRng;
+use base64::{Engine as _, engine::general_purpose};
+use data_encoding::HEXUPPER;
+use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::config::settings::{Argon2Settings, PasswordPolicySettings};
+use crate::error::ErrorCode;
+use crate::utils::jwt_keys::JwtKeys;
+use crate::validation;
 
 //密码哈希部分
-pub fn hash_password(plain: &str) -> anyhow::Result<String> {
+//argon2id，参数和pepper从配置读
+//用了pepper的哈希在PHC字符串里带keyid(pepper摘要的前8字节)，没有keyid的是设置pepper之前的老哈希
+pub fn hash_password(settings: &Argon2Settings, plain: &str) -> anyhow::Result<String> {
     //盐值salt:每个密码都要配一个随机盐
     //这样同样的密码也会哈希出不同的结果
     let salt = SaltString::generate(&mut OsRng);
 
-    //使用Argon2默认参数
-    let argon2 = Argon2::default();
+    let pepper = settings.pepper.as_deref().map(str::as_bytes);
+    let mut builder = ParamsBuilder::new();
+    builder
+        .m_cost(settings.memory_kib)
+        .t_cost(settings.time_cost)
+        .p_cost(settings.parallelism);
+    if let Some(pepper) = pepper {
+        builder.keyid(
+            KeyId::new(&pepper_key_id(pepper))
+                .map_err(|e| anyhow::anyhow!("argon2 keyid failed:{e}"))?,
+        );
+    }
+    let params = builder
+        .build()
+        .map_err(|e| anyhow::anyhow!("argon2 params failed:{e}"))?;
+    let argon2 = argon2_with_pepper(pepper, params)?;
 
     //输出是一个包含算法信息的字符串
     let password_hash = argon2
@@ -28,62 +54,543 @@ pub fn hash_password(plain: &str) -> anyhow::Result<String> {
 //密码校验部分
 //从数据库读取password_hash
 //对用户输入的plain password做校验
-pub fn verify_password(plain: &str, password_hash: &str) -> anyhow::Result<bool> {
+//哈希用的参数(m/t/p)从PHC字符串里读，所以调整参数之后老哈希照样能校验
+pub fn verify_password(
+    settings: &Argon2Settings,
+    plain: &str,
+    password_hash: &str,
+) -> anyhow::Result<bool> {
     //解析数据库里的hash字符串
     let parsed = PasswordHash::new(password_hash)
         .map_err(|e| anyhow::anyhow!("PasswordHash parse failed:{e}"))?;
+    let params =
+        Params::try_from(&parsed).map_err(|e| anyhow::anyhow!("argon2 params failed:{e}"))?;
+
+    //带keyid的哈希必须用同一个pepper校验：在现在的和换下来的pepper里按keyid找
+    //都找不到(pepper配错了或者被删了)时当作密码不对，不能让这个用户每次登录都报500
+    let pepper = if params.keyid().is_empty() {
+        None
+    } else {
+        match find_pepper(settings, params.keyid()) {
+            Some(pepper) => Some(pepper),
+            None => {
+                eprintln!(
+                    "密码哈希用的pepper没有配置(keyid:{}),检查PASSWORD_PEPPER/PASSWORD_OLD_PEPPERS",
+                    HEXUPPER.encode(params.keyid())
+                );
+                return Ok(false);
+            }
+        }
+    };
 
     //verify:返回Ok表示匹配
-    let ok = Argon2::default()
+    let ok = argon2_with_pepper(pepper, Params::default())?
         .verify_password(plain.as_bytes(), &parsed)
         .is_ok();
 
     Ok(ok)
 }
 
+//用户输入的密码和哈希对不对得上
+//Legacy:规范化之前生成的老哈希，要调用方按规范化之后的密码重新哈希
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordMatch {
+    Matched,
+    Legacy,
+    Mismatched,
+}
+
+//校验用户输入的密码，input是原样输入(没有规范化)
+//现在的哈希都是按NFKC规范化之后的密码生成的；规范化上线之前的哈希是按原样输入生成的，
+//所以规范化之后对不上、并且规范化确实改了输入时，再用原样输入试一次
+pub fn verify_user_password(
+    settings: &Argon2Settings,
+    input: &str,
+    password_hash: &str,
+) -> anyhow::Result<PasswordMatch> {
+    let normalized = validation::normalize_password(input);
+    if verify_password(settings, &normalized, password_hash)? {
+        return Ok(PasswordMatch::Matched);
+    }
+    if normalized != input && verify_password(settings, input, password_hash)? {
+        return Ok(PasswordMatch::Legacy);
+    }
+    Ok(PasswordMatch::Mismatched)
+}
+
+//哈希是不是需要按现在的配置重新生成：算法/版本/参数/pepper任何一个和配置不一样都要
+//登录成功时调用，这时手上有明文密码，可以顺便重新哈希
+pub fn needs_rehash(settings: &Argon2Settings, password_hash: &str) -> bool {
+    let Some(parsed) = PasswordHash::new(password_hash).ok() else {
+        return true;
+    };
+    let Some(params) = Params::try_from(&parsed).ok() else {
+        return true;
+    };
+    let expected_keyid = settings
+        .pepper
+        .as_deref()
+        .map(|pepper| pepper_key_id(pepper.as_bytes()));
+
+    parsed.algorithm != Algorithm::Argon2id.ident()
+        || parsed.version != Some(Version::V0x13.into())
+        || params.m_cost() != settings.memory_kib
+        || params.t_cost() != settings.time_cost
+        || params.p_cost() != settings.parallelism
+        || params.keyid() != expected_keyid.as_ref().map_or(&[][..], |id| &id[..])
+}
+
+fn argon2_with_pepper(pepper: Option<&[u8]>, params: Params) -> anyhow::Result<Argon2<'_>> {
+    match pepper {
+        Some(pepper) => {
+            Argon2::new_with_secret(pepper, Algorithm::Argon2id, Version::V0x13, params)
+                .map_err(|e| anyhow::anyhow!("argon2 secret failed:{e}"))
+        }
+        None => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+    }
+}
+
+//按keyid找pepper：先看现在的，再看换下来的
+fn find_pepper<'a>(settings: &'a Argon2Settings, key_id: &[u8]) -> Option<&'a [u8]> {
+    settings
+        .pepper
+        .iter()
+        .chain(&settings.old_peppers)
+        .map(|pepper| pepper.as_bytes())
+        .find(|pepper| pepper_key_id(pepper) == key_id)
+}
+
+//pepper的标识：摘要的前8字节(argon2的keyid最长8字节)，不会暴露pepper本身
+fn pepper_key_id(pepper: &[u8]) -> [u8; 8] {
+    let digest = Sha256::digest(pepper);
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&digest[..8]);
+    id
+}
+
+//密码规则部分
+//注册、重置密码、修改密码时检查新密码：长度、字符种类、强度估算、不能包含用户名/邮箱、不能是泄露过的密码
+//泄露密码列表存的是SHA-1前缀(大写hex)，离线也能查；内置一份常见密码，可以用PASSWORD_BREACHED_LIST再加一份
+const BUNDLED_BREACHED_LIST: &str = include_str!("../../data/breached_sha1_prefixes.txt");
+//前缀太短会误伤正常密码
+const MIN_BREACHED_PREFIX_LENGTH: usize = 10;
+//用户名/邮箱太短的话不检查(比如密码里碰巧有"abc")
+const MIN_PERSONAL_INFO_LENGTH: usize = 3;
+//键盘上挨着的字符也算连续字符
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+pub struct PasswordPolicy {
+    settings: PasswordPolicySettings,
+    //按前缀长度分组：查的时候每种长度各截一次摘要
+    breached: HashMap<usize, HashSet<String>>,
+}
+
+impl PasswordPolicy {
+    pub fn from_settings(settings: &PasswordPolicySettings) -> anyhow::Result<Self> {
+        let mut policy = Self {
+            settings: settings.clone(),
+            breached: HashMap::new(),
+        };
+        policy.load_breached_list(BUNDLED_BREACHED_LIST);
+        if let Some(path) = &settings.breached_list_path {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("读取泄露密码列表失败:{path}:{e}"))?;
+            policy.load_breached_list(&content);
+        }
+        Ok(policy)
+    }
+
+    //每行一个SHA-1前缀，#开头是注释；兼容HIBP的"摘要:次数"格式
+    fn load_breached_list(&mut self, content: &str) {
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let prefix = line
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_uppercase();
+            if !(MIN_BREACHED_PREFIX_LENGTH..=40).contains(&prefix.len())
+                || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                continue;
+            }
+            self.breached
+                .entry(prefix.len())
+                .or_default()
+                .insert(prefix);
+        }
+    }
+
+    //密码是不是在泄露密码列表里
+    pub fn is_breached(&self, password: &str) -> bool {
+        let digest = HEXUPPER.encode(&Sha1::digest(password.as_bytes()));
+        self.breached
+            .iter()
+            .any(|(len, prefixes)| prefixes.contains(&digest[..*len]))
+    }
+
+    //检查新密码，返回所有不满足的规则(空表示通过)
+    //personal_info:用户名、邮箱等，密码里不能包含它们
+    pub fn check(&self, password: &str, personal_info: &[&str]) -> Vec<ErrorCode> {
+        let settings = &self.settings;
+        let mut errors = Vec::new();
+
+        let length = password.chars().count();
+        if length < settings.min_length {
+            errors.push(ErrorCode::PasswordTooShort);
+        } else if length > settings.max_length {
+            //太长的密码后面的检查都没有意义
+            errors.push(ErrorCode::PasswordTooLong);
+            return errors;
+        }
+
+        if char_classes(password) < settings.min_char_classes {
+            errors.push(ErrorCode::PasswordCharClasses);
+        }
+
+        if settings.reject_personal_info && contains_personal_info(password, personal_info) {
+            errors.push(ErrorCode::PasswordContainsPersonalInfo);
+        }
+
+        //泄露过的密码强度再高也没用，两个只报一个
+        if self.is_breached(password) {
+            errors.push(ErrorCode::PasswordBreached);
+        } else if estimate_strength(password) < settings.min_strength {
+            errors.push(ErrorCode::PasswordTooWeak);
+        }
+
+        errors
+    }
+}
+
+//包含几种字符：小写字母、大写字母、数字、其他(符号/空格/中文等)
+fn char_classes(password: &str) -> usize {
+    let lower = password.chars().any(|c| c.is_ascii_lowercase());
+    let upper = password.chars().any(|c| c.is_ascii_uppercase());
+    let digit = password.chars().any(|c| c.is_ascii_digit());
+    let other = password.chars().any(|c| !c.is_ascii_alphanumeric());
+    [lower, upper, digit, other]
+        .into_iter()
+        .filter(|v| *v)
+        .count()
+}
+
+//密码里有没有用户名、邮箱(或者邮箱@前面的部分)，不区分大小写
+fn contains_personal_info(password: &str, personal_info: &[&str]) -> bool {
+    let password = password.to_lowercase();
+    personal_info
+        .iter()
+        .flat_map(|info| {
+            let local_part = info.split_once('@').map(|(local, _)| local);
+            std::iter::once(*info).chain(local_part)
+        })
+        .map(str::to_lowercase)
+        .filter(|info| info.chars().count() >= MIN_PERSONAL_INFO_LENGTH)
+        .any(|info| password.contains(&info))
+}
+
+//粗略估算密码强度，0-4(分档和zxcvbn一样：0太容易猜，4很难猜)
+//每个字符的熵按用到的字符种类算；重复字符、连续字符(abc、321、qwer)只算四分之一
+pub fn estimate_strength(password: &str) -> u8 {
+    let chars = password.chars().collect::<Vec<_>>();
+
+    let mut pool = 0u32;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if chars
+        .iter()
+        .any(|c| c.is_ascii() && !c.is_ascii_alphanumeric())
+    {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0;
+    }
+
+    let effective_length: f64 = chars
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| match i.checked_sub(1).map(|j| chars[j]) {
+            Some(prev) if prev == c || is_sequential(prev, c) => 0.25,
+            _ => 1.0,
+        })
+        .sum();
+    let bits = effective_length * f64::from(pool).log2();
+
+    match bits {
+        b if b < 20.0 => 0,
+        b if b < 30.0 => 1,
+        b if b < 40.0 => 2,
+        b if b < 50.0 => 3,
+        _ => 4,
+    }
+}
+
+//两个字符是不是挨着：编码相邻(ab、21)或者键盘上相邻(qw、lk)
+fn is_sequential(prev: char, c: char) -> bool {
+    let (prev, c) = (prev.to_ascii_lowercase(), c.to_ascii_lowercase());
+    if (c as i64 - prev as i64).abs() == 1 {
+        return true;
+    }
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| match (row.find(prev), row.find(c)) {
+            (Some(a), Some(b)) => a.abs_diff(b) == 1,
+            _ => false,
+        })
+}
+
 //JWT部分（登录token)
 //JWT是什么：
-//JWT是一串字符串(token)，服务器使用secret签名，客户端每次发请求时带上它，后端就可以验证身份了
+//JWT是一串字符串(token)，服务器用密钥签名(密钥见jwt_keys)，客户端每次发请求时带上它，后端就可以验证身份了
 //issue(签发)：登录成功->给token
 //verify(校验)：请求带token->验证签名->得到user_id
-//注意：JWT不能用来存放密码之类的敏感信息，因为payload时可读的，所以只存放用户id和过期时间
-#[derive(Debug, Serialize, Deserialize)]
+//注意：JWT不能用来存放密码之类的敏感信息，因为payload时可读的，所以只存放用户id、时间和签发方这些信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     //subject:用来存放用户id
     pub sub: i64,
+    //issuer:签发方，audience:接收方(都来自配置)，校验时必须和配置一致
+    pub iss: String,
+    pub aud: String,
     //过期时间:Unix时间戳
     pub exp: usize,
+    //生效时间:Unix时间戳，在这之前token无效(等于签发时间)
+    pub nbf: usize,
+    //签发时间:Unix时间戳(按用户吊销时用来判断token是不是吊销之前签发的)
+    pub iat: usize,
+    //token的唯一id：登出时把它记进吊销列表
+    pub jti: String,
+    //所属的登录会话id：会话被删除后这个token立即失效(两步验证的中间token没有会话)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    //用户的角色(签发时从库里查的)，require_role用它判断权限
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    //token类型：同一把密钥签出来的不同用途的token不能混用
+    pub typ: TokenType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    //正常的access token
+    Access,
+    //密码已通过、还差两步验证的中间token，只能拿去换access token
+    MfaChallenge,
 }
 
 //签发token
-pub fn issue_jwt(secret: &str, expire_seconds: i64, user_id: i64) -> anyhow::Result<String> {
+pub fn issue_jwt(
+    keys: &JwtKeys,
+    expire_seconds: i64,
+    user_id: i64,
+    session_id: &str,
+    roles: &[String],
+) -> anyhow::Result<String> {
+    encode_claims(
+        keys,
+        expire_seconds,
+        user_id,
+        Some(session_id),
+        roles,
+        TokenType::Access,
+    )
+}
+
+//校验token部分：成功则返回claim
+//失败原因一般有：token被篡改(验证签名失败)、token过期(exp超时)、kid对应的公钥已经删掉了、类型不对
+pub fn verify_jwt(keys: &JwtKeys, token: &str) -> anyhow::Result<Claims> {
+    decode_claims(keys, token, TokenType::Access)
+}
+
+//签发两步验证的中间token
+pub fn issue_mfa_challenge(
+    keys: &JwtKeys,
+    expire_seconds: i64,
+    user_id: i64,
+) -> anyhow::Result<String> {
+    encode_claims(
+        keys,
+        expire_seconds,
+        user_id,
+        None,
+        &[],
+        TokenType::MfaChallenge,
+    )
+}
+
+//校验两步验证的中间token
+pub fn verify_mfa_challenge(keys: &JwtKeys, token: &str) -> anyhow::Result<Claims> {
+    decode_claims(keys, token, TokenType::MfaChallenge)
+}
+
+fn encode_claims(
+    keys: &JwtKeys,
+    expire_seconds: i64,
+    user_id: i64,
+    session_id: Option<&str>,
+    roles: &[String],
+    typ: TokenType,
+) -> anyhow::Result<String> {
     let now = chrono::Utc::now().timestamp(); //当前秒级时间戳
     let exp = now + expire_seconds;
 
     let claims = Claims {
         sub: user_id,
+        iss: keys.issuer().to_string(),
+        aud: keys.audience().to_string(),
         exp: exp as usize,
+        nbf: now as usize,
+        iat: now as usize,
+        jti: generate_opaque_token(),
+        sid: session_id.map(str::to_string),
+        roles: roles.to_vec(),
+        typ,
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .map_err(|e| anyhow::anyhow!("jwt encode failed:{e}"))?;
+    keys.encode(&claims)
+}
+
+fn decode_claims(keys: &JwtKeys, token: &str, expected: TokenType) -> anyhow::Result<Claims> {
+    let claims = keys.decode::<Claims>(token)?;
+
+    if claims.typ != expected {
+        anyhow::bail!("jwt type mismatch:{:?}", claims.typ);
+    }
 
-    Ok(token)
+    Ok(claims)
 }
 
-//校验token部分：成功则返回claim
-//失败原因一般有：token被篡改(验证签名失败)、token过期(exp超时)、secret不一致
-pub fn verify_jwt(secret: &str, token: &str) -> anyhow::Result<Claims> {
-    let data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map_err(|e| anyhow::anyhow!("jwt decode falied:{e}"))?;
+//不透明令牌部分(refresh token等)
+//和JWT不同，它本身不带任何信息，只是一串足够长的随机数
+//服务端只存它的SHA-256摘要，数据库泄露也拿不到可用的明文
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+//计算不透明令牌的摘要(小写hex，64位)
+//随机数本身熵足够，不需要像密码那样用argon2慢哈希
+pub fn hash_opaque_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //测试里用最小的参数，哈希快一些
+    fn settings() -> Argon2Settings {
+        Argon2Settings {
+            memory_kib: 8,
+            time_cost: 1,
+            parallelism: 1,
+            pepper: None,
+            old_peppers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn verifies_hashes_made_with_a_rotated_pepper() {
+        let old = Argon2Settings {
+            pepper: Some("old-pepper".to_string()),
+            ..settings()
+        };
+        let hash = hash_password(&old, "pass123").unwrap();
+
+        //换了pepper，旧的放进old_peppers：还能校验，并且要按新pepper重新哈希
+        let rotated = Argon2Settings {
+            pepper: Some("new-pepper".to_string()),
+            old_peppers: vec!["old-pepper".to_string()],
+            ..settings()
+        };
+        assert!(verify_password(&rotated, "pass123", &hash).unwrap());
+        assert!(!verify_password(&rotated, "pass124", &hash).unwrap());
+        assert!(needs_rehash(&rotated, &hash));
+
+        let rehashed = hash_password(&rotated, "pass123").unwrap();
+        assert!(verify_password(&rotated, "pass123", &rehashed).unwrap());
+        assert!(!needs_rehash(&rotated, &rehashed));
+    }
+
+    #[test]
+    fn unknown_pepper_is_a_mismatch_not_an_error() {
+        let peppered = Argon2Settings {
+            pepper: Some("lost-pepper".to_string()),
+            ..settings()
+        };
+        let hash = hash_password(&peppered, "pass123").unwrap();
+
+        for settings in [
+            settings(),
+            Argon2Settings {
+                pepper: Some("other-pepper".to_string()),
+                ..settings()
+            },
+        ] {
+            assert!(!verify_password(&settings, "pass123", &hash).unwrap());
+            assert_eq!(
+                verify_user_password(&settings, "pass123", &hash).unwrap(),
+                PasswordMatch::Mismatched
+            );
+        }
+    }
+
+    #[test]
+    fn matches_hashes_of_normalized_password() {
+        let settings = settings();
+        let hash = hash_password(&settings, "pass123").unwrap();
+
+        for input in ["pass123", "ｐａｓｓ１２３"] {
+            assert_eq!(
+                verify_user_password(&settings, input, &hash).unwrap(),
+                PasswordMatch::Matched,
+                "{input}"
+            );
+        }
+        assert_eq!(
+            verify_user_password(&settings, "pass124", &hash).unwrap(),
+            PasswordMatch::Mismatched
+        );
+    }
+
+    #[test]
+    fn falls_back_to_raw_input_for_legacy_hashes() {
+        let settings = settings();
+        //规范化上线之前，全角密码是原样哈希的
+        let legacy = hash_password(&settings, "ｐａｓｓ１２３").unwrap();
+
+        assert_eq!(
+            verify_user_password(&settings, "ｐａｓｓ１２３", &legacy).unwrap(),
+            PasswordMatch::Legacy
+        );
+        //原样输入不一样就不能靠回退对上
+        assert_eq!(
+            verify_user_password(&settings, "pass123", &legacy).unwrap(),
+            PasswordMatch::Mismatched
+        );
 
-    Ok(data.claims)
+        //按规范化之后的密码重新哈希之后就是普通的匹配了
+        let rehashed =
+            hash_password(&settings, &validation::normalize_password("ｐａｓｓ１２３")).unwrap();
+        assert_eq!(
+            verify_user_password(&settings, "ｐａｓｓ１２３", &rehashed).unwrap(),
+            PasswordMatch::Matched
+        );
+    }
 }
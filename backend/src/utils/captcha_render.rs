@@ -0,0 +1,374 @@
+//验证码的几种形式：PNG图片、SVG图片、算术题，PNG还可以附带同一个答案的WAV语音(给看不清图片的用户)
+//PNG和语音用captcha crate生成；SVG用下面内置的笔画字体自己画
+//SVG里每个字符都是一条折线路径，答案不会以文本的形式出现在SVG里
+use std::io::Cursor;
+
+use base64::{Engine as _, engine::general_purpose};
+use captcha::filters::{Dots, Grid, Noise, Wave};
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::config::settings::{CaptchaConfig, CaptchaDifficulty};
+
+//验证码形式：GET /api/captcha?kind=...
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptchaKind {
+    //PNG图片(默认)
+    Image,
+    //SVG图片，比base64的PNG小很多
+    Svg,
+    //算术题，答案是计算结果，题目画成SVG
+    Math,
+    //PNG图片+同一个答案的WAV语音
+    Audio,
+}
+
+impl CaptchaKind {
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "image" => Some(Self::Image),
+            "svg" => Some(Self::Svg),
+            "math" => Some(Self::Math),
+            "audio" => Some(Self::Audio),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Svg => "svg",
+            Self::Math => "math",
+            Self::Audio => "audio",
+        }
+    }
+}
+
+//生成好的验证码
+pub struct RenderedCaptcha {
+    pub answer: String,
+    //data URL，前端可以直接放进<img src>
+    pub image: String,
+    //data URL(audio/wav)，只有语音验证码才有
+    pub audio: Option<String>,
+}
+
+//按形式和配置生成验证码
+//CPU密集(语音要解码、加噪声)，调用方应该放到spawn_blocking里执行
+pub fn render(kind: CaptchaKind, config: &CaptchaConfig) -> anyhow::Result<RenderedCaptcha> {
+    match kind {
+        CaptchaKind::Image => render_png(config, false),
+        CaptchaKind::Audio => render_png(config, true),
+        CaptchaKind::Svg => {
+            let answer = random_svg_text(config);
+            let image = data_url("image/svg+xml", render_svg(config, &answer).as_bytes());
+            Ok(RenderedCaptcha {
+                answer,
+                image,
+                audio: None,
+            })
+        }
+        CaptchaKind::Math => {
+            let (question, answer) = math_question(config.difficulty);
+            let image = data_url("image/svg+xml", render_svg(config, &question).as_bytes());
+            Ok(RenderedCaptcha {
+                answer,
+                image,
+                audio: None,
+            })
+        }
+    }
+}
+
+//用captcha crate画PNG
+//噪点和网格加在整张画布上，再做波浪扭曲，裁成目标尺寸之后撒干扰圆点
+//各难度的参数参考captcha crate自带的样例，按60像素左右的图片高度调小了扭曲幅度
+fn render_png(config: &CaptchaConfig, with_audio: bool) -> anyhow::Result<RenderedCaptcha> {
+    let (noise, grid, wave, dots) = match config.difficulty {
+        CaptchaDifficulty::Easy => (0.1, 8, None, 0),
+        CaptchaDifficulty::Medium => (0.3, 6, Some(4.0), 8),
+        CaptchaDifficulty::Hard => (0.5, 4, Some(8.0), 15),
+    };
+
+    let mut cap = captcha::Captcha::new();
+    cap.set_chars(&config.charset).add_chars(config.length);
+    let answer = cap.chars_as_string(); //取出答案后存储
+
+    cap.apply_filter(Noise::new(noise))
+        .apply_filter(Grid::new(grid, grid));
+    if let Some(amplitude) = wave {
+        cap.apply_filter(Wave::new(2.0, amplitude));
+    }
+    cap.view(config.width, config.height);
+    if dots > 0 {
+        cap.apply_filter(Dots::new(dots).min_radius(2).max_radius(5));
+    }
+
+    let png = cap
+        .as_png()
+        .ok_or_else(|| anyhow::anyhow!("生成验证码图片失败"))?;
+    let audio = if with_audio {
+        Some(data_url("audio/wav", &join_wav(cap.as_wav())?))
+    } else {
+        None
+    };
+
+    Ok(RenderedCaptcha {
+        answer,
+        image: data_url("image/png", &png),
+        audio,
+    })
+}
+
+//captcha crate给每个字符单独生成一段WAV(已经加了噪声并补齐到同样长度)，按顺序拼成一段
+fn join_wav(clips: Vec<Option<Vec<u8>>>) -> anyhow::Result<Vec<u8>> {
+    let mut spec = None;
+    let mut samples = Vec::new();
+    for clip in clips {
+        let clip = clip.ok_or_else(|| anyhow::anyhow!("验证码字符没有对应的语音"))?;
+        let reader = hound::WavReader::new(clip.as_slice())?;
+        spec.get_or_insert(reader.spec());
+        for sample in reader.into_samples::<i16>() {
+            samples.push(sample?);
+        }
+    }
+    let spec = spec.ok_or_else(|| anyhow::anyhow!("验证码里没有字符"))?;
+
+    let mut cursor = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut cursor, spec)?;
+    for sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(cursor.into_inner())
+}
+
+//算术题：返回(题目, 答案)，减法保证结果不是负数
+fn math_question(difficulty: CaptchaDifficulty) -> (String, String) {
+    let mut rng = rand::thread_rng();
+    let op = match difficulty {
+        CaptchaDifficulty::Easy => '+',
+        CaptchaDifficulty::Medium => *['+', '-'].choose(&mut rng).expect("非空"),
+        CaptchaDifficulty::Hard => *['+', '-', '×'].choose(&mut rng).expect("非空"),
+    };
+    let max = match difficulty {
+        CaptchaDifficulty::Easy => 9,
+        CaptchaDifficulty::Medium => 20,
+        CaptchaDifficulty::Hard => 50,
+    };
+
+    let (a, b, answer) = match op {
+        '+' => {
+            let (a, b) = (rng.gen_range(1..=max), rng.gen_range(1..=max));
+            (a, b, a + b)
+        }
+        '-' => {
+            let (a, b) = (rng.gen_range(1..=max), rng.gen_range(1..=max));
+            let (a, b) = (a.max(b), a.min(b));
+            (a, b, a - b)
+        }
+        _ => {
+            let (a, b) = (rng.gen_range(2..=9), rng.gen_range(2..=9));
+            (a, b, a * b)
+        }
+    };
+    (format!("{a}{op}{b}=?"), answer.to_string())
+}
+
+//SVG验证码的字符：配置的字符集里笔画字体能画的(字体只有大写，答案不区分大小写)
+fn random_svg_text(config: &CaptchaConfig) -> String {
+    let mut charset: Vec<char> = Vec::new();
+    for c in config.charset.iter().map(char::to_ascii_uppercase) {
+        if SVG_CHARSET.contains(c) && !charset.contains(&c) {
+            charset.push(c);
+        }
+    }
+    if charset.is_empty() {
+        charset = SVG_CHARSET.chars().collect();
+    }
+
+    let mut rng = rand::thread_rng();
+    (0..config.length)
+        .map(|_| *charset.choose(&mut rng).expect("非空"))
+        .collect()
+}
+
+//把文字画成SVG：每个字符随机旋转、缩放、抖动笔画，再加几条干扰笔画
+//所有笔画(字符的和干扰的)打乱顺序放进同一个<path>，颜色、粗细一样，干扰笔画也是M/L折线：
+//不能按元素顺序拿到答案，也不能按元素类型或颜色把干扰过滤掉
+fn render_svg(config: &CaptchaConfig, text: &str) -> String {
+    //(最大旋转角度, 笔画抖动幅度(字体坐标), 干扰笔画条数)
+    let (max_rotate, jitter, noise) = match config.difficulty {
+        CaptchaDifficulty::Easy => (10.0_f64, 0.1, 4),
+        CaptchaDifficulty::Medium => (20.0, 0.2, 8),
+        CaptchaDifficulty::Hard => (30.0, 0.3, 14),
+    };
+    let (width, height) = (config.width as f64, config.height as f64);
+    let chars: Vec<char> = text.chars().collect();
+    //每个字符占一格，左右各留半格
+    let slot = width / (chars.len() as f64 + 1.0);
+    let scale = (slot * 0.8 / GLYPH_WIDTH).min(height * 0.65 / GLYPH_HEIGHT);
+    let stroke_width = scale * 0.45;
+
+    let mut rng = rand::thread_rng();
+    //每一笔是一串点(像素坐标)
+    let mut strokes: Vec<Vec<(f64, f64)>> = Vec::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        let Some(glyph_strokes) = glyph(*c) else {
+            continue;
+        };
+        let center_x = slot * (i as f64 + 1.0) + rng.gen_range(-0.1..=0.1) * slot;
+        let center_y = height / 2.0 + rng.gen_range(-0.08..=0.08) * height;
+        let glyph_scale = scale * rng.gen_range(0.9..=1.1);
+        let (sin, cos) = rng
+            .gen_range(-max_rotate..=max_rotate)
+            .to_radians()
+            .sin_cos();
+
+        for stroke in glyph_strokes.split('|') {
+            let points = stroke
+                .split(' ')
+                .map(|point| {
+                    let (x, y) = point.split_once(',').expect("字体数据格式固定");
+                    //以字符中心为原点，加抖动后旋转、缩放、平移到字符的位置
+                    let x = x.parse::<f64>().expect("字体数据格式固定") - GLYPH_WIDTH / 2.0
+                        + rng.gen_range(-jitter..=jitter);
+                    let y = y.parse::<f64>().expect("字体数据格式固定") - GLYPH_HEIGHT / 2.0
+                        + rng.gen_range(-jitter..=jitter);
+                    (
+                        center_x + (x * cos - y * sin) * glyph_scale,
+                        center_y + (x * sin + y * cos) * glyph_scale,
+                    )
+                })
+                .collect();
+            strokes.push(points);
+        }
+    }
+
+    //干扰笔画：和字符笔画差不多长的2-3段折线，随机散在整张图上
+    for _ in 0..noise {
+        let mut point = (rng.gen_range(0.0..width), rng.gen_range(0.0..height));
+        let mut points = vec![point];
+        for _ in 0..rng.gen_range(2..=3) {
+            let (sin, cos) = rng.gen_range(0.0..std::f64::consts::TAU).sin_cos();
+            let length = scale * rng.gen_range(1.5..=3.5);
+            point = (point.0 + cos * length, point.1 + sin * length);
+            points.push(point);
+        }
+        strokes.push(points);
+    }
+
+    //打乱笔画顺序，每一笔也随机决定正着画还是倒着画
+    strokes.shuffle(&mut rng);
+    let mut d = String::new();
+    for mut points in strokes {
+        if rng.gen_bool(0.5) {
+            points.reverse();
+        }
+        for (j, (x, y)) in points.iter().enumerate() {
+            d.push_str(&format!("{}{x:.1} {y:.1}", if j == 0 { "M" } else { "L" }));
+        }
+    }
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}"><rect width="100%" height="100%" fill="#fff"/><path d="{d}" fill="none" stroke="{color}" stroke-width="{stroke_width:.1}" stroke-linecap="round" stroke-linejoin="round"/></svg>"##,
+        w = config.width,
+        h = config.height,
+        color = random_color(&mut rng),
+    )
+}
+
+//深色的随机颜色，白底上看得清
+fn random_color(rng: &mut impl Rng) -> String {
+    format!(
+        "hsl({},60%,{}%)",
+        rng.gen_range(0..360),
+        rng.gen_range(20..=40)
+    )
+}
+
+fn data_url(mime: &str, bytes: &[u8]) -> String {
+    format!(
+        "data:{mime};base64,{}",
+        general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+//笔画字体：每个字符在4x6的格子里(y朝下)，笔画之间用|分隔，每笔是一串折线的点
+//字母和数字去掉了容易认错的0/O/1/I，但算术题要用到0和1，所以字体里有
+const GLYPH_WIDTH: f64 = 4.0;
+const GLYPH_HEIGHT: f64 = 6.0;
+const SVG_CHARSET: &str = "23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+fn glyph(c: char) -> Option<&'static str> {
+    let strokes = match c {
+        '0' => "1,0 3,0 4,1 4,5 3,6 1,6 0,5 0,1 1,0|4,1 0,5",
+        '1' => "1,1 2,0 2,6|1,6 3,6",
+        '2' => "0,1 1,0 3,0 4,1 4,2 0,6 4,6",
+        '3' => "0,0 4,0 2,2 3,2 4,3 4,5 3,6 1,6 0,5",
+        '4' => "3,6 3,0 0,4 4,4",
+        '5' => "4,0 0,0 0,3 3,3 4,4 4,5 3,6 0,6",
+        '6' => "3,0 1,0 0,1 0,5 1,6 3,6 4,5 4,4 3,3 0,3",
+        '7' => "0,0 4,0 1,6",
+        '8' => "1,0 3,0 4,1 4,2 3,3 1,3 0,4 0,5 1,6 3,6 4,5 4,4 3,3|1,3 0,2 0,1 1,0",
+        '9' => "4,3 1,3 0,2 0,1 1,0 3,0 4,1 4,5 3,6 1,6",
+        'A' => "0,6 2,0 4,6|1,3 3,3",
+        'B' => "0,0 0,6 3,6 4,5 4,4 3,3 0,3|0,0 3,0 4,1 4,2 3,3",
+        'C' => "4,1 3,0 1,0 0,1 0,5 1,6 3,6 4,5",
+        'D' => "0,0 0,6 2,6 4,4 4,2 2,0 0,0",
+        'E' => "4,0 0,0 0,6 4,6|0,3 3,3",
+        'F' => "4,0 0,0 0,6|0,3 3,3",
+        'G' => "4,1 3,0 1,0 0,1 0,5 1,6 3,6 4,5 4,3 2,3",
+        'H' => "0,0 0,6|4,0 4,6|0,3 4,3",
+        'J' => "4,0 4,5 3,6 1,6 0,5",
+        'K' => "0,0 0,6|4,0 0,4|1,3 4,6",
+        'L' => "0,0 0,6 4,6",
+        'M' => "0,6 0,0 2,3 4,0 4,6",
+        'N' => "0,6 0,0 4,6 4,0",
+        'P' => "0,6 0,0 3,0 4,1 4,2 3,3 0,3",
+        'Q' => "1,0 3,0 4,1 4,5 3,6 1,6 0,5 0,1 1,0|2,4 4,6",
+        'R' => "0,6 0,0 3,0 4,1 4,2 3,3 0,3|2,3 4,6",
+        'S' => "4,1 3,0 1,0 0,1 0,2 1,3 3,3 4,4 4,5 3,6 1,6 0,5",
+        'T' => "0,0 4,0|2,0 2,6",
+        'U' => "0,0 0,5 1,6 3,6 4,5 4,0",
+        'V' => "0,0 2,6 4,0",
+        'W' => "0,0 1,6 2,3 3,6 4,0",
+        'X' => "0,0 4,6|4,0 0,6",
+        'Y' => "0,0 2,3 4,0|2,3 2,6",
+        'Z' => "0,0 4,0 0,6 4,6",
+        '+' => "2,1 2,5|0,3 4,3",
+        '-' => "0,3 4,3",
+        '×' => "1,2 3,4|3,2 1,4",
+        '=' => "0,2 4,2|0,4 4,4",
+        '?' => "0,1 1,0 3,0 4,1 4,2 2,3 2,4|2,6 2,6",
+        _ => return None,
+    };
+    Some(strokes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_is_one_path_of_straight_strokes() {
+        let config = CaptchaConfig {
+            difficulty: CaptchaDifficulty::Hard,
+            ..CaptchaConfig::default()
+        };
+        let svg = render_svg(&config, "AB");
+
+        assert_eq!(svg.matches("<path").count(), 1);
+        let (_, d) = svg.split_once(r#" d=""#).unwrap();
+        let d = &d[..d.find('"').unwrap()];
+        assert!(d.chars().all(|c| "ML0123456789. -".contains(c)), "{d}");
+
+        //A有两笔、B有两笔，再加上干扰笔画
+        let glyph_strokes = "AB"
+            .chars()
+            .map(|c| glyph(c).unwrap().split('|').count())
+            .sum::<usize>();
+        assert_eq!(d.matches('M').count(), glyph_strokes + 14);
+    }
+}
@@ -0,0 +1,453 @@
+//验证码存储：captcha_id -> 正确答案+过期时间
+//做成trait是为了可以替换实现：单实例用进程内存，多实例部署用MySQL或Redis共享
+//verify_and_consume必须是原子的：同一个验证码并发提交多次，最多只有一次能通过
+//防滥用：
+// 1.同一个验证码猜错max_attempts次就作废，不能一直试到过期
+// 2.总数超过capacity时按过期时间从早到晚淘汰(有效期一样时就是最早生成的)
+// 3.同一个IP同时持有的验证码超过per_ip_limit时，淘汰这个IP最早的
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::Script;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use sqlx::{MySql, Pool};
+
+use crate::config::settings::{CaptchaBackend, CaptchaSettings};
+use crate::services::captcha_service;
+
+//单条验证码记录：存储正确答案+过期时间+是哪个IP要的
+#[derive(Clone, Debug)]
+pub struct CaptchaEntry {
+    pub answer: String,
+    pub expires_at: DateTime<Utc>,
+    pub ip: String,
+}
+
+#[async_trait]
+pub trait CaptchaStore: Send + Sync {
+    //保存一条验证码记录，超出上限时先淘汰旧的
+    async fn insert(&self, id: &str, entry: CaptchaEntry) -> anyhow::Result<()>;
+    //验证码校验
+    //找不到/过期 -> false
+    //不匹配 -> false，猜错次数+1，达到上限就删除
+    //匹配 -> true，并且删除(一个验证码只能用一次)
+    async fn verify_and_consume(&self, id: &str, user_input: &str) -> anyhow::Result<bool>;
+    //清理过期的验证码(后台任务定时调用)
+    async fn cleanup_expired(&self) -> anyhow::Result<()>;
+}
+
+//校验答案时忽略大小写+去空格：存和比较之前都转一下
+fn normalize_answer(answer: &str) -> String {
+    answer.trim().to_ascii_lowercase()
+}
+
+//进程内存里的一条记录
+struct MemoryEntry {
+    entry: CaptchaEntry,
+    attempts: u32,
+}
+
+//按(过期时间, id)排序，淘汰时从最前面取
+type ExpiryIndex = BTreeSet<(DateTime<Utc>, String)>;
+
+#[derive(Default)]
+struct MemoryInner {
+    entries: HashMap<String, MemoryEntry>,
+    order: ExpiryIndex,
+    by_ip: HashMap<String, ExpiryIndex>,
+}
+
+impl MemoryInner {
+    //删除一条记录，三个索引一起更新
+    fn remove(&mut self, id: &str) -> Option<MemoryEntry> {
+        let record = self.entries.remove(id)?;
+        let key = (record.entry.expires_at, id.to_string());
+        self.order.remove(&key);
+        if let Some(index) = self.by_ip.get_mut(&record.entry.ip) {
+            index.remove(&key);
+            if index.is_empty() {
+                self.by_ip.remove(&record.entry.ip);
+            }
+        }
+        Some(record)
+    }
+}
+
+//存在进程内存，重启后丢失
+//数量和IP要一起维护，所以用一把锁保护全部数据(锁里没有await)
+pub struct MemoryCaptchaStore {
+    settings: CaptchaSettings,
+    inner: Mutex<MemoryInner>,
+}
+
+impl MemoryCaptchaStore {
+    pub fn new(settings: CaptchaSettings) -> Self {
+        Self {
+            settings,
+            inner: Mutex::new(MemoryInner::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl CaptchaStore for MemoryCaptchaStore {
+    async fn insert(&self, id: &str, entry: CaptchaEntry) -> anyhow::Result<()> {
+        let mut inner = self.inner.lock().expect("验证码存储的锁被污染");
+        inner.remove(id);
+
+        //先按IP淘汰，再按总数淘汰
+        while let Some((_, oldest)) = inner
+            .by_ip
+            .get(&entry.ip)
+            .filter(|index| index.len() >= self.settings.per_ip_limit)
+            .and_then(|index| index.first().cloned())
+        {
+            inner.remove(&oldest);
+        }
+        while inner.entries.len() >= self.settings.capacity {
+            let Some((_, oldest)) = inner.order.first().cloned() else {
+                break;
+            };
+            inner.remove(&oldest);
+        }
+
+        let key = (entry.expires_at, id.to_string());
+        inner.order.insert(key.clone());
+        inner.by_ip.entry(entry.ip.clone()).or_default().insert(key);
+        inner.entries.insert(
+            id.to_string(),
+            MemoryEntry {
+                entry: CaptchaEntry {
+                    answer: normalize_answer(&entry.answer),
+                    ..entry
+                },
+                attempts: 0,
+            },
+        );
+        Ok(())
+    }
+
+    async fn verify_and_consume(&self, id: &str, user_input: &str) -> anyhow::Result<bool> {
+        let now = Utc::now();
+        let input = normalize_answer(user_input);
+
+        let mut inner = self.inner.lock().expect("验证码存储的锁被污染");
+        let Some(record) = inner.entries.get_mut(id) else {
+            return Ok(false);
+        };
+
+        //过期了：删掉
+        if record.entry.expires_at < now {
+            inner.remove(id);
+            return Ok(false);
+        }
+
+        if record.entry.answer == input {
+            inner.remove(id);
+            return Ok(true);
+        }
+
+        record.attempts += 1;
+        if record.attempts >= self.settings.max_attempts {
+            inner.remove(id);
+        }
+        Ok(false)
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let mut inner = self.inner.lock().expect("验证码存储的锁被污染");
+        let expired = inner
+            .order
+            .iter()
+            .take_while(|(expires_at, _)| *expires_at < now)
+            .map(|(_, id)| id.clone())
+            .collect::<Vec<_>>();
+        for id in expired {
+            inner.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+//存在MySQL的captchas表，多个实例共享
+//数量上限是插入前先删再插，并发插入时可能短暂多出几条
+pub struct MySqlCaptchaStore {
+    settings: CaptchaSettings,
+    db: Pool<MySql>,
+}
+
+impl MySqlCaptchaStore {
+    pub fn new(settings: CaptchaSettings, db: Pool<MySql>) -> Self {
+        Self { settings, db }
+    }
+}
+
+#[async_trait]
+impl CaptchaStore for MySqlCaptchaStore {
+    async fn insert(&self, id: &str, entry: CaptchaEntry) -> anyhow::Result<()> {
+        captcha_service::trim_captchas_by_ip(&self.db, &entry.ip, self.settings.per_ip_limit - 1)
+            .await?;
+        captcha_service::trim_captchas(&self.db, self.settings.capacity - 1).await?;
+        captcha_service::insert_captcha(
+            &self.db,
+            id,
+            &normalize_answer(&entry.answer),
+            &entry.ip,
+            entry.expires_at,
+        )
+        .await
+    }
+
+    async fn verify_and_consume(&self, id: &str, user_input: &str) -> anyhow::Result<bool> {
+        captcha_service::consume_captcha(
+            &self.db,
+            id,
+            &normalize_answer(user_input),
+            self.settings.max_attempts,
+        )
+        .await
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        captcha_service::delete_expired_captchas(&self.db).await?;
+        Ok(())
+    }
+}
+
+//Redis里的key：所有验证码放在几个固定的key里，不按id/IP拼key
+//脚本用到的key全部通过KEYS传进去，key名带同一个hash tag({captcha})，Redis Cluster下落在同一个slot
+// {captcha}:answers    哈希表，id -> 答案
+// {captcha}:owners     哈希表，id -> 在IP索引里的成员(见ip_member)
+// {captcha}:attempts   哈希表，id -> 猜错次数
+// {captcha}:index      有序集合，所有验证码id，分数是过期时间(毫秒)
+// {captcha}:by_ip      有序集合，分数都是0，成员是"IP\0过期时间\0id"，按字典序就是按IP分组、组内按过期时间排
+const REDIS_KEYS: [&str; 5] = [
+    "{captcha}:answers",
+    "{captcha}:owners",
+    "{captcha}:attempts",
+    "{captcha}:index",
+    "{captcha}:by_ip",
+];
+
+//连接和单条命令的最长等待时间，Redis挂了不能把请求一直卡住
+const REDIS_TIMEOUT: Duration = Duration::from_secs(5);
+
+//每次写入时顺手清掉的过期验证码数量上限，剩下的交给后台清理
+const REDIS_INSERT_CLEANUP_BATCH: usize = 100;
+
+//几个脚本共用：删除一个验证码，五个key一起更新
+//KEYS: 同REDIS_KEYS
+const REDIS_REMOVE_FN: &str = r#"
+local function remove(id)
+  local member = redis.call('HGET', KEYS[2], id)
+  redis.call('HDEL', KEYS[1], id)
+  redis.call('HDEL', KEYS[2], id)
+  redis.call('HDEL', KEYS[3], id)
+  redis.call('ZREM', KEYS[4], id)
+  if member then redis.call('ZREM', KEYS[5], member) end
+end
+"#;
+
+//保存：先清掉一批过期的，再按IP、按总数淘汰最早的，最后写入
+//Redis执行脚本时不会插入别的命令，所以淘汰和写入是原子的
+//ARGV: id, 答案, 过期时间(毫秒), IP索引成员, IP范围下界, IP范围上界, 总数上限, IP上限, 当前时间(毫秒), 清理数量
+const REDIS_INSERT_SCRIPT: &str = r#"
+for _, old in ipairs(redis.call('ZRANGEBYSCORE', KEYS[4], '-inf', ARGV[9], 'LIMIT', 0, ARGV[10])) do
+  remove(old)
+end
+remove(ARGV[1])
+local excess = redis.call('ZLEXCOUNT', KEYS[5], ARGV[5], ARGV[6]) - tonumber(ARGV[8]) + 1
+if excess > 0 then
+  for _, member in ipairs(redis.call('ZRANGEBYLEX', KEYS[5], ARGV[5], ARGV[6], 'LIMIT', 0, excess)) do
+    remove(string.match(member, '([^%z]+)$'))
+  end
+end
+excess = redis.call('ZCARD', KEYS[4]) - tonumber(ARGV[7]) + 1
+if excess > 0 then
+  for _, old in ipairs(redis.call('ZRANGE', KEYS[4], 0, excess - 1)) do
+    remove(old)
+  end
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+redis.call('HSET', KEYS[2], ARGV[1], ARGV[4])
+redis.call('HSET', KEYS[3], ARGV[1], 0)
+redis.call('ZADD', KEYS[4], ARGV[3], ARGV[1])
+redis.call('ZADD', KEYS[5], 0, ARGV[4])
+return 1
+"#;
+
+//校验：过期的当作不存在；答案对返回1并删除；答案错猜错次数+1，达到上限也删除
+//ARGV: id, 用户输入, 猜错上限, 当前时间(毫秒)
+const REDIS_CONSUME_SCRIPT: &str = r#"
+local expires_at = redis.call('ZSCORE', KEYS[4], ARGV[1])
+if not expires_at then return 0 end
+if tonumber(expires_at) < tonumber(ARGV[4]) then
+  remove(ARGV[1])
+  return 0
+end
+local ok = redis.call('HGET', KEYS[1], ARGV[1]) == ARGV[2]
+if not ok and redis.call('HINCRBY', KEYS[3], ARGV[1], 1) < tonumber(ARGV[3]) then return 0 end
+remove(ARGV[1])
+if ok then return 1 end
+return 0
+"#;
+
+//清理：删除所有过期的验证码，返回删了几个
+//ARGV: 当前时间(毫秒)
+const REDIS_CLEANUP_SCRIPT: &str = r#"
+local expired = redis.call('ZRANGEBYSCORE', KEYS[4], '-inf', ARGV[1])
+for _, old in ipairs(expired) do
+  remove(old)
+end
+return #expired
+"#;
+
+//IP索引里的成员：IP\0过期时间(补零到15位，字典序和数值顺序一致)\0id
+fn ip_member(ip: &str, expires_ms: i64, id: &str) -> String {
+    format!("{ip}\0{expires_ms:015}\0{id}")
+}
+
+//IP索引里这个IP的全部成员的字典序范围：[IP\0, IP\x01)
+fn ip_range(ip: &str) -> (String, String) {
+    (format!("[{ip}\0"), format!("({ip}\x01"))
+}
+
+//连接Redis，带连接/命令超时；PoW随机串的存储也用这个
+pub(crate) async fn connect_redis(redis_url: &str) -> anyhow::Result<ConnectionManager> {
+    let client = redis::Client::open(redis_url)?;
+    let config = ConnectionManagerConfig::new()
+        .set_connection_timeout(Some(REDIS_TIMEOUT))
+        .set_response_timeout(Some(REDIS_TIMEOUT));
+    Ok(client.get_connection_manager_with_config(config).await?)
+}
+
+//存在Redis(或兼容RESP协议的服务)，多个实例共享
+//Redis里没有按条的过期时间：过期的验证码校验时当作不存在，写入时和后台任务里删除
+//ConnectionManager可以clone，clone出来的共用同一条连接，并发的命令不用互相等
+//出错(包括超时)后连接会被丢掉重建，不会把上一条命令的回复当成下一条的
+pub struct RedisCaptchaStore {
+    settings: CaptchaSettings,
+    conn: ConnectionManager,
+    insert_script: Script,
+    consume_script: Script,
+    cleanup_script: Script,
+}
+
+impl RedisCaptchaStore {
+    //按settings.redis_url连接，连不上时启动失败
+    pub async fn connect(settings: CaptchaSettings) -> anyhow::Result<Self> {
+        let conn = connect_redis(&settings.redis_url).await?;
+        //用EVALSHA执行，服务端没缓存脚本时自动SCRIPT LOAD
+        let script = |body: &str| Script::new(&format!("{REDIS_REMOVE_FN}{body}"));
+        Ok(Self {
+            settings,
+            conn,
+            insert_script: script(REDIS_INSERT_SCRIPT),
+            consume_script: script(REDIS_CONSUME_SCRIPT),
+            cleanup_script: script(REDIS_CLEANUP_SCRIPT),
+        })
+    }
+}
+
+//把REDIS_KEYS按顺序加到脚本调用上
+fn with_keys(script: &Script) -> redis::ScriptInvocation<'_> {
+    let mut invocation = script.prepare_invoke();
+    for key in REDIS_KEYS {
+        invocation.key(key);
+    }
+    invocation
+}
+
+#[async_trait]
+impl CaptchaStore for RedisCaptchaStore {
+    async fn insert(&self, id: &str, entry: CaptchaEntry) -> anyhow::Result<()> {
+        let now_ms = Utc::now().timestamp_millis();
+        let expires_ms = entry.expires_at.timestamp_millis();
+        let (ip_min, ip_max) = ip_range(&entry.ip);
+        with_keys(&self.insert_script)
+            .arg(id)
+            .arg(normalize_answer(&entry.answer))
+            .arg(expires_ms)
+            .arg(ip_member(&entry.ip, expires_ms, id))
+            .arg(ip_min)
+            .arg(ip_max)
+            .arg(self.settings.capacity)
+            .arg(self.settings.per_ip_limit)
+            .arg(now_ms)
+            .arg(REDIS_INSERT_CLEANUP_BATCH)
+            .invoke_async::<i64>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn verify_and_consume(&self, id: &str, user_input: &str) -> anyhow::Result<bool> {
+        let consumed = with_keys(&self.consume_script)
+            .arg(id)
+            .arg(normalize_answer(user_input))
+            .arg(self.settings.max_attempts)
+            .arg(Utc::now().timestamp_millis())
+            .invoke_async::<i64>(&mut self.conn.clone())
+            .await?;
+        Ok(consumed == 1)
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        with_keys(&self.cleanup_script)
+            .arg(Utc::now().timestamp_millis())
+            .invoke_async::<i64>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+}
+
+//根据配置创建验证码存储
+pub async fn build_captcha_store(
+    settings: &CaptchaSettings,
+    db: &Pool<MySql>,
+) -> anyhow::Result<Arc<dyn CaptchaStore>> {
+    Ok(match settings.backend {
+        CaptchaBackend::Memory => Arc::new(MemoryCaptchaStore::new(settings.clone())),
+        CaptchaBackend::Mysql => Arc::new(MySqlCaptchaStore::new(settings.clone(), db.clone())),
+        CaptchaBackend::Redis => Arc::new(RedisCaptchaStore::connect(settings.clone()).await?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //ZRANGEBYLEX按字节比较："[a"包含a本身，"(a"不包含
+    fn in_range(member: &str, (min, max): &(String, String)) -> bool {
+        member.as_bytes() >= &min.as_bytes()[1..] && member.as_bytes() < &max.as_bytes()[1..]
+    }
+
+    #[test]
+    fn ip_range_covers_only_that_ip() {
+        let range = ip_range("1.1.1.1");
+        assert!(in_range(&ip_member("1.1.1.1", 1, "a"), &range));
+        assert!(in_range(
+            &ip_member("1.1.1.1", i64::MAX / 1000, "z"),
+            &range
+        ));
+        //前缀相同的IP不能算进来
+        assert!(!in_range(&ip_member("1.1.1.10", 1, "a"), &range));
+        assert!(!in_range(&ip_member("1.1.1", 1, "a"), &range));
+    }
+
+    #[test]
+    fn ip_members_sort_by_expiry() {
+        let mut members = [
+            ip_member("::1", 1_700_000_000_000, "b"),
+            ip_member("::1", 999, "c"),
+            ip_member("::1", 1_700_000_000_001, "a"),
+        ];
+        members.sort();
+        assert!(members[0].ends_with("\0c"));
+        assert!(members[1].ends_with("\0b"));
+        assert!(members[2].ends_with("\0a"));
+    }
+}
@@ -0,0 +1,88 @@
+//从请求里取客户端信息(记录登录会话用)
+use salvo::prelude::*;
+
+//user_agent最多保存多少个字符(和表结构一致)
+const MAX_USER_AGENT_CHARS: usize = 255;
+
+//客户端IP
+//trusted_proxy_hops>0时从X-Forwarded-For取(部署在nginx/负载均衡后面)
+//每层代理都把它看到的对端地址追加到最右边，所以只有右边trusted_proxy_hops个是自己的代理写的，
+//再往左的都是客户端自己带来的，可以随便伪造：取右数第trusted_proxy_hops个
+//直接暴露在公网时保持0，否则客户端可以随便伪造
+pub fn client_ip(req: &Request, trusted_proxy_hops: usize) -> String {
+    let forwarded = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    if let Some(ip) = forwarded_ip(&forwarded, trusted_proxy_hops) {
+        return ip.to_string();
+    }
+
+    req.remote_addr()
+        .clone()
+        .into_std()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default()
+}
+
+//从X-Forwarded-For里取右数第hops个地址
+//地址不够hops个(请求没经过那么多层代理)时取最左边的：那是离客户端最近的一层代理写的
+fn forwarded_ip(forwarded: &str, hops: usize) -> Option<&str> {
+    if hops == 0 {
+        return None;
+    }
+    let entries = forwarded
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+    let index = entries.len().saturating_sub(hops);
+    entries.get(index).copied()
+}
+
+//User-Agent，超长的截断
+pub fn user_agent(req: &Request) -> String {
+    req.headers()
+        .get("user-agent")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .chars()
+        .take(MAX_USER_AGENT_CHARS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::forwarded_ip;
+
+    #[test]
+    fn ignores_forwarded_for_without_trusted_proxies() {
+        assert_eq!(forwarded_ip("1.1.1.1", 0), None);
+    }
+
+    #[test]
+    fn takes_the_entry_written_by_the_outermost_trusted_proxy() {
+        //客户端自己带了一个伪造的地址，一层代理在后面追加了真实地址
+        assert_eq!(forwarded_ip("6.6.6.6, 1.1.1.1", 1), Some("1.1.1.1"));
+        //两层代理：CDN追加了客户端地址，nginx追加了CDN的地址
+        assert_eq!(
+            forwarded_ip("6.6.6.6, 1.1.1.1, 10.0.0.2", 2),
+            Some("1.1.1.1")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_leftmost_when_fewer_entries_than_hops() {
+        assert_eq!(forwarded_ip("1.1.1.1", 2), Some("1.1.1.1"));
+    }
+
+    #[test]
+    fn skips_empty_entries() {
+        assert_eq!(forwarded_ip("6.6.6.6,, 1.1.1.1 ,", 1), Some("1.1.1.1"));
+        assert_eq!(forwarded_ip("", 1), None);
+        assert_eq!(forwarded_ip(" , ", 1), None);
+    }
+}
@@ -0,0 +1,223 @@
+//JWT的签名密钥和校验密钥
+//签名：只用一把当前的密钥，非对称算法时token头里带上它的kid
+//校验：按token头里的kid找公钥，没有kid的token(HS256签发的)用JWT_SECRET校验
+//公钥通过/.well-known/jwks.json公开，其他服务不用拿到私钥也能自己校验token
+//校验时除了签名和exp，还要检查nbf、iss、aud：别的环境签发的token就算密钥一样也不认
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rsa::RsaPublicKey;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::der::pem;
+use rsa::pkcs8::spki::{ObjectIdentifier, SubjectPublicKeyInfoRef};
+use rsa::traits::PublicKeyParts;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::config::settings::JwtKeySettings;
+
+//公钥的算法标识(SubjectPublicKeyInfo里的OID)
+const RSA_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+//一把校验用的公钥
+struct VerifyingKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    key_id: Option<String>,
+    signing_key: EncodingKey,
+    //kid -> 公钥
+    verifying_keys: HashMap<String, VerifyingKey>,
+    //校验没有kid的token
+    secret: Option<DecodingKey>,
+    //对外公开的公钥
+    jwks: JwkSet,
+    issuer: String,
+    audience: String,
+    leeway_seconds: u64,
+}
+
+impl JwtKeys {
+    //按配置读取密钥文件；文件读不了、格式不对、私钥和公钥对不上，启动时就报错
+    pub fn from_settings(settings: &JwtKeySettings) -> anyhow::Result<Self> {
+        let mut verifying_keys = HashMap::new();
+        let mut jwks = JwkSet { keys: Vec::new() };
+        for (kid, path) in &settings.public_keys {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("读取JWT公钥失败:{path}:{e}"))?;
+            let jwk = public_key_to_jwk(kid, &content)
+                .map_err(|e| anyhow::anyhow!("解析JWT公钥失败:{path}:{e}"))?;
+            let algorithm = match jwk.common.key_algorithm {
+                Some(KeyAlgorithm::EdDSA) => Algorithm::EdDSA,
+                _ => Algorithm::RS256,
+            };
+            let key = DecodingKey::from_jwk(&jwk)?;
+            verifying_keys.insert(kid.clone(), VerifyingKey { algorithm, key });
+            jwks.keys.push(jwk);
+        }
+
+        let secret = settings.secret.as_deref();
+        let signing_key = match settings.algorithm {
+            Algorithm::HS256 => EncodingKey::from_secret(secret.unwrap_or_default().as_bytes()),
+            algorithm => {
+                let path = settings.private_key_path.as_deref().unwrap_or_default();
+                let content = std::fs::read(path)
+                    .map_err(|e| anyhow::anyhow!("读取JWT私钥失败:{path}:{e}"))?;
+                let key = if algorithm == Algorithm::EdDSA {
+                    EncodingKey::from_ed_pem(&content)
+                } else {
+                    EncodingKey::from_rsa_pem(&content)
+                };
+                key.map_err(|e| anyhow::anyhow!("解析JWT私钥失败:{path}:{e}"))?
+            }
+        };
+
+        let keys = Self {
+            algorithm: settings.algorithm,
+            key_id: settings
+                .key_id
+                .clone()
+                .filter(|_| settings.algorithm != Algorithm::HS256),
+            signing_key,
+            verifying_keys,
+            secret: secret.map(|s| DecodingKey::from_secret(s.as_bytes())),
+            jwks,
+            issuer: settings.issuer.clone(),
+            audience: settings.audience.clone(),
+            leeway_seconds: settings.leeway_seconds,
+        };
+
+        //用私钥签一个token再用公钥校验，确认两者是一对
+        let probe = keys.encode(&serde_json::json!({
+            "iss": keys.issuer,
+            "aud": keys.audience,
+            "nbf": 0,
+            "exp": usize::MAX,
+        }))?;
+        keys.decode::<serde_json::Value>(&probe)
+            .map_err(|e| anyhow::anyhow!("JWT私钥和JWT_KEY_ID对应的公钥不匹配:{e}"))?;
+
+        Ok(keys)
+    }
+
+    //用当前的签名密钥签发
+    pub fn encode<T: Serialize>(&self, claims: &T) -> anyhow::Result<String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.key_id.clone();
+        jsonwebtoken::encode(&header, claims, &self.signing_key)
+            .map_err(|e| anyhow::anyhow!("jwt encode failed:{e}"))
+    }
+
+    //签发方/接收方：签发token时写进iss/aud
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn audience(&self) -> &str {
+        &self.audience
+    }
+
+    //校验签名、exp/nbf(允许leeway秒的误差)、iss、aud，这几个声明缺了也不行
+    //算法跟着密钥走，不信任token头里的alg，避免拿公钥当HMAC密钥伪造token
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> anyhow::Result<T> {
+        let header = jsonwebtoken::decode_header(token)
+            .map_err(|e| anyhow::anyhow!("jwt decode falied:{e}"))?;
+        let (algorithm, key) = match &header.kid {
+            Some(kid) => {
+                let key = self
+                    .verifying_keys
+                    .get(kid)
+                    .ok_or_else(|| anyhow::anyhow!("jwt kid unknown:{kid}"))?;
+                (key.algorithm, &key.key)
+            }
+            None => {
+                let key = self
+                    .secret
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("jwt kid missing"))?;
+                (Algorithm::HS256, key)
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway_seconds;
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud"]);
+
+        let data = jsonwebtoken::decode::<T>(token, key, &validation)
+            .map_err(|e| anyhow::anyhow!("jwt decode falied:{e}"))?;
+        Ok(data.claims)
+    }
+
+    //所有校验用的公钥(JWKS格式)；HS256的密钥不会出现在这里
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+//把PEM格式的公钥转成JWK
+//支持"PUBLIC KEY"(RSA或Ed25519)和"RSA PUBLIC KEY"(PKCS#1)
+fn public_key_to_jwk(kid: &str, content: &str) -> anyhow::Result<Jwk> {
+    let (label, der) = pem::decode_vec(content.trim().as_bytes())
+        .map_err(|e| anyhow::anyhow!("不是PEM格式:{e}"))?;
+
+    let (key_algorithm, algorithm) = match label {
+        "RSA PUBLIC KEY" => (
+            KeyAlgorithm::RS256,
+            rsa_parameters(RsaPublicKey::from_pkcs1_der(&der)?),
+        ),
+        "PUBLIC KEY" => {
+            let spki = SubjectPublicKeyInfoRef::try_from(der.as_slice())?;
+            if spki.algorithm.oid == RSA_OID {
+                let key = RsaPublicKey::try_from(spki)?;
+                (KeyAlgorithm::RS256, rsa_parameters(key))
+            } else if spki.algorithm.oid == ED25519_OID {
+                let x = spki
+                    .subject_public_key
+                    .as_bytes()
+                    .filter(|x| x.len() == 32)
+                    .ok_or_else(|| anyhow::anyhow!("Ed25519公钥长度不对"))?;
+                (
+                    KeyAlgorithm::EdDSA,
+                    AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                        key_type: OctetKeyPairType::OctetKeyPair,
+                        curve: EllipticCurve::Ed25519,
+                        x: URL_SAFE_NO_PAD.encode(x),
+                    }),
+                )
+            } else {
+                anyhow::bail!("只支持RSA和Ed25519公钥:{}", spki.algorithm.oid);
+            }
+        }
+        other => anyhow::bail!("不是公钥:{other}"),
+    };
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm,
+    })
+}
+
+fn rsa_parameters(key: RsaPublicKey) -> AlgorithmParameters {
+    AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+        e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+    })
+}
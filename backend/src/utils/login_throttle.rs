@@ -0,0 +1,336 @@
+//登录失败锁定：按账号、按IP分别计数，连续失败太多次就锁一段时间
+//做成trait是为了可以替换实现：单实例用进程内存，多实例部署用MySQL共享计数
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use sqlx::{MySql, Pool};
+
+use crate::config::settings::{LoginThrottleSettings, ThrottleBackend};
+use crate::services::login_attempt_service;
+
+#[async_trait]
+pub trait LoginAttemptTracker: Send + Sync {
+    //还在锁定中的话返回解锁时间
+    async fn locked_until(&self, key: &str) -> anyhow::Result<Option<DateTime<Utc>>>;
+    //记一次失败；达到阈值时返回这次锁定到什么时候
+    async fn record_failure(
+        &self,
+        key: &str,
+        max_failures: u32,
+    ) -> anyhow::Result<Option<DateTime<Utc>>>;
+    //统计窗口内的失败次数(工作量证明按它调整难度)
+    async fn recent_failures(&self, key: &str) -> anyhow::Result<u32>;
+    //登录成功后清零
+    async fn reset(&self, key: &str) -> anyhow::Result<()>;
+    //清理过期的计数(后台任务定时调用)
+    async fn cleanup_expired(&self) -> anyhow::Result<()>;
+}
+
+//计数用的key：用户存在时按用户id，这样用户名/邮箱换着试也算同一个账号
+pub fn user_key(user_id: i64) -> String {
+    format!("user:{user_id}")
+}
+
+//用户不存在时按输入的账号计数(大小写不敏感)
+pub fn account_key(account: &str) -> String {
+    format!("account:{}", account.trim().to_lowercase())
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{ip}")
+}
+
+//第failures次失败之后要锁多久：没到阈值不锁；到了阈值锁base秒，之后每多一次翻倍，最多max秒
+fn lockout_duration(
+    settings: &LoginThrottleSettings,
+    failures: u32,
+    max_failures: u32,
+) -> Option<Duration> {
+    if max_failures == 0 || failures < max_failures {
+        return None;
+    }
+
+    //指数封顶，防止左移溢出
+    let exponent = (failures - max_failures).min(20);
+    let seconds = settings
+        .base_lockout_seconds
+        .saturating_mul(1_i64 << exponent)
+        .min(settings.max_lockout_seconds);
+    Some(Duration::seconds(seconds))
+}
+
+//进程内存里的一条计数
+struct AttemptRecord {
+    failures: u32,
+    last_failure_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl AttemptRecord {
+    //统计窗口从最后一次失败和锁定结束里晚的那个开始算
+    //只按最后一次失败算的话，锁得比窗口还久时解锁后计数已经清零，退避永远涨不上去
+    fn window_anchor(&self) -> DateTime<Utc> {
+        self.locked_until.map_or(self.last_failure_at, |until| {
+            until.max(self.last_failure_at)
+        })
+    }
+}
+
+//计数存在进程内存(DashMap)，重启后清零
+pub struct MemoryAttemptTracker {
+    settings: LoginThrottleSettings,
+    map: DashMap<String, AttemptRecord>,
+}
+
+impl MemoryAttemptTracker {
+    pub fn new(settings: LoginThrottleSettings) -> Self {
+        Self {
+            settings,
+            map: DashMap::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl LoginAttemptTracker for MemoryAttemptTracker {
+    async fn locked_until(&self, key: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let now = Utc::now();
+        Ok(self
+            .map
+            .get(key)
+            .and_then(|record| record.locked_until)
+            .filter(|until| *until > now))
+    }
+
+    async fn record_failure(
+        &self,
+        key: &str,
+        max_failures: u32,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let now = Utc::now();
+        let window_start = now - Duration::seconds(self.settings.window_seconds);
+
+        //entry持有分片锁，同一个key的并发失败不会丢计数
+        let mut record = self.map.entry(key.to_string()).or_insert(AttemptRecord {
+            failures: 0,
+            last_failure_at: now,
+            locked_until: None,
+        });
+        if record.window_anchor() < window_start {
+            record.failures = 0;
+        }
+        record.failures += 1;
+        record.last_failure_at = now;
+
+        let locked_until = lockout_duration(&self.settings, record.failures, max_failures)
+            .map(|duration| now + duration);
+        if locked_until.is_some() {
+            record.locked_until = locked_until;
+        }
+
+        Ok(locked_until)
+    }
+
+    async fn recent_failures(&self, key: &str) -> anyhow::Result<u32> {
+        let window_start = Utc::now() - Duration::seconds(self.settings.window_seconds);
+        Ok(self
+            .map
+            .get(key)
+            .filter(|record| record.window_anchor() >= window_start)
+            .map_or(0, |record| record.failures))
+    }
+
+    async fn reset(&self, key: &str) -> anyhow::Result<()> {
+        self.map.remove(key);
+        Ok(())
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        let window_start = Utc::now() - Duration::seconds(self.settings.window_seconds);
+        //还在锁定中的记录锚点在将来，不会被清掉
+        self.map
+            .retain(|_, record| record.window_anchor() >= window_start);
+        Ok(())
+    }
+}
+
+//计数存在MySQL的login_attempts表，多个实例共享
+pub struct MySqlAttemptTracker {
+    settings: LoginThrottleSettings,
+    db: Pool<MySql>,
+}
+
+impl MySqlAttemptTracker {
+    pub fn new(settings: LoginThrottleSettings, db: Pool<MySql>) -> Self {
+        Self { settings, db }
+    }
+}
+
+#[async_trait]
+impl LoginAttemptTracker for MySqlAttemptTracker {
+    async fn locked_until(&self, key: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        login_attempt_service::find_locked_until(&self.db, key).await
+    }
+
+    async fn record_failure(
+        &self,
+        key: &str,
+        max_failures: u32,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let window_start = Utc::now() - Duration::seconds(self.settings.window_seconds);
+        let failures = login_attempt_service::record_failure(&self.db, key, window_start).await?;
+
+        let Some(duration) = lockout_duration(&self.settings, failures, max_failures) else {
+            return Ok(None);
+        };
+        let locked_until = Utc::now() + duration;
+        login_attempt_service::set_locked_until(&self.db, key, locked_until).await?;
+
+        Ok(Some(locked_until))
+    }
+
+    async fn recent_failures(&self, key: &str) -> anyhow::Result<u32> {
+        let window_start = Utc::now() - Duration::seconds(self.settings.window_seconds);
+        login_attempt_service::find_recent_failures(&self.db, key, window_start).await
+    }
+
+    async fn reset(&self, key: &str) -> anyhow::Result<()> {
+        login_attempt_service::delete_attempts(&self.db, key).await
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        let window_start = Utc::now() - Duration::seconds(self.settings.window_seconds);
+        login_attempt_service::delete_stale_attempts(&self.db, window_start).await?;
+        Ok(())
+    }
+}
+
+//根据配置创建计数器
+pub fn build_attempt_tracker(
+    settings: &LoginThrottleSettings,
+    db: &Pool<MySql>,
+) -> Arc<dyn LoginAttemptTracker> {
+    match settings.backend {
+        ThrottleBackend::Memory => Arc::new(MemoryAttemptTracker::new(settings.clone())),
+        ThrottleBackend::Mysql => Arc::new(MySqlAttemptTracker::new(settings.clone(), db.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LoginThrottleSettings {
+        LoginThrottleSettings {
+            backend: ThrottleBackend::Memory,
+            account_max_failures: 3,
+            ip_max_failures: 10,
+            base_lockout_seconds: 60,
+            max_lockout_seconds: 600,
+            window_seconds: 900,
+        }
+    }
+
+    #[test]
+    fn lockout_doubles_from_threshold_and_is_capped() {
+        let settings = settings();
+        let seconds = |failures| lockout_duration(&settings, failures, 3).map(|d| d.num_seconds());
+
+        assert_eq!(seconds(1), None);
+        assert_eq!(seconds(2), None);
+        assert_eq!(seconds(3), Some(60));
+        assert_eq!(seconds(4), Some(120));
+        assert_eq!(seconds(6), Some(480));
+        assert_eq!(seconds(7), Some(600));
+        //指数很大也不会溢出
+        assert_eq!(seconds(u32::MAX), Some(600));
+    }
+
+    #[test]
+    fn zero_threshold_never_locks() {
+        assert_eq!(lockout_duration(&settings(), 100, 0), None);
+    }
+
+    #[test]
+    fn account_key_ignores_case_and_spaces() {
+        assert_eq!(
+            account_key(" Alice@Example.com "),
+            "account:alice@example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_tracker_locks_after_threshold_until_reset() {
+        let tracker = MemoryAttemptTracker::new(settings());
+        let key = user_key(1);
+
+        assert_eq!(tracker.record_failure(&key, 3).await.unwrap(), None);
+        assert_eq!(tracker.record_failure(&key, 3).await.unwrap(), None);
+        assert_eq!(tracker.locked_until(&key).await.unwrap(), None);
+
+        let until = tracker.record_failure(&key, 3).await.unwrap().unwrap();
+        let expected = Utc::now() + Duration::seconds(60);
+        assert!((until - expected).num_seconds().abs() <= 1);
+        assert_eq!(tracker.locked_until(&key).await.unwrap(), Some(until));
+        assert_eq!(tracker.recent_failures(&key).await.unwrap(), 3);
+
+        //其他key不受影响
+        assert_eq!(tracker.locked_until(&user_key(2)).await.unwrap(), None);
+        assert_eq!(tracker.recent_failures(&user_key(2)).await.unwrap(), 0);
+
+        tracker.reset(&key).await.unwrap();
+        assert_eq!(tracker.locked_until(&key).await.unwrap(), None);
+        assert_eq!(tracker.recent_failures(&key).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn memory_tracker_keeps_locked_records_on_cleanup() {
+        let tracker = MemoryAttemptTracker::new(settings());
+        let key = ip_key("1.1.1.1");
+        for _ in 0..3 {
+            tracker.record_failure(&key, 3).await.unwrap();
+        }
+
+        tracker.cleanup_expired().await.unwrap();
+        assert!(tracker.locked_until(&key).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn lock_longer_than_window_keeps_backing_off() {
+        //默认配置：第6次锁定是960秒，比900秒的窗口还长
+        let settings = LoginThrottleSettings {
+            base_lockout_seconds: 30,
+            max_lockout_seconds: 3600,
+            ..settings()
+        };
+        let tracker = MemoryAttemptTracker::new(settings.clone());
+        let key = user_key(1);
+        let failures = 3 + 5;
+        assert_eq!(
+            lockout_duration(&settings, failures, 3).map(|d| d.num_seconds()),
+            Some(960)
+        );
+
+        //上一次失败在961秒之前，锁定刚结束
+        let now = Utc::now();
+        tracker.map.insert(
+            key.clone(),
+            AttemptRecord {
+                failures,
+                last_failure_at: now - Duration::seconds(961),
+                locked_until: Some(now - Duration::seconds(1)),
+            },
+        );
+        assert_eq!(tracker.locked_until(&key).await.unwrap(), None);
+        assert_eq!(tracker.recent_failures(&key).await.unwrap(), failures);
+
+        //清理不能把计数清掉
+        tracker.cleanup_expired().await.unwrap();
+        //解锁后再失败一次：接着翻倍，不是从1重新开始
+        let until = tracker.record_failure(&key, 3).await.unwrap().unwrap();
+        let expected = Utc::now() + Duration::seconds(1920);
+        assert!((until - expected).num_seconds().abs() <= 1);
+    }
+}
@@ -0,0 +1,97 @@
+//邮件发送：找回密码、邮箱验证等都通过这里发信
+//做成trait是为了可以替换实现：本地开发只打印，线上/测试走SMTP
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::message::{Mailbox, header::ContentType};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::settings::{MailSettings, MailTransport, SmtpTls};
+
+//一封纯文本邮件
+#[derive(Clone, Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()>;
+}
+
+//只把邮件打印到控制台，本地开发不用配SMTP也能拿到链接
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        println!(
+            "[mail] to={} subject={}\n{}",
+            mail.to, mail.subject, mail.body
+        );
+        Ok(())
+    }
+}
+
+//通过SMTP发送
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(settings: &MailSettings) -> anyhow::Result<Self> {
+        let builder = match settings.smtp_tls {
+            //不加密：只给本地SMTP捕获工具用
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.smtp_host)
+            }
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.smtp_host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.smtp_host)?,
+        };
+
+        let mut builder = builder.port(settings.smtp_port);
+        if let (Some(username), Some(password)) = (&settings.smtp_username, &settings.smtp_password)
+        {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let from = settings
+            .from
+            .parse::<Mailbox>()
+            .map_err(|e| anyhow::anyhow!("MAIL_FROM格式错误:{e}"))?;
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail.to.parse::<Mailbox>()?)
+            .subject(mail.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(mail.body)?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+//根据配置选择发信方式
+pub fn build_mailer(settings: &MailSettings) -> anyhow::Result<Arc<dyn Mailer>> {
+    Ok(match settings.transport {
+        MailTransport::Log => Arc::new(LogMailer),
+        MailTransport::Smtp => Arc::new(SmtpMailer::new(settings)?),
+    })
+}
@@ -1 +1,10 @@
 pub mod auth;
+pub mod captcha_render;
+pub mod captcha_store;
+pub mod client;
+pub mod jwt_keys;
+pub mod login_throttle;
+pub mod mailer;
+pub mod pow;
+pub mod pow_nonce_store;
+pub mod totp;
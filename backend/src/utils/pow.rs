@@ -0,0 +1,131 @@
+//工作量证明(proof of work)：验证码的替代方式，不用用户看图，适合API客户端和无障碍场景
+//服务端发一个签名过的challenge，客户端不断尝试solution，直到sha256(challenge:solution)开头有足够多的0比特
+//challenge格式：随机串.难度.过期时间(unix秒).签名
+//签名=HMAC-SHA256(密钥, 随机串.难度.过期时间.客户端IP)，难度和过期时间改不了，换个IP也用不了
+//同一个challenge只能用一次：发的时候不用存，用过之后把随机串记到pow_nonce_store里
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+
+use crate::config::settings::PowSettings;
+
+type HmacSha256 = Hmac<Sha256>;
+
+//solution的最大长度，防止提交超长字符串
+const MAX_SOLUTION_LENGTH: usize = 64;
+
+//发出去的一个challenge
+pub struct PowChallenge {
+    //随机串：用过之后记下来，防止重复用
+    pub nonce: String,
+    //交给客户端的完整challenge
+    pub challenge: String,
+    pub difficulty: u32,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub struct ProofOfWork {
+    key: Vec<u8>,
+    settings: PowSettings,
+}
+
+impl ProofOfWork {
+    pub fn new(settings: &PowSettings) -> Self {
+        let key = match &settings.secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                let mut key = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            }
+        };
+        Self {
+            key,
+            settings: settings.clone(),
+        }
+    }
+
+    //按这个IP最近的失败次数算难度
+    pub fn difficulty(&self, recent_failures: u32) -> u32 {
+        recent_failures
+            .saturating_mul(self.settings.difficulty_step)
+            .saturating_add(self.settings.base_difficulty)
+            .min(self.settings.max_difficulty)
+    }
+
+    pub fn ttl_seconds(&self) -> i64 {
+        self.settings.ttl_seconds
+    }
+
+    //给这个IP发一个challenge
+    pub fn issue(&self, difficulty: u32, ip: &str) -> PowChallenge {
+        let nonce: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+        let expires_at = Utc::now() + Duration::seconds(self.settings.ttl_seconds);
+        let payload = format!("{nonce}.{difficulty}.{}", expires_at.timestamp());
+        let signature = URL_SAFE_NO_PAD.encode(self.sign(&payload, ip).finalize().into_bytes());
+
+        PowChallenge {
+            challenge: format!("{payload}.{signature}"),
+            nonce,
+            difficulty,
+            expires_at,
+        }
+    }
+
+    //校验签名、过期时间和solution，通过时返回随机串和过期时间(调用方还要记到已用随机串里，保证只用一次)
+    pub fn verify(
+        &self,
+        challenge: &str,
+        solution: &str,
+        ip: &str,
+    ) -> Option<(String, DateTime<Utc>)> {
+        if solution.is_empty() || solution.len() > MAX_SOLUTION_LENGTH {
+            return None;
+        }
+
+        let (payload, signature) = challenge.rsplit_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        //verify_slice是常量时间比较
+        self.sign(payload, ip).verify_slice(&signature).ok()?;
+
+        let mut parts = payload.split('.');
+        let (nonce, difficulty, expires_at) = (parts.next()?, parts.next()?, parts.next()?);
+        let difficulty = difficulty.parse::<u32>().ok()?;
+        let expires_at = DateTime::from_timestamp(expires_at.parse::<i64>().ok()?, 0)?;
+        if expires_at < Utc::now() {
+            return None;
+        }
+
+        let hash = Sha256::digest(format!("{challenge}:{solution}").as_bytes());
+        if leading_zero_bits(&hash) < difficulty {
+            return None;
+        }
+
+        Some((nonce.to_string(), expires_at))
+    }
+
+    fn sign(&self, payload: &str, ip: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC可以接受任意长度的key");
+        mac.update(format!("{payload}.{ip}").as_bytes());
+        mac
+    }
+}
+
+//哈希值开头有多少个0比特
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
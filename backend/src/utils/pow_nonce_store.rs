@@ -0,0 +1,223 @@
+//已经用过的工作量证明随机串：同一个challenge只能用一次
+//跟验证码存储分开：不占验证码的总数和每个IP的名额，有自己的上限
+//只需要记到challenge过期为止，过期的challenge签名校验就过不了
+//满了之后拒绝新的solution，不淘汰旧的：淘汰掉的随机串在challenge过期前可以再提交一次
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::Script;
+use redis::aio::ConnectionManager;
+use sqlx::{MySql, Pool};
+
+use crate::config::settings::{CaptchaBackend, CaptchaSettings, PowSettings};
+use crate::services::pow_nonce_service;
+use crate::utils::captcha_store::connect_redis;
+
+#[async_trait]
+pub trait PowNonceStore: Send + Sync {
+    //记下一个已用的随机串，expires_at是challenge的过期时间
+    //第一次用 -> true
+    //已经用过/存满了 -> false
+    //必须是原子的：同一个challenge并发提交多次，最多只有一次返回true
+    async fn mark_spent(&self, nonce: &str, expires_at: DateTime<Utc>) -> anyhow::Result<bool>;
+    //清理challenge已经过期的随机串(后台任务定时调用)
+    async fn cleanup_expired(&self) -> anyhow::Result<()>;
+}
+
+//存在进程内存，重启后丢失(重启后签名密钥没配置时也会换，之前的challenge本来就用不了)
+pub struct MemoryPowNonceStore {
+    capacity: usize,
+    spent: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl MemoryPowNonceStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            spent: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl PowNonceStore for MemoryPowNonceStore {
+    async fn mark_spent(&self, nonce: &str, expires_at: DateTime<Utc>) -> anyhow::Result<bool> {
+        let mut spent = self.spent.lock().expect("PoW随机串存储的锁被污染");
+        if spent.contains_key(nonce) {
+            return Ok(false);
+        }
+        //满了先清一次过期的，还是满就拒绝
+        if spent.len() >= self.capacity {
+            let now = Utc::now();
+            spent.retain(|_, expires_at| *expires_at >= now);
+            if spent.len() >= self.capacity {
+                return Ok(false);
+            }
+        }
+        spent.insert(nonce.to_string(), expires_at);
+        Ok(true)
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        self.spent
+            .lock()
+            .expect("PoW随机串存储的锁被污染")
+            .retain(|_, expires_at| *expires_at >= now);
+        Ok(())
+    }
+}
+
+//存在MySQL的pow_nonces表，多个实例共享
+//数量检查和插入不在一个事务里，并发时可能稍微超出上限；重复提交靠主键挡住
+pub struct MySqlPowNonceStore {
+    capacity: usize,
+    db: Pool<MySql>,
+}
+
+impl MySqlPowNonceStore {
+    pub fn new(capacity: usize, db: Pool<MySql>) -> Self {
+        Self { capacity, db }
+    }
+}
+
+#[async_trait]
+impl PowNonceStore for MySqlPowNonceStore {
+    async fn mark_spent(&self, nonce: &str, expires_at: DateTime<Utc>) -> anyhow::Result<bool> {
+        let count = pow_nonce_service::count_pow_nonces(&self.db).await?;
+        if count >= self.capacity as i64 {
+            return Ok(false);
+        }
+        pow_nonce_service::insert_pow_nonce(&self.db, nonce, expires_at).await
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        pow_nonce_service::delete_expired_pow_nonces(&self.db).await?;
+        Ok(())
+    }
+}
+
+//Redis里的key：
+// pow:spent   有序集合，已用的随机串，分数是challenge的过期时间(毫秒)
+//跟验证码的captcha:前缀分开，验证码脚本里的淘汰不会碰到这里
+const REDIS_SPENT_KEY: &str = "pow:spent";
+
+//记录：先清掉已经过期的，满了或者已经有了返回0，否则加进去返回1
+//整个集合的过期时间跟着最晚的challenge走，长时间没人用时自己消失
+//KEYS: 已用集合
+//ARGV: 随机串, 过期时间(毫秒), 有效期(毫秒), 上限, 当前时间(毫秒)
+const REDIS_MARK_SCRIPT: &str = r#"
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[5])
+if redis.call('ZSCORE', KEYS[1], ARGV[1]) then return 0 end
+if redis.call('ZCARD', KEYS[1]) >= tonumber(ARGV[4]) then return 0 end
+redis.call('ZADD', KEYS[1], ARGV[2], ARGV[1])
+if redis.call('PTTL', KEYS[1]) < tonumber(ARGV[3]) then redis.call('PEXPIRE', KEYS[1], ARGV[3]) end
+return 1
+"#;
+
+//存在Redis，多个实例共享；和验证码用同一个Redis
+pub struct RedisPowNonceStore {
+    capacity: usize,
+    conn: ConnectionManager,
+    mark_script: Script,
+}
+
+impl RedisPowNonceStore {
+    //按redis_url连接，连不上时启动失败
+    pub async fn connect(capacity: usize, redis_url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            capacity,
+            conn: connect_redis(redis_url).await?,
+            mark_script: Script::new(REDIS_MARK_SCRIPT),
+        })
+    }
+}
+
+#[async_trait]
+impl PowNonceStore for RedisPowNonceStore {
+    async fn mark_spent(&self, nonce: &str, expires_at: DateTime<Utc>) -> anyhow::Result<bool> {
+        let now_ms = Utc::now().timestamp_millis();
+        let expires_ms = expires_at.timestamp_millis();
+        let marked = self
+            .mark_script
+            .key(REDIS_SPENT_KEY)
+            .arg(nonce)
+            .arg(expires_ms)
+            .arg((expires_ms - now_ms).max(1))
+            .arg(self.capacity)
+            .arg(now_ms)
+            .invoke_async::<i64>(&mut self.conn.clone())
+            .await?;
+        Ok(marked == 1)
+    }
+
+    async fn cleanup_expired(&self) -> anyhow::Result<()> {
+        redis::cmd("ZREMRANGEBYSCORE")
+            .arg(REDIS_SPENT_KEY)
+            .arg("-inf")
+            .arg(Utc::now().timestamp_millis())
+            .query_async::<i64>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+}
+
+//根据配置创建PoW随机串存储：后端跟验证码存储一样(CAPTCHA_BACKEND)，上限用POW_CAPACITY
+pub async fn build_pow_nonce_store(
+    captcha: &CaptchaSettings,
+    pow: &PowSettings,
+    db: &Pool<MySql>,
+) -> anyhow::Result<Arc<dyn PowNonceStore>> {
+    Ok(match captcha.backend {
+        CaptchaBackend::Memory => Arc::new(MemoryPowNonceStore::new(pow.capacity)),
+        CaptchaBackend::Mysql => Arc::new(MySqlPowNonceStore::new(pow.capacity, db.clone())),
+        CaptchaBackend::Redis => {
+            Arc::new(RedisPowNonceStore::connect(pow.capacity, &captcha.redis_url).await?)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn nonce_can_only_be_spent_once() {
+        let store = MemoryPowNonceStore::new(10);
+        let expires_at = Utc::now() + Duration::seconds(60);
+
+        assert!(store.mark_spent("abc", expires_at).await.unwrap());
+        assert!(!store.mark_spent("abc", expires_at).await.unwrap());
+        assert!(store.mark_spent("def", expires_at).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn full_store_rejects_instead_of_evicting() {
+        let store = MemoryPowNonceStore::new(2);
+        let expires_at = Utc::now() + Duration::seconds(60);
+
+        assert!(store.mark_spent("a", expires_at).await.unwrap());
+        assert!(store.mark_spent("b", expires_at).await.unwrap());
+        assert!(!store.mark_spent("c", expires_at).await.unwrap());
+        //没有被淘汰，仍然不能重复用
+        assert!(!store.mark_spent("a", expires_at).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn expired_nonces_make_room() {
+        let store = MemoryPowNonceStore::new(2);
+        let expired = Utc::now() - Duration::seconds(1);
+        let expires_at = Utc::now() + Duration::seconds(60);
+
+        assert!(store.mark_spent("a", expired).await.unwrap());
+        assert!(store.mark_spent("b", expires_at).await.unwrap());
+        assert!(store.mark_spent("c", expires_at).await.unwrap());
+
+        store.cleanup_expired().await.unwrap();
+        assert_eq!(store.spent.lock().unwrap().len(), 2);
+    }
+}
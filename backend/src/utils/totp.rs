@@ -0,0 +1,113 @@
+//两步验证：TOTP(RFC 6238)
+//TOTP是什么：
+//服务端和用户的验证器App共享一个密钥，双方都用"当前时间/30秒"作为计数器算HMAC-SHA1，截取出6位数字
+//所以验证码每30秒变一次，服务端不需要和手机通信就能校验
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rand_core::{OsRng, RngCore};
+use sha1::Sha1;
+
+type HmacSha1 = Hmac<Sha1>;
+
+//时间步长(秒)和验证码位数，和主流验证器App的默认值一致
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+//允许前后各偏差一个时间步(手机时间不准)
+const ALLOWED_SKEW: i64 = 1;
+
+//恢复码字符集：去掉了容易看错的0/o/1/l/i
+const RECOVERY_CHARSET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+//生成一个新密钥：20字节随机数(RFC 4226推荐长度)，base32编码后给验证器App
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+//生成otpauth://链接，前端把它做成二维码让验证器App扫
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+//校验验证码：通过则返回匹配上的时间步(调用方记下来防止同一个验证码被重复使用)
+pub fn verify_code(secret: &str, code: &str, now: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let current = now / STEP_SECONDS;
+    (current - ALLOWED_SKEW..=current + ALLOWED_SKEW).find(|&step| {
+        let expected = format!(
+            "{:0width$}",
+            hotp(&key, step as u64),
+            width = DIGITS as usize
+        );
+        constant_time_eq(expected.as_bytes(), code.as_bytes())
+    })
+}
+
+//生成一组恢复码，格式xxxxx-xxxxx
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..count)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| RECOVERY_CHARSET[rng.gen_range(0..RECOVERY_CHARSET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect()
+}
+
+//用户输入的恢复码统一成保存时的格式：去掉横线和空格，转小写
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+//HOTP(RFC 4226)：HMAC-SHA1(key, counter)后动态截取31位，再取后DIGITS位十进制
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = HmacSha1::new_from_slice(key).expect("HMAC可以接受任意长度的key");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+//逐字节比较完再返回，避免通过响应时间猜出前几位
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+//otpauth链接里的label/issuer需要百分号编码(用户名里可能有空格、@等)
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
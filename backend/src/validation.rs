@@ -0,0 +1,201 @@
+//请求参数校验
+//请求体实现Validate：先把字段规范化(去空格、Unicode NFKC)，再按规则逐个检查
+//所有字段的错误一次性收集起来，handler用parse_valid解析，有错误时统一返回422 {fields: {字段: [错误]}}
+use std::collections::BTreeMap;
+
+use email_address::EmailAddress;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::{AppError, AppResult, ErrorCode};
+use crate::services::user_service::UserRow;
+use crate::state::AppState;
+use crate::utils::auth::PasswordPolicy;
+
+//用户名长度(字符数)
+const USERNAME_MIN_LENGTH: usize = 3;
+const USERNAME_MAX_LENGTH: usize = 32;
+//邮箱最长254个字符(RFC 5321)
+const EMAIL_MAX_LENGTH: usize = 254;
+
+//按字段收集的错误：字段名 -> 错误码列表
+#[derive(Debug, Default)]
+pub struct FieldErrors(BTreeMap<&'static str, Vec<ErrorCode>>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &'static str, code: ErrorCode) {
+        self.0.entry(field).or_default().push(code);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &[ErrorCode])> {
+        self.0
+            .iter()
+            .map(|(field, codes)| (*field, codes.as_slice()))
+    }
+}
+
+//请求体的校验规则
+//validate里可以改写字段(规范化)，后面的handler拿到的就是规范化之后的值
+pub trait Validate {
+    fn validate(&mut self, state: &AppState, errors: &mut FieldErrors);
+}
+
+//Unicode规范化：全角字母数字转半角、组合字符合并，避免看起来一样的两个用户名同时存在
+pub fn normalize(value: &str) -> String {
+    value.trim().nfkc().collect()
+}
+
+//密码只做NFKC不去空格：空格也是密码的一部分
+pub fn normalize_password(value: &str) -> String {
+    value.nfkc().collect()
+}
+
+//用户名：3-32个字符，只能用字母(包括中文等)、数字和 _ - .，并且要以字母或数字开头
+//不允许@，否则用户名和邮箱登录时分不清
+pub fn check_username(errors: &mut FieldErrors, field: &'static str, username: &str) {
+    let length = username.chars().count();
+    if !(USERNAME_MIN_LENGTH..=USERNAME_MAX_LENGTH).contains(&length) {
+        errors.add(field, ErrorCode::UsernameLength);
+    }
+
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+    let starts_ok = username.chars().next().is_none_or(char::is_alphanumeric);
+    if !username.chars().all(allowed) || !starts_ok {
+        errors.add(field, ErrorCode::UsernameCharset);
+    }
+}
+
+//邮箱：按RFC 5322校验格式
+pub fn check_email(errors: &mut FieldErrors, field: &'static str, email: &str) {
+    if email.chars().count() > EMAIL_MAX_LENGTH || !EmailAddress::is_valid(email) {
+        errors.add(field, ErrorCode::EmailInvalid);
+    }
+}
+
+//新密码：按密码规则检查(规则在utils::auth里)
+//personal_info:这个用户的用户名、邮箱，密码里不能包含它们
+pub fn check_password(
+    errors: &mut FieldErrors,
+    field: &'static str,
+    password: &str,
+    policy: &PasswordPolicy,
+    personal_info: &[&str],
+) {
+    for code in policy.check(password, personal_info) {
+        errors.add(field, code);
+    }
+}
+
+//新密码的完整检查：已经知道是哪个用户时用，密码里不能包含这个用户的用户名/邮箱
+pub fn ensure_new_password(
+    state: &AppState,
+    field: &'static str,
+    password: &str,
+    user: &UserRow,
+) -> AppResult<()> {
+    let mut errors = FieldErrors::default();
+    check_password(
+        &mut errors,
+        field,
+        password,
+        &state.password_policy,
+        &[&user.username, &user.email],
+    );
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+    Ok(())
+}
+
+//必填字段
+pub fn check_required(errors: &mut FieldErrors, field: &'static str, value: &str, code: ErrorCode) {
+    if value.is_empty() {
+        errors.add(field, code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(errors: &FieldErrors, field: &str) -> Vec<ErrorCode> {
+        errors
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, codes)| codes.to_vec())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn normalize_trims_and_folds_full_width() {
+        assert_eq!(normalize("  ａｌｉｃｅ１ "), "alice1");
+        //组合字符合并成一个
+        assert_eq!(normalize("e\u{301}"), "\u{e9}");
+    }
+
+    #[test]
+    fn normalize_password_keeps_spaces() {
+        assert_eq!(normalize_password(" ｐａｓｓ word "), " pass word ");
+    }
+
+    #[test]
+    fn username_rules() {
+        for ok in ["abc", "张三丰", "a.b-c_d", &"a".repeat(32)] {
+            let mut errors = FieldErrors::default();
+            check_username(&mut errors, "username", ok);
+            assert!(errors.is_empty(), "{ok}");
+        }
+
+        let mut errors = FieldErrors::default();
+        check_username(&mut errors, "username", "ab");
+        assert_eq!(codes(&errors, "username"), [ErrorCode::UsernameLength]);
+
+        for bad in ["_abc", "a@b.com", "a b c"] {
+            let mut errors = FieldErrors::default();
+            check_username(&mut errors, "username", bad);
+            assert_eq!(
+                codes(&errors, "username"),
+                [ErrorCode::UsernameCharset],
+                "{bad}"
+            );
+        }
+
+        //同一个字段的多个错误都保留下来
+        let mut errors = FieldErrors::default();
+        check_username(&mut errors, "username", "@");
+        assert_eq!(
+            codes(&errors, "username"),
+            [ErrorCode::UsernameLength, ErrorCode::UsernameCharset]
+        );
+    }
+
+    #[test]
+    fn email_rules() {
+        let mut errors = FieldErrors::default();
+        check_email(&mut errors, "email", "alice@example.com");
+        assert!(errors.is_empty());
+
+        for bad in ["alice", "alice@", "@example.com"] {
+            let mut errors = FieldErrors::default();
+            check_email(&mut errors, "email", bad);
+            assert_eq!(codes(&errors, "email"), [ErrorCode::EmailInvalid], "{bad}");
+        }
+
+        let mut errors = FieldErrors::default();
+        let long = format!("{}@example.com", "a".repeat(EMAIL_MAX_LENGTH));
+        check_email(&mut errors, "email", &long);
+        assert_eq!(codes(&errors, "email"), [ErrorCode::EmailInvalid]);
+    }
+
+    #[test]
+    fn required_fields() {
+        let mut errors = FieldErrors::default();
+        check_required(&mut errors, "account", "", ErrorCode::AccountRequired);
+        check_required(&mut errors, "password", "x", ErrorCode::PasswordRequired);
+        assert_eq!(codes(&errors, "account"), [ErrorCode::AccountRequired]);
+        assert!(codes(&errors, "password").is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+//集成测试共用：连测试库，组装和线上一模一样的路由
+//需要一个已经执行过deploy/docker-compose/migrations的MySQL，用TEST_DATABASE_URL指定
+//没有设置TEST_DATABASE_URL时测试直接跳过(返回None)，不算失败
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration as StdDuration;
+
+use async_trait::async_trait;
+
+use backend::app::{self, Limiters};
+use backend::config::{database::create_mysql_pool, settings::Settings};
+use backend::state::AppState;
+use backend::utils::captcha_store::CaptchaEntry;
+use backend::utils::mailer::{Mail, Mailer};
+use chrono::{Duration, Utc};
+use rand::{Rng, distributions::Alphanumeric};
+use salvo::prelude::*;
+use salvo::test::{ResponseExt, TestClient};
+use serde_json::{Value, json};
+
+pub const BASE_URL: &str = "http://127.0.0.1:3000";
+pub const PASSWORD: &str = "Correct-Horse-42-battery";
+
+static ENV: Once = Once::new();
+
+//测试用的Mailer：不发信，只把邮件记下来，测试里再去取邮件里的链接
+#[derive(Clone, Default)]
+pub struct RecordingMailer {
+    sent: Arc<Mutex<Vec<Mail>>>,
+}
+
+#[async_trait]
+impl Mailer for RecordingMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        self.sent.lock().unwrap().push(mail);
+        Ok(())
+    }
+}
+
+impl RecordingMailer {
+    //等一封发给to、标题是subject的邮件：handler是在后台任务里发信的，响应返回时不一定已经发出去
+    pub async fn wait_for(&self, to: &str, subject: &str) -> Mail {
+        for _ in 0..100 {
+            if let Some(mail) = self
+                .sent
+                .lock()
+                .unwrap()
+                .iter()
+                .rfind(|m| m.to == to && m.subject == subject)
+            {
+                return mail.clone();
+            }
+            tokio::time::sleep(StdDuration::from_millis(20)).await;
+        }
+        panic!("没有等到发给{to}的邮件:{subject}");
+    }
+}
+
+//从邮件正文里取出链接里的token参数
+pub fn token_in(mail: &Mail) -> String {
+    let (_, rest) = mail.body.split_once("token=").expect("邮件里没有token");
+    rest.split_whitespace().next().unwrap().to_string()
+}
+
+pub struct TestApp {
+    pub state: AppState,
+    pub service: Service,
+}
+
+//注册成功后拿到的账号和token
+pub struct TestUser {
+    pub id: i64,
+    pub username: String,
+    pub email: String,
+    pub token: String,
+    pub refresh_token: String,
+}
+
+//用TEST_DATABASE_URL建一套AppState；customize可以在组装路由之前替换state里的组件(比如mailer)
+pub async fn setup_with(customize: impl FnOnce(&mut AppState)) -> Option<TestApp> {
+    let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("跳过：没有设置TEST_DATABASE_URL");
+        return None;
+    };
+    ENV.call_once(|| {
+        //Settings::from_env只读环境变量；测试进程里只在这里写一次，之后都是读
+        unsafe {
+            std::env::set_var("DATABASE_URL", &database_url);
+            if std::env::var("JWT_SECRET").is_err() {
+                std::env::set_var("JWT_SECRET", "integration-test-secret-0123456789abcdef");
+            }
+        }
+    });
+
+    let settings = Settings::from_env().expect("读取配置失败");
+    let db = create_mysql_pool(&settings.database_url)
+        .await
+        .expect("连接测试库失败");
+    let mut state = app::build_state(&settings, db)
+        .await
+        .expect("组装AppState失败");
+    customize(&mut state);
+    let router = app::router(state.clone(), &Limiters::new(&settings));
+
+    Some(TestApp {
+        state,
+        service: Service::new(router),
+    })
+}
+
+pub async fn setup() -> Option<TestApp> {
+    setup_with(|_| {}).await
+}
+
+pub fn random_suffix() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+impl TestApp {
+    //直接往验证码存储里放一条已知答案的验证码，返回(captcha_id, 答案)
+    pub async fn captcha(&self) -> (String, String) {
+        let id = random_suffix();
+        let answer = "abcd".to_string();
+        self.state
+            .captcha_store
+            .insert(
+                &id,
+                CaptchaEntry {
+                    answer: answer.clone(),
+                    expires_at: Utc::now() + Duration::seconds(60),
+                    ip: String::new(),
+                },
+            )
+            .await
+            .expect("写入验证码失败");
+        (id, answer)
+    }
+
+    //注册一个新用户
+    pub async fn register(&self) -> TestUser {
+        let suffix = random_suffix();
+        let username = format!("it{suffix}");
+        let email = format!("{username}@example.com");
+        let (captcha_id, captcha) = self.captcha().await;
+
+        let mut res = TestClient::post(format!("{BASE_URL}/api/auth/register"))
+            .json(&json!({
+                "username": username,
+                "email": email,
+                "password": PASSWORD,
+                "captcha_id": captcha_id,
+                "captcha": captcha,
+            }))
+            .send(&self.service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK), "注册失败");
+        let body: Value = res.take_json().await.unwrap();
+
+        let me = self
+            .get_json("/api/auth/me", body["token"].as_str().unwrap())
+            .await;
+        TestUser {
+            id: me["id"].as_i64().expect("me里没有id"),
+            username,
+            email,
+            token: body["token"].as_str().unwrap().to_string(),
+            refresh_token: body["refresh_token"].as_str().unwrap().to_string(),
+        }
+    }
+
+    //带token的POST，返回(状态码, body)
+    pub async fn post(&self, path: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+        let mut client = TestClient::post(format!("{BASE_URL}{path}")).json(&body);
+        if let Some(token) = token {
+            client = client.bearer_auth(token);
+        }
+        let mut res = client.send(&self.service).await;
+        let status = res.status_code.unwrap_or(StatusCode::OK);
+        let body = res.take_json::<Value>().await.unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    //带token的GET，返回(状态码, body)
+    pub async fn get(&self, path: &str, token: &str) -> (StatusCode, Value) {
+        let mut res = TestClient::get(format!("{BASE_URL}{path}"))
+            .bearer_auth(token)
+            .send(&self.service)
+            .await;
+        let status = res.status_code.unwrap_or(StatusCode::OK);
+        let body = res.take_json::<Value>().await.unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    //GET并且要求返回200
+    pub async fn get_json(&self, path: &str, token: &str) -> Value {
+        let (status, body) = self.get(path, token).await;
+        assert_eq!(status, StatusCode::OK, "GET {path}失败:{body}");
+        body
+    }
+}
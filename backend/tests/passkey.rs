@@ -0,0 +1,125 @@
+//通行密钥：注册一个软件认证器，再用它登录，拿到的token要能通过require_auth
+mod common;
+
+use backend::services::user_service;
+use salvo::prelude::*;
+use serde_json::json;
+use webauthn_authenticator_rs::WebauthnAuthenticator;
+use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse, Url};
+
+#[tokio::test]
+async fn register_then_login_with_passkey() {
+    let Some(app) = common::setup().await else {
+        return;
+    };
+    let user = app.register().await;
+    //没验证邮箱不能注册通行密钥
+    let (status, body) = app
+        .post(
+            "/api/auth/passkey/register/start",
+            Some(&user.token),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "email_not_verified");
+    user_service::mark_email_verified(&app.state.db, user.id, &user.email)
+        .await
+        .unwrap();
+
+    //认证器按浏览器的规则校验origin，和WEBAUTHN_RP_ORIGIN(默认APP_BASE_URL)一致
+    let origin = Url::parse(&app.state.app_base_url).unwrap();
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+    //注册
+    let (status, start) = app
+        .post(
+            "/api/auth/passkey/register/start",
+            Some(&user.token),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{start}");
+    let options: CreationChallengeResponse =
+        serde_json::from_value(start["options"].clone()).unwrap();
+    let credential = authenticator
+        .do_registration(origin.clone(), options)
+        .expect("软件认证器注册失败");
+    let (status, finished) = app
+        .post(
+            "/api/auth/passkey/register/finish",
+            Some(&user.token),
+            json!({
+                "challenge_id": start["challenge_id"],
+                "name": "测试认证器",
+                "credential": credential,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{finished}");
+    assert_eq!(finished["name"], "测试认证器");
+
+    //同一个challenge不能再用一次
+    let (status, _) = app
+        .post(
+            "/api/auth/passkey/register/finish",
+            Some(&user.token),
+            json!({
+                "challenge_id": start["challenge_id"],
+                "credential": credential,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    //登录
+    let (status, start) = app
+        .post(
+            "/api/auth/passkey/login/start",
+            None,
+            json!({ "account": user.username }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{start}");
+    let options: RequestChallengeResponse =
+        serde_json::from_value(start["options"].clone()).unwrap();
+    let assertion = authenticator
+        .do_authentication(origin, options)
+        .expect("软件认证器登录失败");
+    let (status, tokens) = app
+        .post(
+            "/api/auth/passkey/login/finish",
+            None,
+            json!({
+                "challenge_id": start["challenge_id"],
+                "credential": assertion,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{tokens}");
+
+    //通行密钥登录签发的token和密码登录的一样，能访问需要登录的接口
+    let me = app
+        .get_json("/api/auth/me", tokens["token"].as_str().unwrap())
+        .await;
+    assert_eq!(me["id"], user.id);
+    assert_eq!(me["username"], user.username);
+}
+
+#[tokio::test]
+async fn passkey_login_requires_registered_passkey() {
+    let Some(app) = common::setup().await else {
+        return;
+    };
+    let user = app.register().await;
+
+    let (status, _) = app
+        .post(
+            "/api/auth/passkey/login/start",
+            None,
+            json!({ "account": user.username }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
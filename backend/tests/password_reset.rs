@@ -0,0 +1,133 @@
+//找回密码：邮件里的链接带着令牌，令牌只能用一次、过期无效，重置后所有会话下线
+mod common;
+
+use std::sync::Arc;
+
+use common::{PASSWORD, RecordingMailer};
+use salvo::prelude::*;
+use serde_json::json;
+
+const NEW_PASSWORD: &str = "Another-Stapler-77-purple";
+
+#[tokio::test]
+async fn forgot_then_reset_password() {
+    let mailer = RecordingMailer::default();
+    let recorder = mailer.clone();
+    let Some(app) = common::setup_with(move |state| state.mailer = Arc::new(recorder)).await else {
+        return;
+    };
+    let user = app.register().await;
+
+    let (captcha_id, captcha) = app.captcha().await;
+    let (status, body) = app
+        .post(
+            "/api/auth/password/forgot",
+            None,
+            json!({ "account": user.username, "captcha_id": captcha_id, "captcha": captcha }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "{body}");
+
+    let mail = mailer.wait_for(&user.email, "重置密码").await;
+    let token = common::token_in(&mail);
+    assert!(
+        mail.body
+            .contains(&format!("{}/forget?token={token}", app.state.app_base_url)),
+        "{}",
+        mail.body
+    );
+
+    let (status, body) = app
+        .post(
+            "/api/auth/password/reset",
+            None,
+            json!({ "token": token, "password": NEW_PASSWORD }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "{body}");
+
+    //令牌只能用一次
+    let (status, body) = app
+        .post(
+            "/api/auth/password/reset",
+            None,
+            json!({ "token": token, "password": "Yet-Another-Pass-99-green" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "reset_token_invalid");
+
+    //重置前登录的地方全部下线：access token和refresh token都不能再用
+    let (status, _) = app.get("/api/auth/me", &user.token).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app
+        .post(
+            "/api/auth/refresh",
+            None,
+            json!({ "refresh_token": user.refresh_token }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    //旧密码不能登录，新密码可以
+    for (password, expected) in [
+        (PASSWORD, StatusCode::UNAUTHORIZED),
+        (NEW_PASSWORD, StatusCode::OK),
+    ] {
+        let (captcha_id, captcha) = app.captcha().await;
+        let (status, body) = app
+            .post(
+                "/api/auth/login",
+                None,
+                json!({
+                    "account": user.username,
+                    "password": password,
+                    "captcha_id": captcha_id,
+                    "captcha": captcha,
+                }),
+            )
+            .await;
+        assert_eq!(status, expected, "{body}");
+    }
+}
+
+#[tokio::test]
+async fn expired_reset_token_is_rejected() {
+    let mailer = RecordingMailer::default();
+    let recorder = mailer.clone();
+    //令牌签发时就已经过期
+    let Some(app) = common::setup_with(move |state| {
+        state.mailer = Arc::new(recorder);
+        state.password_reset_expire_seconds = -1;
+    })
+    .await
+    else {
+        return;
+    };
+    let user = app.register().await;
+
+    let (captcha_id, captcha) = app.captcha().await;
+    let (status, _) = app
+        .post(
+            "/api/auth/password/forgot",
+            None,
+            json!({ "account": user.email, "captcha_id": captcha_id, "captcha": captcha }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let mail = mailer.wait_for(&user.email, "重置密码").await;
+    let (status, body) = app
+        .post(
+            "/api/auth/password/reset",
+            None,
+            json!({ "token": common::token_in(&mail), "password": NEW_PASSWORD }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "reset_token_invalid");
+
+    //令牌没被用掉，旧的会话也还在
+    let (status, _) = app.get("/api/auth/me", &user.token).await;
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,117 @@
+//接口限流：真实的限流hoop挂在路由上，用TestClient检查RateLimit-*header、429和按IP/用户两个桶
+mod common;
+
+use backend::config::settings::RateQuota;
+use backend::middleware::rate_limit::RateLimiter;
+use backend::state::AppState;
+use backend::utils::auth;
+use salvo::affix_state;
+use salvo::prelude::*;
+use salvo::test::{ResponseExt, TestClient};
+use serde_json::Value;
+
+#[handler]
+async fn ok() -> &'static str {
+    "ok"
+}
+
+//每60秒2次；信任一层代理，测试里用X-Forwarded-For换IP
+async fn setup() -> Option<(AppState, Service)> {
+    let app = common::setup_with(|state| state.trusted_proxy_hops = 1).await?;
+    let limiter = RateLimiter::new(
+        "test",
+        RateQuota {
+            requests: 2,
+            period_seconds: 60,
+        },
+    );
+    let router = Router::new()
+        .hoop(affix_state::inject(app.state.clone()))
+        .hoop(limiter)
+        .goal(ok);
+    Some((app.state, Service::new(router)))
+}
+
+async fn call(service: &Service, ip: &str, token: Option<&str>) -> Response {
+    let mut client =
+        TestClient::get(format!("{}/", common::BASE_URL)).add_header("x-forwarded-for", ip, true);
+    if let Some(token) = token {
+        client = client.bearer_auth(token);
+    }
+    client.send(service).await
+}
+
+fn header(res: &Response, name: &str) -> String {
+    res.headers()
+        .get(name)
+        .unwrap_or_else(|| panic!("没有{name}"))
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+//只验签不查库，随便签一个就能代表这个用户
+fn token_for(state: &AppState, user_id: i64) -> String {
+    auth::issue_jwt(&state.jwt_keys, 900, user_id, "rate-limit-test", &[]).unwrap()
+}
+
+#[tokio::test]
+async fn reports_quota_and_rejects_when_empty() {
+    let Some((_, service)) = setup().await else {
+        return;
+    };
+
+    let res = call(&service, "203.0.113.1", None).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    assert_eq!(header(&res, "ratelimit-limit"), "2");
+    assert_eq!(header(&res, "ratelimit-remaining"), "1");
+    //每30秒补一个，补满还差一个
+    assert_eq!(header(&res, "ratelimit-reset"), "30");
+
+    let res = call(&service, "203.0.113.1", None).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    assert_eq!(header(&res, "ratelimit-remaining"), "0");
+    assert_eq!(header(&res, "ratelimit-reset"), "60");
+
+    let mut res = call(&service, "203.0.113.1", None).await;
+    assert_eq!(res.status_code, Some(StatusCode::TOO_MANY_REQUESTS));
+    assert_eq!(header(&res, "ratelimit-remaining"), "0");
+    assert_eq!(header(&res, "retry-after"), "30");
+    let body = res.take_json::<Value>().await.unwrap();
+    assert_eq!(body["code"], "too_many_requests");
+
+    //别的IP有自己的桶
+    let res = call(&service, "203.0.113.2", None).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+}
+
+#[tokio::test]
+async fn signed_in_requests_need_both_ip_and_user_tokens() {
+    let Some((state, service)) = setup().await else {
+        return;
+    };
+    let (user1, user2, user3) = (
+        token_for(&state, 1),
+        token_for(&state, 2),
+        token_for(&state, 3),
+    );
+
+    for _ in 0..2 {
+        let res = call(&service, "198.51.100.1", Some(&user1)).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+    }
+
+    //换个IP，同一个用户的桶已经空了
+    let res = call(&service, "198.51.100.2", Some(&user1)).await;
+    assert_eq!(res.status_code, Some(StatusCode::TOO_MANY_REQUESTS));
+
+    //换个用户，同一个IP的桶已经空了
+    let res = call(&service, "198.51.100.1", Some(&user2)).await;
+    assert_eq!(res.status_code, Some(StatusCode::TOO_MANY_REQUESTS));
+
+    //IP被拒绝时不扣用户的令牌：user3还是满的
+    let res = call(&service, "198.51.100.1", Some(&user3)).await;
+    assert_eq!(res.status_code, Some(StatusCode::TOO_MANY_REQUESTS));
+    let res = call(&service, "198.51.100.3", Some(&user3)).await;
+    assert_eq!(header(&res, "ratelimit-remaining"), "1");
+}
@@ -0,0 +1,16 @@
+-- refresh token：只存SHA-256摘要，明文只在签发时返回给客户端一次
+-- family_id：同一次登录轮换出来的所有refresh token属于同一个family
+-- rotated_at不为空说明已经被换过新token，再次出现就是被盗用，整个family一起作废
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  user_id BIGINT NOT NULL,
+  family_id VARCHAR(64) NOT NULL,
+  token_hash CHAR(64) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  rotated_at DATETIME NULL,
+  revoked_at DATETIME NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE KEY uk_refresh_tokens_hash (token_hash),
+  KEY idx_refresh_tokens_family (family_id),
+  KEY idx_refresh_tokens_user (user_id)
+);
@@ -0,0 +1,9 @@
+-- access token吊销列表：登出后token里的jti记在这里，直到token本身过期
+-- expires_at和token的exp一致，过期之后这条记录就没用了，后台任务定时清理
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+  jti VARCHAR(64) PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  expires_at DATETIME NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  KEY idx_revoked_tokens_expires (expires_at)
+);
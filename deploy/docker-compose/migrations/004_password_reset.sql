@@ -0,0 +1,21 @@
+-- 找回密码的一次性令牌：和refresh token一样只存SHA-256摘要
+-- used_at不为空表示已经用过(或者被更新的令牌顶掉了)
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  user_id BIGINT NOT NULL,
+  token_hash CHAR(64) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  used_at DATETIME NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE KEY uk_password_reset_tokens_hash (token_hash),
+  KEY idx_password_reset_tokens_user (user_id)
+);
+
+-- 按用户吊销access token：not_before之前签发的token全部作废(重置密码后让所有已登录的地方下线)
+-- expires_at = not_before + access token有效期，过了这个时间旧token本身都过期了，记录可以清理
+CREATE TABLE IF NOT EXISTS revoked_user_tokens (
+  user_id BIGINT PRIMARY KEY,
+  not_before DATETIME NOT NULL,
+  expires_at DATETIME NOT NULL,
+  KEY idx_revoked_user_tokens_expires (expires_at)
+);
@@ -0,0 +1,16 @@
+-- 邮箱验证：email_verified_at为空表示还没验证
+ALTER TABLE users ADD COLUMN email_verified_at DATETIME NULL;
+
+-- 邮箱验证令牌：只存SHA-256摘要
+-- email记录发信时的地址，验证时地址已经变了就不算数
+CREATE TABLE IF NOT EXISTS email_verification_tokens (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  user_id BIGINT NOT NULL,
+  email VARCHAR(255) NOT NULL,
+  token_hash CHAR(64) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  used_at DATETIME NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE KEY uk_email_verification_tokens_hash (token_hash),
+  KEY idx_email_verification_tokens_user (user_id)
+);
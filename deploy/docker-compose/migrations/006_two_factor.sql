@@ -0,0 +1,18 @@
+-- 两步验证(TOTP, RFC 6238)
+-- totp_secret：base32编码的密钥；开启流程中先写入，确认第一个验证码后才设置totp_enabled_at
+-- totp_last_step：最近一次验证通过的时间步，同一个验证码不能用两次
+ALTER TABLE users
+  ADD COLUMN totp_secret VARCHAR(64) NULL,
+  ADD COLUMN totp_enabled_at DATETIME NULL,
+  ADD COLUMN totp_last_step BIGINT NULL;
+
+-- 恢复码：手机丢了时代替TOTP验证码使用，每个只能用一次
+-- 和密码一样用argon2哈希保存
+CREATE TABLE IF NOT EXISTS recovery_codes (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  user_id BIGINT NOT NULL,
+  code_hash VARCHAR(255) NOT NULL,
+  used_at DATETIME NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  KEY idx_recovery_codes_user (user_id)
+);
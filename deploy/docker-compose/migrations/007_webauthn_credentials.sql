@@ -0,0 +1,14 @@
+-- 通行密钥(WebAuthn/passkey)凭据，一个用户可以有多个(手机、电脑、硬件密钥...)
+-- credential_id：认证器生成的凭据id(base64url)，登录时用它找到对应的凭据
+-- passkey：webauthn-rs的Passkey结构序列化成JSON，里面有公钥和签名计数器
+CREATE TABLE IF NOT EXISTS webauthn_credentials (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  user_id BIGINT NOT NULL,
+  credential_id VARCHAR(255) NOT NULL,
+  name VARCHAR(64) NOT NULL,
+  passkey TEXT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  last_used_at DATETIME NULL,
+  UNIQUE KEY uk_webauthn_credentials_credential_id (credential_id),
+  KEY idx_webauthn_credentials_user (user_id)
+);
@@ -0,0 +1,12 @@
+-- 登录会话：每次登录/注册成功记一行，用来展示"在哪些地方登录过"和远程下线
+-- id和这次登录的refresh token family_id相同；access token里的sid也是它
+-- 删除会话 = 这个会话的access token立即失效 + refresh token全部作废
+CREATE TABLE IF NOT EXISTS sessions (
+  id VARCHAR(64) PRIMARY KEY,
+  user_id BIGINT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  last_seen_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  user_agent VARCHAR(255) NOT NULL DEFAULT '',
+  ip VARCHAR(45) NOT NULL DEFAULT '',
+  KEY idx_sessions_user (user_id)
+);
@@ -0,0 +1,10 @@
+-- 登录失败计数(LOGIN_THROTTLE_BACKEND=mysql时使用)
+-- attempt_key形如 user:123 / account:alice / ip:1.2.3.4
+-- 登录成功后整行删除；长时间没有再失败、也不在锁定中的行由后台任务清理
+CREATE TABLE IF NOT EXISTS login_attempts (
+  attempt_key VARCHAR(191) PRIMARY KEY,
+  failures INT NOT NULL DEFAULT 0,
+  last_failure_at DATETIME NOT NULL,
+  locked_until DATETIME NULL,
+  KEY idx_login_attempts_last_failure (last_failure_at)
+);
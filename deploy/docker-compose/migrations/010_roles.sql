@@ -0,0 +1,18 @@
+-- 角色：用户和角色多对多
+-- 登录时把用户的角色写进access token，require_role按token里的角色判断权限
+CREATE TABLE IF NOT EXISTS roles (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  name VARCHAR(32) NOT NULL UNIQUE,
+  description VARCHAR(255) NOT NULL DEFAULT '',
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+  user_id BIGINT NOT NULL,
+  role_id BIGINT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, role_id),
+  KEY idx_user_roles_role (role_id)
+);
+
+INSERT IGNORE INTO roles (name, description) VALUES ('admin', '管理员');
@@ -0,0 +1,6 @@
+-- 账号状态：管理员可以禁用账号，也可以软删除
+-- disabled_at不为空：禁止登录，已登录的地方全部下线
+-- deleted_at不为空：软删除，除了管理接口以外都当作用户不存在(用户名和邮箱仍然占用)
+ALTER TABLE users
+  ADD COLUMN disabled_at DATETIME NULL,
+  ADD COLUMN deleted_at DATETIME NULL;
@@ -0,0 +1,4 @@
+-- 用户选择的界面语言(比如zh-CN、en)，错误提示按它返回
+-- 为空表示没有设置，跟随请求头Accept-Language
+ALTER TABLE users
+  ADD COLUMN locale VARCHAR(16) NULL;
@@ -0,0 +1,27 @@
+-- 修改邮箱：先给新邮箱发确认链接，点了之后才真正替换users.email
+-- session_id记录发起修改的会话：确认后除了这个会话，其他地方全部下线
+CREATE TABLE IF NOT EXISTS email_change_requests (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  user_id BIGINT NOT NULL,
+  new_email VARCHAR(255) NOT NULL,
+  session_id VARCHAR(64) NOT NULL,
+  token_hash CHAR(64) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  used_at DATETIME NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE KEY uk_email_change_requests_hash (token_hash),
+  KEY idx_email_change_requests_user (user_id)
+);
+
+-- 审计日志：账号相关的敏感操作(改密码、改邮箱等)
+-- 用户被硬删除后也保留，方便事后追查
+CREATE TABLE IF NOT EXISTS audit_logs (
+  id BIGINT PRIMARY KEY AUTO_INCREMENT,
+  user_id BIGINT NOT NULL,
+  action VARCHAR(64) NOT NULL,
+  detail VARCHAR(512) NOT NULL DEFAULT '',
+  ip VARCHAR(45) NOT NULL DEFAULT '',
+  user_agent VARCHAR(255) NOT NULL DEFAULT '',
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  KEY idx_audit_logs_user (user_id, created_at)
+);
@@ -0,0 +1,8 @@
+-- 验证码答案(CAPTCHA_BACKEND=mysql时使用)
+-- 校验通过时整行删除；过期没用的行由后台任务清理
+CREATE TABLE IF NOT EXISTS captchas (
+  id VARCHAR(64) PRIMARY KEY,
+  answer VARCHAR(64) NOT NULL,
+  expires_at DATETIME NOT NULL,
+  KEY idx_captchas_expires_at (expires_at)
+);
@@ -0,0 +1,5 @@
+-- 验证码限制：记录生成验证码的IP(每个IP同时持有的数量有上限)和猜错的次数(猜错太多次作废)
+ALTER TABLE captchas
+  ADD COLUMN ip VARCHAR(45) NOT NULL DEFAULT '' AFTER answer,
+  ADD COLUMN attempts INT NOT NULL DEFAULT 0 AFTER ip,
+  ADD KEY idx_captchas_ip (ip, expires_at);
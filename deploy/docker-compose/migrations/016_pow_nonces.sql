@@ -0,0 +1,7 @@
+-- 已经用过的工作量证明随机串(CAPTCHA_BACKEND=mysql时使用)
+-- 同一个challenge第二次提交时主键冲突，拒绝；challenge过期之后的行由后台任务清理
+CREATE TABLE IF NOT EXISTS pow_nonces (
+  nonce VARCHAR(32) PRIMARY KEY,
+  expires_at DATETIME NOT NULL,
+  KEY idx_pow_nonces_expires_at (expires_at)
+);
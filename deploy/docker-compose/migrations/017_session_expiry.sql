@@ -0,0 +1,15 @@
+-- 会话过期时间：和这次登录的refresh token family一起过期，每次刷新往后延
+-- 过期的会话不再出现在会话列表里，后台任务定时删除
+ALTER TABLE sessions
+  ADD COLUMN expires_at DATETIME NULL AFTER last_seen_at,
+  ADD KEY idx_sessions_expires_at (expires_at);
+
+-- 已有的会话按family里还没作废的refresh token算，一个都没有的当作已经过期
+UPDATE sessions s
+SET expires_at = COALESCE(
+  (SELECT MAX(r.expires_at) FROM refresh_tokens r WHERE r.family_id = s.id AND r.revoked_at IS NULL),
+  CURRENT_TIMESTAMP
+);
+
+ALTER TABLE sessions
+  MODIFY COLUMN expires_at DATETIME NOT NULL;
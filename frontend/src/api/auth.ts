@@ -4,13 +4,62 @@ import request from './request';
 
 //验证码部分
 //captcha_id：让后端生成验证码的唯一id
-//image：验证码图片
-export type CaptchaResp = { captcha_id: string; image: string };
-export async function getCaptcha() {
-    const { data } = await request.get<CaptchaResp>('/api/captcha');
+//image：验证码图片(PNG或SVG的data URL)，width/height是图片的实际尺寸
+//audio：kind=audio时才有，和图片是同一个答案的语音(WAV的data URL)
+//scene：login/register，后端按场景使用不同的长度、尺寸、难度；不传用通用配置
+//kind：image(默认)/svg/math(算术题，填计算结果)/audio
+export type CaptchaScene = 'login' | 'register';
+export type CaptchaKind = 'image' | 'svg' | 'math' | 'audio';
+export type CaptchaResp = {
+    captcha_id: string;
+    kind: CaptchaKind;
+    image: string;
+    audio?: string;
+    width: number;
+    height: number;
+    expires_in: number;
+};
+export async function getCaptcha(scene?: CaptchaScene, kind?: CaptchaKind) {
+    const { data } = await request.get<CaptchaResp>('/api/captcha', { params: { scene, kind } });
     return data;
 }
 
+//工作量证明：验证码的替代方式(API客户端、看不清验证码时用)
+//找到solution，让sha256(challenge + ":" + solution)开头至少有difficulty个0比特
+export type PowChallengeResp = {
+    challenge: string;
+    difficulty: number;
+    algorithm: 'sha256';
+    expires_in: number;
+};
+export type PowSolution = { challenge: string; solution: string };
+export async function getPowChallenge() {
+    const { data } = await request.get<PowChallengeResp>('/api/challenge/pow');
+    return data;
+}
+//在浏览器里算solution：从0开始逐个尝试，难度每加1平均要多算一倍
+export async function solvePow({ challenge, difficulty }: PowChallengeResp): Promise<PowSolution> {
+    const encoder = new TextEncoder();
+    for (let n = 0; ; n++) {
+        const solution = n.toString();
+        const digest = new Uint8Array(
+            await crypto.subtle.digest('SHA-256', encoder.encode(`${challenge}:${solution}`)),
+        );
+        let bits = 0;
+        for (const byte of digest) {
+            if (byte === 0) {
+                bits += 8;
+                continue;
+            }
+            bits += Math.clz32(byte) - 24;
+            break;
+        }
+        if (bits >= difficulty) {
+            return { challenge, solution };
+        }
+    }
+}
+
 //登录部分
 //和登录页面提交字段对齐
 export type LoginReq = {
@@ -18,8 +67,13 @@ export type LoginReq = {
     password: string,
     captcha_id: string,
     captcha: string,
+    //可以用工作量证明代替captcha_id+captcha
+    pow?: PowSolution,
 };
-export type LoginResp = { token: string };
+//token：access token(JWT)，expires_in秒后过期(默认15分钟)
+//refresh_token：用来换新的token，见refreshApi
+export type TokenResp = { token: string, refresh_token: string, expires_in: number };
+export type LoginResp = TokenResp;
 export async function loginApi(payload: LoginReq) {
     const { data } = await request.post<LoginResp>("/api/auth/login", payload);
     return data;
@@ -33,13 +87,22 @@ export type RegisterReq = {
     password: string,
     captcha_id: string,
     captcha: string,
+    //可以用工作量证明代替captcha_id+captcha
+    pow?: PowSolution,
 };
-export type RegisterResp = { token: string }
+export type RegisterResp = TokenResp
 export async function registerApi(payload: RegisterReq) {
     const { data } = await request.post<RegisterResp>("/api/auth/register", payload);
     return data;
 }
 
+//用refresh token换一对新的token(旧的refresh token随即作废)
+//access token过期后由request.ts的响应拦截器自动调用
+export async function refreshApi(refresh_token: string) {
+    const { data } = await request.post<TokenResp>("/api/auth/refresh", { refresh_token });
+    return data;
+}
+
 //获取当前用户信息，用来检查token是否有效
 export async function meApi() {
     const { data } = await request.get("/api/auth/me");
@@ -1,6 +1,6 @@
 //Axios实例和拦截器
 
-import axios from 'axios';
+import axios, { type InternalAxiosRequestConfig } from 'axios';
 import { message } from "ant-design-vue";
 import { useAuthStore } from "../stores/auth";
 import { pinia } from "@/stores";
@@ -24,22 +24,45 @@ request.interceptors.request.use((config) => {
     return config;
 });
 
+//401时不去刷新token的接口：登录本身失败、刷新本身失败
+const NO_REFRESH_URLS = ["/api/auth/login", "/api/auth/login/2fa", "/api/auth/refresh"];
+
+//重发过一次的请求打个标记，重发后还是401就不再刷新，避免死循环
+type RetryConfig = InternalAxiosRequestConfig & { _retried?: boolean };
+
 //响应拦截器：统一处理错误，重点处理401
-//报401错误说明token过期/无效，此时就退出登录，并且弹出提示
+//报401错误说明token过期/无效：先用refresh token换新token再重发一次原请求，
+//换不到(refresh token也过期/被吊销)才退出登录，并且弹出提示
 request.interceptors.response.use(
     (resp) => resp,
-    (err) => {
+    async (err) => {
         if (!err?.response) {
             message.error("网络错误，无法连接服务器");
             return Promise.reject(err);
         }
 
         const status = err.response.status;
-        //尝试从后端拿message
-        const backendMsg: string | undefined = err.response.data?.message;
+        //尝试从后端拿message；参数校验错误(422)优先显示第一个字段的错误
+        const fields: Record<string, { message: string }[]> | undefined = err.response.data?.fields;
+        const fieldMsg = fields ? Object.values(fields)[0]?.[0]?.message : undefined;
+        const backendMsg: string | undefined = fieldMsg || err.response.data?.message;
 
         if (status == 401) {
             const auth = useAuthStore(pinia);
+            const config = err.config as RetryConfig | undefined;
+            if (config && !config._retried && auth.refreshToken && !NO_REFRESH_URLS.includes(config.url ?? "")) {
+                config._retried = true;
+                try {
+                    //并发的请求共用同一次刷新
+                    await auth.refresh();
+                    config.headers.Authorization = `Bearer ${auth.token}`;
+                    return request(config);
+                } catch {
+                    //刷新请求自己401时已经登出并提示过了，不再重复提示
+                    if (!auth.token) return Promise.reject(err);
+                    //其他原因刷新失败：往下走，按登录失效处理
+                }
+            }
             auth.logout();
             message.warning(backendMsg || "登录已失效，请重新登录");
         } else if (status === 400) {
@@ -1,13 +1,16 @@
 //Pinia认证仓库
 
 import {defineStore} from 'pinia';
-import {meApi} from "../api/auth";
+import {meApi, refreshApi} from "../api/auth";
 
 //“记住我”部分一般对应：
 //- 记住：localStorage（关闭浏览器也还在）
 //- 不记住：sessionStorage（关闭浏览器就没了）
 const LS_KEY = "auth_token_ls";
 const SS_KEY = "auth_token_ss";
+//refresh token跟着access token存在同一个storage里
+const LS_REFRESH_KEY = "auth_refresh_token_ls";
+const SS_REFRESH_KEY = "auth_refresh_token_ss";
 
 //从两个地方尝试读取token
 //优先从localStorage（因为“记住我”存在这里)
@@ -15,12 +18,26 @@ const SS_KEY = "auth_token_ss";
 function loadToken(): string {
     return localStorage.getItem(LS_KEY) || sessionStorage.getItem(SS_KEY) || "";
 }
+function loadRefreshToken(): string {
+    return localStorage.getItem(LS_REFRESH_KEY) || sessionStorage.getItem(SS_REFRESH_KEY) || "";
+}
+
+function clearStorage() {
+    localStorage.removeItem(LS_KEY);
+    sessionStorage.removeItem(SS_KEY);
+    localStorage.removeItem(LS_REFRESH_KEY);
+    sessionStorage.removeItem(SS_REFRESH_KEY);
+}
 
 export const useAuthStore = defineStore("auth", {
     state: () => ({
         //token:JWT字符串
         //刷新页面时state状态会重建，所以初始化时从storage里读一次
         token: loadToken(),
+        //refreshToken：access token过期(401)后用它换新的，不用重新登录
+        refreshToken: loadRefreshToken(),
+        //当初登录时选没选“记住我”，刷新后的token存回同一个地方
+        remember: !!localStorage.getItem(LS_KEY),
 
         //user：当前登录用户信息（来自 /api/auth/me）
         //一开始不知道是谁，所以是 null
@@ -33,6 +50,9 @@ export const useAuthStore = defineStore("auth", {
         //这里不知道在干嘛
         //防抖/去重：多次并发触发 verifyTokenOnce 时，复用同一个请求
         verifyingPromise: null as null | Promise<boolean>,
+
+        //并发的多个请求同时401时只刷新一次，其他请求等同一个promise
+        refreshingPromise: null as null | Promise<void>,
     }),
     getters: {
         //只要本地有token就认为可能登录了
@@ -42,40 +62,63 @@ export const useAuthStore = defineStore("auth", {
         },
     },
     actions: {
-        //登录成功后调用setToken，接收后端签发的JWT和“是否记住我"
+        //登录成功后调用setToken，接收后端签发的JWT、refresh token和“是否记住我"
         //之后
-        // 1.写state.token
+        // 1.写state.token/refreshToken
         // 2.根据remember选择，存入localStorage或者sessionStorage
         // 3.清空user，并且让isTokenVerified=false，因为这是一个新token，还没有被确认过
-        setToken(token: string, remember: boolean) {
-            this.token = token;
-
+        setToken(token: string, refreshToken: string, remember: boolean) {
             //新token还没有后端验证，先重置
             this.isTokenVerified = false;
             this.user = null;
+            this.saveTokens(token, refreshToken, remember);
+        },
+
+        //写state和storage
+        saveTokens(token: string, refreshToken: string, remember: boolean) {
+            this.token = token;
+            this.refreshToken = refreshToken;
+            this.remember = remember;
 
             //清理两个storage，防止同时存在旧的token
-            localStorage.removeItem(LS_KEY);
-            sessionStorage.removeItem(SS_KEY);
+            clearStorage();
 
             //根据remember选择决定存在哪里
-            if (remember) {
-                localStorage.setItem(LS_KEY, token);
-            } else {
-                sessionStorage.setItem(SS_KEY, token);
-            }
+            const storage = remember ? localStorage : sessionStorage;
+            storage.setItem(remember ? LS_KEY : SS_KEY, token);
+            storage.setItem(remember ? LS_REFRESH_KEY : SS_REFRESH_KEY, refreshToken);
+        },
+
+        //用refresh token换新的token(access token只有15分钟)
+        //失败(refresh token也过期/被吊销)时抛出，由调用方决定是否登出
+        async refresh(): Promise<void> {
+            if (!this.refreshToken) throw new Error("没有refresh token");
+
+            //正在刷新：复用同一个promise，避免同一个refresh token被用两次(第二次会被后端拒绝)
+            if (this.refreshingPromise) return this.refreshingPromise;
+
+            this.refreshingPromise = (async () => {
+                try {
+                    const resp = await refreshApi(this.refreshToken);
+                    //同一个用户换了个token，已经确认过的user不用清
+                    this.saveTokens(resp.token, resp.refresh_token, this.remember);
+                } finally {
+                    this.refreshingPromise = null;
+                }
+            })();
+            return this.refreshingPromise;
         },
 
         //logout退出登录
         //需要把state和storage清空
         logout() {
             this.token = "";
+            this.refreshToken = "";
             this.user = null;
             this.isTokenVerified = false;
             this.verifyingPromise = null;
 
-            localStorage.removeItem(LS_KEY);
-            sessionStorage.removeItem(SS_KEY);
+            clearStorage();
         },
 
         //核心函数
@@ -97,7 +140,7 @@ export const useAuthStore = defineStore("auth", {
                     this.isTokenVerified = true;
                     return true;
                 } catch (e) {
-                    //一旦/me失败（401或者过期),就把token认为是无效的，直接登出
+                    //一旦/me失败（401并且refresh也失败了),就把token认为是无效的，直接登出
                     this.logout();
                     return false;
                 } finally {
@@ -111,6 +154,7 @@ export const useAuthStore = defineStore("auth", {
 })
 
 //最后达到的效果
-//登录成功:auth.setToken(token,remember)
+//登录成功:auth.setToken(token,refreshToken,remember)
+//token过期:request.ts拦截到401后await auth.refresh()，再重发原请求
 //需要认证:await auth.verifyTokenOnce()
 //退出登录:auth.logout()
\ No newline at end of file